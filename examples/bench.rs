@@ -20,7 +20,7 @@ fn main() {
                 }),
                 ..default()
             }),
-            TextInputPlugin,
+            TextInputPlugin::default(),
             FrameTimeDiagnosticsPlugin::default(),
         ))
         .add_systems(Startup, setup)
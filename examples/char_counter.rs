@@ -0,0 +1,56 @@
+//! character counter example, using the `TextInputRef` query data
+
+use bevy::{color::palettes::css::NAVY, prelude::*};
+use bevy_ui_text_input::{TextInputNode, TextInputPlugin, query::TextInputRef};
+
+fn main() {
+    App::new()
+        .add_plugins((DefaultPlugins, TextInputPlugin::default()))
+        .add_systems(Startup, setup)
+        .add_systems(Update, update_counter)
+        .run();
+}
+
+#[derive(Component)]
+struct Counter;
+
+fn setup(mut commands: Commands) {
+    // UI camera
+    commands.spawn(Camera2d);
+    commands
+        .spawn(Node {
+            width: Val::Percent(100.),
+            height: Val::Percent(100.),
+            justify_content: JustifyContent::Center,
+            align_items: AlignItems::Center,
+            flex_direction: FlexDirection::Column,
+            row_gap: Val::Px(10.),
+            ..Default::default()
+        })
+        .with_children(|commands| {
+            commands.spawn((
+                TextInputNode {
+                    max_chars: Some(140),
+                    ..default()
+                },
+                Node {
+                    width: Val::Px(500.),
+                    height: Val::Px(250.),
+                    ..default()
+                },
+                BackgroundColor(NAVY.into()),
+            ));
+            commands.spawn((Text::default(), Counter));
+        });
+}
+
+fn update_counter(inputs: Query<TextInputRef>, mut counter: Single<&mut Text, With<Counter>>) {
+    for input in &inputs {
+        let count = input.text().chars().count();
+        let max = input.node.max_chars.unwrap_or(usize::MAX);
+        let text = format!("{count} / {max}");
+        if counter.0 != text {
+            counter.0 = text;
+        }
+    }
+}
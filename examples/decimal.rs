@@ -7,7 +7,7 @@ use bevy_ui_text_input::{
 
 fn main() {
     App::new()
-        .add_plugins((DefaultPlugins, TextInputPlugin))
+        .add_plugins((DefaultPlugins, TextInputPlugin::default()))
         .add_systems(Startup, setup)
         .add_systems(Update, reciever)
         .run();
@@ -0,0 +1,58 @@
+//! Types digits into the end of long filtered text inputs and logs the average cost per keystroke.
+//!
+//! `TextInputFilter::Integer` only checks each inserted digit, the `Custom` filter accepting the same
+//! text has to rebuild and check the whole text after every keystroke.
+
+use bevy_ui_text_input::TextInputFilter;
+use bevy_ui_text_input::actions::{TextInputEdit, apply_text_input_edit};
+use cosmic_text::{Attrs, Buffer, Cursor, Edit, Editor, FontSystem, Metrics, Shaping, fontdb};
+use std::time::{Duration, Instant};
+
+const KEYSTROKES: usize = 1_000;
+
+fn main() {
+    let mut fonts = fontdb::Database::new();
+    fonts.load_font_data(include_bytes!("../assets/fonts/FiraMono-Medium.ttf").to_vec());
+    let mut font_system = FontSystem::new_with_locale_and_db("en-US".into(), fonts);
+
+    for len in [1_000, 10_000, 100_000] {
+        for (name, filter) in [
+            ("Integer", TextInputFilter::Integer),
+            (
+                "Custom",
+                TextInputFilter::custom(|text| TextInputFilter::Integer.is_match(text)),
+            ),
+        ] {
+            let per_keystroke = type_digits(&mut font_system, len, &filter);
+            println!("{name:>8} filter, {len:>6} chars: {per_keystroke:?} per keystroke");
+        }
+    }
+}
+
+fn type_digits(font_system: &mut FontSystem, len: usize, filter: &TextInputFilter) -> Duration {
+    let mut buffer = Buffer::new(font_system, Metrics::new(20., 20.));
+    buffer.set_text(
+        font_system,
+        &"1".repeat(len),
+        &Attrs::new(),
+        Shaping::Advanced,
+        None,
+    );
+    let mut editor = Editor::new(buffer);
+    editor.set_cursor(Cursor::new(0, len));
+    let mut editor = editor.borrow_with(font_system);
+    let mut changes = cosmic_undo_2::Commands::new();
+
+    let start = Instant::now();
+    for _ in 0..KEYSTROKES {
+        let rejection = apply_text_input_edit(
+            TextInputEdit::Insert('1', false),
+            &mut editor,
+            &mut changes,
+            None,
+            Some(filter),
+        );
+        assert!(rejection.is_none());
+    }
+    start.elapsed() / KEYSTROKES as u32
+}
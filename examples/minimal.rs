@@ -5,7 +5,7 @@ use bevy_ui_text_input::{TextInputNode, TextInputPlugin};
 
 fn main() {
     App::new()
-        .add_plugins((DefaultPlugins, TextInputPlugin))
+        .add_plugins((DefaultPlugins, TextInputPlugin::default()))
         .add_systems(Startup, setup)
         .run();
 }
@@ -8,11 +8,12 @@ use bevy::{
 };
 use bevy_ui_text_input::{
     SubmitText, TextInputFilter, TextInputMode, TextInputNode, TextInputPlugin, TextInputPrompt,
+    is_focused,
 };
 
 fn main() {
     App::new()
-        .add_plugins((DefaultPlugins, TextInputPlugin))
+        .add_plugins((DefaultPlugins, TextInputPlugin::default()))
         .add_systems(Startup, setup)
         .add_systems(Update, update)
         .run();
@@ -103,7 +104,7 @@ fn update(
 ) {
     if input_focus.is_changed() {
         for (entity, mut outline) in outline_query.iter_mut() {
-            if input_focus.0.is_some_and(|active| active == entity) {
+            if is_focused(entity, &input_focus) {
                 outline.color = Color::WHITE;
             } else {
                 outline.color = GREY.into();
@@ -0,0 +1,93 @@
+//! Resizes the window back and forth every frame with several text inputs, logging the frame rate
+//! and how many layouts reused cached shaping.
+
+use bevy::{
+    color::palettes::css::NAVY,
+    diagnostic::{DiagnosticsStore, FrameTimeDiagnosticsPlugin},
+    prelude::*,
+    time::common_conditions::on_timer,
+    window::{PresentMode, WindowResolution},
+};
+use bevy_ui_text_input::text_input_pipeline::TextInputPipeline;
+use bevy_ui_text_input::{TextInputBuffer, TextInputNode, TextInputPlugin};
+use std::time::Duration;
+
+const TEXT: &str =
+    "The quick brown fox jumps over the lazy dog. Pack my box with five dozen liquor jugs.";
+
+fn main() {
+    App::new()
+        .add_plugins((
+            DefaultPlugins.set(WindowPlugin {
+                primary_window: Some(Window {
+                    present_mode: PresentMode::AutoNoVsync,
+                    resolution: WindowResolution::new(1200, 800).with_scale_factor_override(1.0),
+                    ..default()
+                }),
+                ..default()
+            }),
+            TextInputPlugin::default(),
+            FrameTimeDiagnosticsPlugin::default(),
+        ))
+        .add_systems(Startup, setup)
+        .add_systems(Update, resize_window)
+        .add_systems(
+            Update,
+            report_system.run_if(on_timer(Duration::from_secs(1))),
+        )
+        .run();
+}
+
+fn setup(mut commands: Commands) {
+    // UI camera
+    commands.spawn(Camera2d);
+
+    commands
+        .spawn(Node {
+            width: Val::Percent(100.),
+            height: Val::Percent(100.),
+            flex_direction: FlexDirection::Column,
+            row_gap: Val::Px(10.),
+            padding: UiRect::all(Val::Px(10.)),
+            ..Default::default()
+        })
+        .with_children(|parent| {
+            for _ in 0..20 {
+                let mut buffer = TextInputBuffer::default();
+                buffer.set_text(TEXT);
+                parent.spawn((
+                    TextInputNode::default(),
+                    buffer,
+                    Node {
+                        width: Val::Percent(50.),
+                        height: Val::Px(30.),
+                        ..default()
+                    },
+                    BackgroundColor(NAVY.into()),
+                ));
+            }
+        });
+}
+
+/// Alternate between two window widths, like a window being dragged back and forth
+fn resize_window(mut window: Single<&mut Window>, mut frame: Local<u32>) {
+    *frame += 1;
+    let width = if (*frame / 2).is_multiple_of(2) {
+        1200.
+    } else {
+        1000.
+    };
+    window.resolution.set(width, 800.);
+}
+
+fn report_system(diagnostics: Res<DiagnosticsStore>, pipeline: Res<TextInputPipeline>) {
+    let fps = diagnostics
+        .get(&FrameTimeDiagnosticsPlugin::FPS)
+        .and_then(|fps| fps.smoothed())
+        .unwrap_or_default();
+    info!(
+        "fps: {fps:.0}, shaping cache hits: {}, misses: {}",
+        pipeline.shaping_cache.hits(),
+        pipeline.shaping_cache.misses()
+    );
+}
@@ -1,5 +1,6 @@
 use cosmic_text::Action;
 use cosmic_text::BorrowedWithFontSystem;
+use cosmic_text::Cursor;
 use cosmic_text::Edit;
 use cosmic_text::Editor;
 use cosmic_text::Motion;
@@ -9,8 +10,17 @@ use crate::TextInputFilter;
 use crate::clipboard::ClipboardRead;
 use crate::edit::apply_action;
 use crate::edit::apply_motion;
+use crate::edit::backspace_grapheme;
 use crate::edit::buffer_len;
 use crate::edit::cursor_at_line_end;
+use crate::edit::delete_line;
+use crate::edit::duplicate_line;
+use crate::edit::indent_lines;
+use crate::edit::inserted_len;
+use crate::edit::move_line;
+use crate::edit::normalize_newlines;
+use crate::edit::selection_len;
+use crate::edit::transpose_chars;
 
 /// Actions that can be recieved by a text input
 #[derive(Debug)]
@@ -23,14 +33,44 @@ pub enum TextInputAction {
     Cut,
     /// Retrieve text from the clipboard and then queue
     Paste,
+    /// Retrieve text from the primary selection and then queue.
+    /// Falls back to the clipboard on platforms without a primary selection.
+    PastePrimary,
     /// Paste text from the clipboard
     PasteDeferred(ClipboardRead),
     /// A single edit action
     Edit(TextInputEdit),
+    /// Complete the text at the cursor using the input's `TextInputCompletion`, if it has one
+    Complete,
+    /// Focus the text input, if it is enabled
+    Focus,
+    /// Unfocus the text input if it is focused
+    Blur,
+    /// Submit the text, then unfocus the input if the submission wasn't rejected or suppressed
+    SubmitAndBlur,
+}
+
+impl TextInputAction {
+    /// True if the action can change the input's text
+    pub fn modifies_text(&self) -> bool {
+        match self {
+            TextInputAction::Cut
+            | TextInputAction::Paste
+            | TextInputAction::PastePrimary
+            | TextInputAction::PasteDeferred(_)
+            | TextInputAction::Complete => true,
+            TextInputAction::Edit(edit) => edit.modifies_text(),
+            TextInputAction::Submit
+            | TextInputAction::SubmitAndBlur
+            | TextInputAction::Copy
+            | TextInputAction::Focus
+            | TextInputAction::Blur => false,
+        }
+    }
 }
 
 /// An edit to perform on a [`TextInputBuffer`](crate::TextInputBuffer)
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum TextInputEdit {
     /// Move the cursor with some motion
     Motion(Motion, bool),
@@ -40,13 +80,19 @@ pub enum TextInputEdit {
     Insert(char, bool),
     /// Create new line
     Enter,
-    /// Delete text behind cursor
+    /// Insert a soft line break, represented by `LINE_SEPARATOR` in the input's text
+    SoftBreak,
+    /// Delete text behind cursor.
+    /// At the start of a line, joins the line onto the end of the previous line.
+    /// Does nothing at the start of the text.
     Backspace,
-    /// Delete text in front of cursor
+    /// Delete text in front of cursor.
+    /// At the end of a line, pulls the next line up onto it.
+    /// Does nothing at the end of the text.
     Delete,
-    // Indent text (typically Tab)
+    // Indent the selected lines, or the cursor's line (typically Tab)
     Indent,
-    // Unindent text (typically Shift+Tab)
+    // Unindent the selected lines, or the cursor's line (typically Shift+Tab)
     Unindent,
     /// Mouse click at specified position
     Click {
@@ -76,16 +122,90 @@ pub enum TextInputEdit {
     Undo,
     Redo,
     SelectAll,
+    /// Swap the grapheme clusters either side of the cursor, or the two before it at the end of a line
+    TransposeChars,
+    /// Delete the line the cursor is on, including its line break
+    DeleteLine,
+    /// Insert a copy of the cursor's line below it
+    DuplicateLine,
+    /// Swap the cursor's line with the line above it
+    MoveLineUp,
+    /// Swap the cursor's line with the line below it
+    MoveLineDown,
+}
+
+impl TextInputEdit {
+    /// True if the edit can change the input's text
+    pub fn modifies_text(&self) -> bool {
+        !matches!(
+            self,
+            TextInputEdit::Motion(..)
+                | TextInputEdit::Escape
+                | TextInputEdit::Click { .. }
+                | TextInputEdit::DoubleClick { .. }
+                | TextInputEdit::TripleClick { .. }
+                | TextInputEdit::Drag { .. }
+                | TextInputEdit::Scroll { .. }
+                | TextInputEdit::SelectAll
+        )
+    }
 }
 
-/// apply a single `TextInputEdit` to a text editor buffer
+/// Why an edit was rejected
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TextInputRejection {
+    /// The resulting text would not match the input's `TextInputFilter` or `TextInputRegex`
+    Filter,
+    /// The resulting text would be longer than the input's `max_chars`
+    MaxChars,
+    /// The input is read-only, it mirrors another input
+    ReadOnly,
+}
+
+/// apply a single `TextInputEdit` to a text editor buffer.
+///
+/// Returns the reason the edit was rejected, if it was rejected.
+///
+/// Assumes the editor's text passes `filter_mode`. Single chars inserted after the start of the text
+/// are only checked with `TextInputFilter::is_match_char`, so they aren't rejected for the rest of
+/// the text failing the filter.
 pub fn apply_text_input_edit(
     edit: TextInputEdit,
     editor: &mut BorrowedWithFontSystem<'_, Editor<'static>>,
     changes: &mut cosmic_undo_2::Commands<cosmic_text::Change>,
     max_chars: Option<usize>,
     filter_mode: Option<&TextInputFilter>,
-) {
+) -> Option<TextInputRejection> {
+    apply_validated_edit(edit, editor, changes, max_chars, filter_mode, None)
+}
+
+/// Applies an edit like `apply_text_input_edit`, also rejecting it with `TextInputRejection::Filter` if
+/// the resulting text fails `validate`.
+pub(crate) fn apply_validated_edit(
+    edit: TextInputEdit,
+    editor: &mut BorrowedWithFontSystem<'_, Editor<'static>>,
+    changes: &mut cosmic_undo_2::Commands<cosmic_text::Change>,
+    max_chars: Option<usize>,
+    mut filter_mode: Option<&TextInputFilter>,
+    validate: Option<&dyn Fn(&str) -> bool>,
+) -> Option<TextInputRejection> {
+    // Avoid rebuilding the whole text to check the filter if checking the inserted char is enough.
+    // Only sound if the text already passes the filter, see `TextInputFilter::is_match_char`.
+    if let TextInputEdit::Insert(ch, _) = edit
+        && let Some(is_match) = filter_mode.and_then(|filter| filter.is_match_char(ch))
+        && let Cursor { line, index, .. } = editor
+            .selection_bounds()
+            .map_or(editor.cursor(), |(start, _)| start)
+        && (line, index) != (0, 0)
+    {
+        if !is_match {
+            return Some(TextInputRejection::Filter);
+        }
+        filter_mode = None;
+    }
+
+    let mut rejection = None;
+
     editor.start_change();
 
     match edit {
@@ -103,13 +223,15 @@ pub fn apply_text_input_edit(
                 editor.action(Action::Insert(ch));
             } else if max_chars.is_none_or(|max_chars| editor.with_buffer(buffer_len) < max_chars) {
                 editor.action(Action::Insert(ch));
+            } else {
+                rejection = Some(TextInputRejection::MaxChars);
             }
         }
         TextInputEdit::Backspace => {
             if editor.delete_selection() {
                 editor.set_redraw(true);
             } else {
-                editor.action(Action::Backspace);
+                backspace_grapheme(editor);
             }
         }
         TextInputEdit::Delete => {
@@ -120,10 +242,10 @@ pub fn apply_text_input_edit(
             }
         }
         TextInputEdit::Indent => {
-            editor.action(Action::Indent);
+            indent_lines(editor, false);
         }
         TextInputEdit::Unindent => {
-            editor.action(Action::Unindent);
+            indent_lines(editor, true);
         }
         TextInputEdit::Click { x, y } => {
             editor.action(Action::Click { x, y });
@@ -141,8 +263,13 @@ pub fn apply_text_input_edit(
             editor.action(Action::Scroll { pixels });
         }
         TextInputEdit::Paste(text) => {
-            if max_chars.is_none_or(|max| editor.with_buffer(buffer_len) + text.len() <= max) {
-                editor.insert_string(&text, None);
+            let text = normalize_newlines(text);
+            if max_chars.is_none_or(|max| {
+                editor.with_buffer(buffer_len) - selection_len(editor) + inserted_len(&text) <= max
+            }) {
+                editor.insert_string(&crate::to_buffer_text(&text), None);
+            } else {
+                rejection = Some(TextInputRejection::MaxChars);
             }
         }
         TextInputEdit::Undo => {
@@ -166,25 +293,48 @@ pub fn apply_text_input_edit(
         TextInputEdit::Enter => {
             editor.action(Action::Enter);
         }
+        TextInputEdit::SoftBreak => {
+            editor.insert_string(crate::SOFT_BREAK.as_str(), None);
+        }
+        TextInputEdit::TransposeChars => {
+            transpose_chars(editor);
+        }
+        TextInputEdit::DeleteLine => {
+            delete_line(editor);
+        }
+        TextInputEdit::DuplicateLine => {
+            if !duplicate_line(editor, max_chars) {
+                rejection = Some(TextInputRejection::MaxChars);
+            }
+        }
+        TextInputEdit::MoveLineUp => {
+            move_line(editor, false);
+        }
+        TextInputEdit::MoveLineDown => {
+            move_line(editor, true);
+        }
     }
 
     let Some(mut change) = editor.finish_change() else {
-        return;
+        return rejection;
     };
 
     if change.items.is_empty() {
-        return;
+        return rejection;
     }
 
-    if let Some(filter_mode) = filter_mode {
+    if filter_mode.is_some() || validate.is_some() {
         let text = editor.with_buffer(crate::get_text);
-        if !filter_mode.is_match(&text) {
+        if !filter_mode.is_none_or(|filter_mode| filter_mode.is_match(&text))
+            || !validate.is_none_or(|validate| validate(&text))
+        {
             change.reverse();
             editor.apply_change(&change);
-            return;
+            return Some(TextInputRejection::Filter);
         }
     }
 
     changes.push(change);
     editor.set_redraw(true);
+    None
 }
@@ -40,31 +40,86 @@ impl ClipboardRead {
     }
 }
 
+/// The state of a [`ClipboardRead`], returned by [`ClipboardRead::poll`].
+#[derive(Debug)]
+pub enum ClipboardReadStatus {
+    /// The clipboard contents are still being fetched.
+    Pending,
+    /// The clipboard holds text.
+    Text(String),
+    /// The clipboard is empty or holds non-text content, such as an image.
+    NoText,
+    /// Reading from the clipboard failed.
+    Error(ClipboardError),
+}
+
+impl ClipboardRead {
+    /// Poll the clipboard read, distinguishing between pending reads, text, non-text contents and errors.
+    pub fn poll(&mut self) -> ClipboardReadStatus {
+        match self.poll_result() {
+            None => ClipboardReadStatus::Pending,
+            Some(Ok(text)) if text.is_empty() => ClipboardReadStatus::NoText,
+            Some(Ok(text)) => ClipboardReadStatus::Text(text),
+            Some(Err(ClipboardError::ContentNotAvailable)) => ClipboardReadStatus::NoText,
+            Some(Err(error)) => ClipboardReadStatus::Error(error),
+        }
+    }
+}
+
+/// A clipboard used by text inputs instead of the system clipboard, for example an in-app clipboard
+/// or one shared with a remote client. Set with `TextInputPlugin::with_clipboard_backend`.
+pub trait ClipboardBackend: Send + Sync + 'static {
+    /// Read the clipboard's text
+    fn get_text(&mut self) -> Result<String, ClipboardError>;
+
+    /// Place `text` on the clipboard
+    fn set_text(&mut self, text: String) -> Result<(), ClipboardError>;
+
+    /// Read the primary selection's text. Reads the clipboard by default.
+    fn get_primary_text(&mut self) -> Result<String, ClipboardError> {
+        self.get_text()
+    }
+}
+
 /// Resource providing access to the clipboard
-#[cfg(unix)]
 #[derive(Resource)]
-pub struct Clipboard(Option<arboard::Clipboard>);
+pub struct Clipboard {
+    #[cfg(unix)]
+    system: Option<arboard::Clipboard>,
+    backend: Option<Box<dyn ClipboardBackend>>,
+}
 
-#[cfg(unix)]
 impl Default for Clipboard {
     fn default() -> Self {
-        Self(arboard::Clipboard::new().ok())
+        Self {
+            #[cfg(unix)]
+            system: arboard::Clipboard::new().ok(),
+            backend: None,
+        }
     }
 }
 
-/// Resource providing access to the clipboard
-#[cfg(not(unix))]
-#[derive(Resource, Default)]
-pub struct Clipboard;
-
 impl Clipboard {
+    /// A clipboard that uses `backend` instead of the system clipboard
+    pub fn with_backend(backend: Box<dyn ClipboardBackend>) -> Self {
+        Self {
+            #[cfg(unix)]
+            system: None,
+            backend: Some(backend),
+        }
+    }
+
     /// Fetches UTF-8 text from the clipboard and returns it via a `ClipboardRead`.
     ///
     /// On Windows and Unix `ClipboardRead`s are completed instantly, on wasm32 the result is fetched asynchronously.
     pub fn fetch_text(&mut self) -> ClipboardRead {
+        if let Some(backend) = self.backend.as_mut() {
+            return ClipboardRead::Ready(backend.get_text());
+        }
+
         #[cfg(unix)]
         {
-            ClipboardRead::Ready(if let Some(clipboard) = self.0.as_mut() {
+            ClipboardRead::Ready(if let Some(clipboard) = self.system.as_mut() {
                 clipboard.get_text().map_err(ClipboardError::from)
             } else {
                 Err(ClipboardError::ClipboardNotSupported)
@@ -105,6 +160,40 @@ impl Clipboard {
         }
     }
 
+    /// Fetches UTF-8 text from the primary selection and returns it via a `ClipboardRead`.
+    ///
+    /// The primary selection is only available on Linux and BSD, other platforms fall back to the regular clipboard.
+    pub fn fetch_primary_text(&mut self) -> ClipboardRead {
+        if let Some(backend) = self.backend.as_mut() {
+            return ClipboardRead::Ready(backend.get_primary_text());
+        }
+
+        #[cfg(all(
+            unix,
+            not(any(target_os = "macos", target_os = "android", target_os = "emscripten"))
+        ))]
+        {
+            use arboard::{GetExtLinux, LinuxClipboardKind};
+            ClipboardRead::Ready(if let Some(clipboard) = self.system.as_mut() {
+                clipboard
+                    .get()
+                    .clipboard(LinuxClipboardKind::Primary)
+                    .text()
+                    .map_err(ClipboardError::from)
+            } else {
+                Err(ClipboardError::ClipboardNotSupported)
+            })
+        }
+
+        #[cfg(not(all(
+            unix,
+            not(any(target_os = "macos", target_os = "android", target_os = "emscripten"))
+        )))]
+        {
+            self.fetch_text()
+        }
+    }
+
     /// Places the text onto the clipboard. Any valid UTF-8 string is accepted.
     ///
     /// # Errors
@@ -114,9 +203,13 @@ impl Clipboard {
         &mut self,
         text: T,
     ) -> Result<(), ClipboardError> {
+        if let Some(backend) = self.backend.as_mut() {
+            return backend.set_text(text.into().into_owned());
+        }
+
         #[cfg(unix)]
         {
-            if let Some(clipboard) = self.0.as_mut() {
+            if let Some(clipboard) = self.system.as_mut() {
                 clipboard.set_text(text).map_err(ClipboardError::from)
             } else {
                 Err(ClipboardError::ClipboardNotSupported)
@@ -1,50 +1,102 @@
+use crate::BlinkClock;
+use crate::CaretMoved;
+use crate::CaretRect;
+use crate::CompletionCandidates;
+use crate::CopyFormatter;
+use crate::InterceptEdits;
+use crate::KeepTextInputFocus;
+use crate::ManualInput;
+use crate::MirrorTextInput;
+use crate::OnBeforeEdit;
+use crate::PasteFailed;
+use crate::PasteFailure;
+use crate::PasteIndentation;
+use crate::PasteOverflow;
+use crate::PasteTooLarge;
+#[cfg(feature = "regex")]
+use crate::RegexValidateMode;
+use crate::SelectAllOnFocus;
+use crate::SubmitModifier;
 use crate::SubmitText;
 use crate::TextInputBuffer;
+use crate::TextInputCompletion;
+use crate::TextInputConfig;
+use crate::TextInputCursorBlink;
 use crate::TextInputFilter;
 use crate::TextInputGlobalState;
+use crate::TextInputGutter;
 use crate::TextInputMode;
+use crate::TextInputMotionMask;
 use crate::TextInputNode;
 use crate::TextInputQueue;
+use crate::TextInputRecorder;
+#[cfg(feature = "regex")]
+use crate::TextInputRegex;
+use crate::TextInputRejected;
 use crate::TextInputStyle;
 use crate::actions::TextInputAction;
 use crate::actions::TextInputEdit;
-use crate::actions::apply_text_input_edit;
+use crate::actions::TextInputRejection;
+use crate::actions::apply_validated_edit;
 use crate::clipboard::Clipboard;
+use crate::clipboard::ClipboardReadStatus;
+use crate::is_focused;
 use crate::text_input_pipeline::TextInputPipeline;
+use bevy::camera::NormalizedRenderTarget;
 use bevy::ecs::component::Component;
+use bevy::ecs::entity::ContainsEntity;
 use bevy::ecs::entity::Entity;
+use bevy::ecs::hierarchy::ChildOf;
 use bevy::ecs::message::MessageReader;
 use bevy::ecs::message::MessageWriter;
 use bevy::ecs::observer::On;
+use bevy::ecs::query::Has;
+use bevy::ecs::query::With;
+use bevy::ecs::query::Without;
 use bevy::ecs::system::Commands;
+use bevy::ecs::system::Local;
 use bevy::ecs::system::Query;
 use bevy::ecs::system::Res;
 use bevy::ecs::system::ResMut;
+use bevy::ecs::world::World;
 use bevy::input::ButtonState;
 use bevy::input::keyboard::Key;
 use bevy::input::keyboard::KeyboardInput;
 use bevy::input::mouse::MouseScrollUnit;
-use bevy::input::mouse::MouseWheel;
 use bevy::input_focus::FocusedInput;
 use bevy::input_focus::InputFocus;
 use bevy::math::Rect;
+use bevy::math::Vec2;
 use bevy::picking::events::Click;
 use bevy::picking::events::Drag;
 use bevy::picking::events::Move;
 use bevy::picking::events::Pointer;
 use bevy::picking::events::Press;
+use bevy::picking::events::Scroll;
 use bevy::picking::hover::HoverMap;
 use bevy::picking::pointer::PointerButton;
+use bevy::picking::pointer::PointerId;
+use bevy::picking::pointer::PointerLocation;
+use bevy::picking::pointer::PointerPress;
+use bevy::time::Real;
 use bevy::time::Time;
 use bevy::ui::ComputedNode;
 use bevy::ui::UiGlobalTransform;
+use bevy::window::CursorIcon;
+use bevy::window::SystemCursorIcon;
+use bevy::window::Window;
 use cosmic_text::Action;
 use cosmic_text::BorrowedWithFontSystem;
 use cosmic_text::Change;
+use cosmic_text::Cursor;
 use cosmic_text::Edit;
 use cosmic_text::Editor;
+use cosmic_text::LineEnding;
 use cosmic_text::Motion;
 use cosmic_text::Selection;
+use cosmic_text::Wrap;
+use std::collections::VecDeque;
+use unicode_segmentation::UnicodeSegmentation;
 
 pub fn apply_action<'a>(
     editor: &mut BorrowedWithFontSystem<Editor<'a>>,
@@ -62,6 +114,11 @@ pub fn apply_action<'a>(
     }
 }
 
+/// Moves the cursor, selecting if `shift_pressed`.
+///
+/// Horizontal motions move past whole grapheme clusters. From a cursor inside a cluster, placed
+/// there programmatically or by a click on a cluster drawn with several glyphs, they move to the
+/// cluster's end or start.
 pub fn apply_motion<'a>(
     editor: &mut BorrowedWithFontSystem<Editor<'a>>,
     shift_pressed: bool,
@@ -75,7 +132,79 @@ pub fn apply_motion<'a>(
     } else {
         editor.action(Action::Escape);
     }
+    let cluster_start = if matches!(
+        motion,
+        Motion::Left | Motion::Right | Motion::Previous | Motion::Next
+    ) {
+        containing_grapheme_start(editor, editor.cursor())
+    } else {
+        None
+    };
+    // The editor only moves by whole clusters from a cluster boundary
+    if let Some(cluster_start) = cluster_start {
+        editor.set_cursor(cluster_start);
+    }
     editor.action(Action::Motion(motion));
+    // A backward motion from inside a cluster stops at the cluster's start
+    if let Some(cluster_start) = cluster_start
+        && editor.cursor() < cluster_start
+    {
+        editor.set_cursor(cluster_start);
+    }
+}
+
+/// Start of the grapheme cluster containing `cursor`, if it's inside a cluster rather than at a
+/// boundary between them.
+fn containing_grapheme_start(
+    editor: &BorrowedWithFontSystem<Editor<'_>>,
+    cursor: Cursor,
+) -> Option<Cursor> {
+    editor.with_buffer(|buffer| {
+        let (start, grapheme) = buffer
+            .lines
+            .get(cursor.line)?
+            .text()
+            .grapheme_indices(true)
+            .take_while(|(index, _)| *index < cursor.index)
+            .last()?;
+        (cursor.index < start + grapheme.len()).then(|| Cursor::new(cursor.line, start))
+    })
+}
+
+/// Makes the next vertical motion start from the cursor's current column, instead of the column
+/// remembered by the editor from earlier vertical motions. Doesn't move the cursor.
+fn reset_goal_column(editor: &mut BorrowedWithFontSystem<Editor<'_>>) {
+    let cursor = editor.cursor();
+    // Non-vertical motions clear the editor's remembered column
+    editor.action(Action::Motion(Motion::ParagraphStart));
+    editor.set_cursor(cursor);
+}
+
+/// Applies an edit, keeping the editor's remembered column only across consecutive vertical motions.
+pub(crate) fn apply_edit_with_goal_column(
+    edit: TextInputEdit,
+    editor: &mut BorrowedWithFontSystem<Editor<'static>>,
+    changes: &mut cosmic_undo_2::Commands<Change>,
+    vertical_motion_cursor: &mut Option<Cursor>,
+    max_chars: Option<usize>,
+    filter: Option<&TextInputFilter>,
+    validate: Option<&dyn Fn(&str) -> bool>,
+) -> Option<TextInputRejection> {
+    let is_vertical_motion = matches!(
+        edit,
+        TextInputEdit::Motion(
+            Motion::Up | Motion::Down | Motion::PageUp | Motion::PageDown | Motion::Vertical(_),
+            _
+        )
+    );
+    // Only keep the remembered column if the cursor hasn't moved since the last
+    // vertical motion, by an edit, a click or programmatically.
+    if is_vertical_motion && vertical_motion_cursor.is_none_or(|cursor| cursor != editor.cursor()) {
+        reset_goal_column(editor);
+    }
+    let rejection = apply_validated_edit(edit, editor, changes, max_chars, filter, validate);
+    *vertical_motion_cursor = is_vertical_motion.then(|| editor.cursor());
+    rejection
 }
 
 pub fn buffer_len(buffer: &cosmic_text::Buffer) -> usize {
@@ -86,6 +215,69 @@ pub fn buffer_len(buffer: &cosmic_text::Buffer) -> usize {
         .sum()
 }
 
+/// Converts `\r\n` and lone `\r` line breaks to `\n`
+pub(crate) fn normalize_newlines(text: String) -> String {
+    if text.contains('\r') {
+        text.replace("\r\n", "\n").replace('\r', "\n")
+    } else {
+        text
+    }
+}
+
+/// True for the characters removed by sanitization: control characters, and the bidirectional
+/// formatting characters that can make text display in a different order than it is stored.
+pub fn is_disallowed_char(ch: char) -> bool {
+    ch.is_control()
+        || matches!(
+            ch,
+            '\u{061C}' | '\u{200E}' | '\u{200F}' | '\u{202A}'..='\u{202E}' | '\u{2066}'..='\u{2069}'
+        )
+}
+
+/// Removes the disallowed characters not in `allowed` from `text`
+pub(crate) fn sanitize_text(text: String, allowed: &[char]) -> String {
+    if text
+        .chars()
+        .any(|ch| is_disallowed_char(ch) && !allowed.contains(&ch))
+    {
+        text.chars()
+            .filter(|ch| !is_disallowed_char(*ch) || allowed.contains(ch))
+            .collect()
+    } else {
+        text
+    }
+}
+
+/// Number of characters `text` adds to `buffer_len` when inserted, line breaks aren't counted
+pub(crate) fn inserted_len(text: &str) -> usize {
+    text.chars().filter(|ch| !matches!(ch, '\n' | '\r')).count()
+}
+
+/// Number of characters the selection frees up when it is replaced, as counted by `buffer_len`
+pub(crate) fn selection_len(editor: &BorrowedWithFontSystem<Editor<'_>>) -> usize {
+    editor
+        .copy_selection()
+        .map_or(0, |selected| inserted_len(&selected))
+}
+
+/// Truncates pasted text to the characters that fit within `max_chars`,
+/// counting the selection replaced by the paste as free space.
+fn truncate_paste(
+    editor: &BorrowedWithFontSystem<Editor<'_>>,
+    text: &mut String,
+    max_chars: usize,
+) {
+    let available =
+        max_chars.saturating_sub(editor.with_buffer(buffer_len) - selection_len(editor));
+    if let Some((end, _)) = text
+        .char_indices()
+        .filter(|(_, ch)| !matches!(ch, '\n' | '\r'))
+        .nth(available)
+    {
+        text.truncate(end);
+    }
+}
+
 pub fn cursor_at_line_end(editor: &mut BorrowedWithFontSystem<Editor<'_>>) -> bool {
     let cursor = editor.cursor();
     editor.with_buffer(|buffer| {
@@ -97,17 +289,566 @@ pub fn cursor_at_line_end(editor: &mut BorrowedWithFontSystem<Editor<'_>>) -> bo
     })
 }
 
+/// Indents, or unindents, each line touched by the selection, or the cursor's line if nothing is selected.
+/// The selection is kept. A selection ending at the start of a line doesn't touch that line.
+pub fn indent_lines(editor: &mut BorrowedWithFontSystem<Editor<'_>>, unindent: bool) {
+    let action = if unindent {
+        Action::Unindent
+    } else {
+        Action::Indent
+    };
+    let (Selection::Normal(anchor), Some((start, end))) =
+        (editor.selection(), editor.selection_bounds())
+    else {
+        editor.action(action);
+        return;
+    };
+    if end.index != 0 || end.line == start.line {
+        editor.action(action);
+        return;
+    }
+
+    // End the selection on the previous line while indenting, then restore it
+    let previous_line_end = editor
+        .with_buffer(|buffer| Cursor::new(end.line - 1, buffer.lines[end.line - 1].text().len()));
+    if anchor == end {
+        editor.set_selection(Selection::Normal(previous_line_end));
+        editor.action(action);
+        editor.set_selection(Selection::Normal(end));
+    } else {
+        editor.set_cursor(previous_line_end);
+        editor.action(action);
+        editor.set_cursor(end);
+    }
+}
+
+/// Swap the grapheme clusters either side of the cursor, or the two before the cursor if it is at the end of a line.
+/// Does nothing at the start of a line or if there is a selection.
+pub fn transpose_chars(editor: &mut BorrowedWithFontSystem<Editor<'_>>) {
+    if editor.selection() != Selection::None {
+        return;
+    }
+
+    let cursor = editor.cursor();
+    let Some(line) = editor.with_buffer(|buffer| {
+        buffer
+            .lines
+            .get(cursor.line)
+            .map(|line| line.text().to_string())
+    }) else {
+        return;
+    };
+
+    let prev_grapheme_start = |index: usize| {
+        line[..index]
+            .grapheme_indices(true)
+            .next_back()
+            .map(|(i, _)| i)
+    };
+
+    let (start, mid, end) = if cursor.index < line.len() {
+        let Some(start) = prev_grapheme_start(cursor.index) else {
+            return;
+        };
+        let end = line[cursor.index..]
+            .graphemes(true)
+            .next()
+            .map_or(cursor.index, |grapheme| cursor.index + grapheme.len());
+        (start, cursor.index, end)
+    } else {
+        let Some(mid) = prev_grapheme_start(cursor.index) else {
+            return;
+        };
+        let Some(start) = prev_grapheme_start(mid) else {
+            return;
+        };
+        (start, mid, cursor.index)
+    };
+
+    let swapped = format!("{}{}", &line[mid..end], &line[start..mid]);
+    editor.delete_range(
+        Cursor::new(cursor.line, start),
+        Cursor::new(cursor.line, end),
+    );
+    editor.insert_at(Cursor::new(cursor.line, start), &swapped, None);
+    editor.set_cursor(Cursor::new(cursor.line, end));
+}
+
+/// Deletes the line the cursor is on along with its line break, and moves the cursor to the start of the following line.
+/// The last line takes the preceding line break instead. If there is only one line it is cleared.
+pub fn delete_line(editor: &mut BorrowedWithFontSystem<Editor<'_>>) {
+    let line = editor.cursor().line;
+    let Some((line_count, prev_len, len)) = editor.with_buffer(|buffer| {
+        buffer.lines.get(line).map(|current| {
+            let prev_len = line
+                .checked_sub(1)
+                .map_or(0, |prev| buffer.lines[prev].text().len());
+            (buffer.lines.len(), prev_len, current.text().len())
+        })
+    }) else {
+        return;
+    };
+
+    editor.set_selection(Selection::None);
+    let (start, end, cursor) = if line + 1 < line_count {
+        let start = Cursor::new(line, 0);
+        (start, Cursor::new(line + 1, 0), start)
+    } else if 0 < line {
+        (
+            Cursor::new(line - 1, prev_len),
+            Cursor::new(line, len),
+            Cursor::new(line - 1, 0),
+        )
+    } else {
+        let start = Cursor::new(line, 0);
+        (start, Cursor::new(line, len), start)
+    };
+    editor.delete_range(start, end);
+    editor.set_cursor(cursor);
+    editor.set_redraw(true);
+}
+
+/// Inserts a copy of the cursor's line below it and moves the cursor to the same column on the copy.
+/// Returns false without editing if the copy would take the text over `max_chars`.
+pub fn duplicate_line(
+    editor: &mut BorrowedWithFontSystem<Editor<'_>>,
+    max_chars: Option<usize>,
+) -> bool {
+    let cursor = editor.cursor();
+    let Some((line, ending)) = editor.with_buffer(|buffer| {
+        buffer
+            .lines
+            .get(cursor.line)
+            .map(|line| (line.text().to_string(), line.ending()))
+    }) else {
+        return true;
+    };
+    // The copy is separated by the line's own break, so duplicating a line ending in a soft break
+    // doesn't turn it into a paragraph break. The last line has no ending.
+    let separator = match ending {
+        LineEnding::None => LineEnding::Lf,
+        ending => ending,
+    };
+
+    if max_chars.is_some_and(|max| max < editor.with_buffer(buffer_len) + inserted_len(&line)) {
+        return false;
+    }
+
+    editor.set_selection(Selection::None);
+    editor.insert_at(
+        Cursor::new(cursor.line, line.len()),
+        &format!("{}{line}", separator.as_str()),
+        None,
+    );
+    editor.set_cursor(Cursor::new(cursor.line + 1, cursor.index));
+    editor.set_redraw(true);
+    true
+}
+
+/// Swaps the cursor's line with the line below it, or above it if `down` is false.
+/// The cursor follows the moved line. Does nothing if there is no line to swap with.
+pub fn move_line(editor: &mut BorrowedWithFontSystem<Editor<'_>>, down: bool) {
+    let cursor = editor.cursor();
+    let (first, target) = if down {
+        (cursor.line, cursor.line + 1)
+    } else {
+        let Some(above) = cursor.line.checked_sub(1) else {
+            return;
+        };
+        (above, above)
+    };
+    let Some((upper, separator, lower)) = editor.with_buffer(|buffer| {
+        let upper = buffer.lines.get(first)?;
+        Some((
+            upper.text().to_string(),
+            upper.ending(),
+            buffer.lines.get(first + 1)?.text().to_string(),
+        ))
+    }) else {
+        return;
+    };
+
+    // Only the texts are swapped, the line breaks stay in place
+    editor.set_selection(Selection::None);
+    editor.delete_range(Cursor::new(first, 0), Cursor::new(first + 1, lower.len()));
+    editor.insert_at(
+        Cursor::new(first, 0),
+        &format!("{lower}{}{upper}", separator.as_str()),
+        None,
+    );
+    editor.set_cursor(Cursor::new(target, cursor.index));
+    editor.set_redraw(true);
+}
+
+/// Deletes the grapheme cluster before the cursor, so multi-codepoint emoji are removed in one go.
+/// At the start of a line, joins it with the previous line. Does nothing at the start of the text.
+pub fn backspace_grapheme(editor: &mut BorrowedWithFontSystem<Editor<'_>>) {
+    let cursor = editor.cursor();
+    if cursor.index == 0 {
+        editor.action(Action::Backspace);
+        return;
+    }
+
+    let start = editor.with_buffer(|buffer| {
+        buffer.lines[cursor.line].text()[..cursor.index]
+            .grapheme_indices(true)
+            .next_back()
+            .map_or(0, |(index, _)| index)
+    });
+    let start = Cursor::new(cursor.line, start);
+    editor.delete_range(start, cursor);
+    editor.set_cursor(start);
+    editor.set_redraw(true);
+}
+
+fn leading_whitespace(line: &str) -> &str {
+    &line[..line.len() - line.trim_start_matches([' ', '\t']).len()]
+}
+
+/// Width in columns of an indentation, with tabs advancing to the next tab stop.
+fn indent_columns(indent: &str, tab_width: usize) -> usize {
+    indent.chars().fold(0, |column, ch| {
+        if ch == '\t' {
+            (column / tab_width + 1) * tab_width
+        } else {
+            column + 1
+        }
+    })
+}
+
+/// Converts the leading indentation of each line of pasted text. If `reindent_to` is set, the lines
+/// after the first have their common indentation replaced with it.
+pub(crate) fn normalize_pasted_text(
+    text: &str,
+    indentation: PasteIndentation,
+    reindent_to: Option<&str>,
+) -> String {
+    let mut lines: Vec<(String, &str)> = text
+        .split('\n')
+        .map(|line| {
+            let indent = leading_whitespace(line);
+            let indent = match indentation {
+                PasteIndentation::Keep => indent.to_string(),
+                PasteIndentation::TabsToSpaces { tab_width } => {
+                    " ".repeat(indent_columns(indent, tab_width.max(1)))
+                }
+                PasteIndentation::SpacesToTabs { tab_width } => {
+                    let tab_width = tab_width.max(1);
+                    let columns = indent_columns(indent, tab_width);
+                    "\t".repeat(columns / tab_width) + &" ".repeat(columns % tab_width)
+                }
+            };
+            (indent, &line[leading_whitespace(line).len()..])
+        })
+        .collect();
+
+    if let Some(reindent_to) = reindent_to {
+        let common = lines
+            .iter()
+            .skip(1)
+            .filter(|(_, body)| !body.trim().is_empty())
+            .map(|(indent, _)| indent.chars().count())
+            .min()
+            .unwrap_or(0);
+        for (indent, body) in lines.iter_mut().skip(1) {
+            *indent = if body.trim().is_empty() {
+                String::new()
+            } else {
+                reindent_to.to_string() + &indent.chars().skip(common).collect::<String>()
+            };
+        }
+    }
+
+    lines
+        .iter()
+        .map(|(indent, body)| indent.clone() + body)
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Returns the cursor at a byte offset into the buffer's text, with lines joined as in `get_text`.
+/// Offsets past the end of the buffer are clamped to the end.
+pub(crate) fn cursor_at_byte_offset(buffer: &cosmic_text::Buffer, mut offset: usize) -> Cursor {
+    for (line_index, line) in buffer.lines.iter().enumerate() {
+        let len = line.text().len();
+        if offset <= len {
+            return Cursor::new(line_index, offset);
+        }
+        offset -= len + crate::line_break(line.ending()).len_utf8();
+    }
+    buffer
+        .lines
+        .len()
+        .checked_sub(1)
+        .map(|last| Cursor::new(last, buffer.lines[last].text().len()))
+        .unwrap_or_default()
+}
+
+/// Returns the cursor at a char offset into the buffer's text, counting each line break as one char.
+/// Offsets past the end of the buffer are clamped to the end.
+pub(crate) fn cursor_at_char_offset(buffer: &cosmic_text::Buffer, mut offset: usize) -> Cursor {
+    for (line_index, line) in buffer.lines.iter().enumerate() {
+        let text = line.text();
+        if let Some((index, _)) = text.char_indices().nth(offset) {
+            return Cursor::new(line_index, index);
+        }
+        let len = text.chars().count();
+        if offset == len {
+            return Cursor::new(line_index, text.len());
+        }
+        offset -= len + 1;
+    }
+    clamp_cursor(buffer, Cursor::new(usize::MAX, usize::MAX))
+}
+
+/// Returns the position of the caret at `cursor` in the buffer's last layout, relative to the top-left
+/// corner of the laid out text. Matches the position of the editor's own cursor, see
+/// `cosmic_text::Editor::cursor_position`.
+/// Returns `None` if the cursor's line isn't laid out, for example when it is scrolled out of view.
+pub(crate) fn caret_position(buffer: &cosmic_text::Buffer, cursor: Cursor) -> Option<Vec2> {
+    buffer
+        .layout_runs()
+        .filter(|run| run.line_i == cursor.line)
+        .find_map(|run| {
+            let x = if let Some(glyph) = run.glyphs.iter().find(|glyph| {
+                glyph.start == cursor.index
+                    || (glyph.start < cursor.index && cursor.index < glyph.end)
+            }) {
+                // Split multi-grapheme glyphs, like ligatures, evenly between their graphemes
+                let cluster = &run.text[glyph.start..glyph.end];
+                let total = cluster.graphemes(true).count();
+                let before = cluster
+                    .grapheme_indices(true)
+                    .filter(|(i, _)| glyph.start + i < cursor.index)
+                    .count();
+                let offset = glyph.w * before as f32 / total as f32;
+                if glyph.level.is_rtl() {
+                    glyph.x + glyph.w - offset
+                } else {
+                    glyph.x + offset
+                }
+            } else {
+                match run.glyphs.last() {
+                    Some(glyph) if glyph.end == cursor.index => {
+                        if glyph.level.is_rtl() {
+                            glyph.x
+                        } else {
+                            glyph.x + glyph.w
+                        }
+                    }
+                    Some(_) => return None,
+                    None => 0.,
+                }
+            };
+            Some(Vec2::new(x, run.line_top))
+        })
+}
+
+/// Layout of a masked input's text, with one mask char for each grapheme. It's drawn in place of
+/// the text, and cursors and pointer positions are mapped between the two by grapheme.
+#[derive(Debug)]
+pub(crate) struct TextMask {
+    pub(crate) buffer: cosmic_text::Buffer,
+    /// Byte offsets of the grapheme boundaries of each line of the text, including its end
+    boundaries: Vec<Vec<usize>>,
+    mask_len: usize,
+}
+
+impl TextMask {
+    /// Lays out the mask of the text in `text`, with the same metrics, size and wrapping.
+    /// The horizontal scroll is kept from `previous`, and moved to keep the cursor in view.
+    pub(crate) fn new(
+        font_system: &mut cosmic_text::FontSystem,
+        text: &cosmic_text::Buffer,
+        cursor: Cursor,
+        mask_char: char,
+        align: Option<cosmic_text::Align>,
+        previous: Option<&TextMask>,
+    ) -> Self {
+        let boundaries: Vec<Vec<usize>> = text
+            .lines
+            .iter()
+            .map(|line| {
+                let text = line.text();
+                text.grapheme_indices(true)
+                    .map(|(i, _)| i)
+                    .chain([text.len()])
+                    .collect()
+            })
+            .collect();
+        let masked = boundaries
+            .iter()
+            .map(|line| String::from(mask_char).repeat(line.len() - 1))
+            .collect::<Vec<_>>()
+            .join("\n");
+
+        let mut buffer = cosmic_text::Buffer::new(font_system, text.metrics());
+        let (width, height) = text.size();
+        buffer.set_size(font_system, width, height);
+        buffer.set_wrap(font_system, text.wrap());
+        let attrs = text
+            .lines
+            .first()
+            .map(|line| line.attrs_list().defaults())
+            .unwrap_or_else(cosmic_text::Attrs::new);
+        buffer.set_text(
+            font_system,
+            &masked,
+            &attrs,
+            cosmic_text::Shaping::Advanced,
+            align,
+        );
+
+        let mut mask = Self {
+            buffer,
+            boundaries,
+            mask_len: mask_char.len_utf8(),
+        };
+
+        let mut scroll = text.scroll();
+        scroll.horizontal = previous.map_or(0., |previous| previous.buffer.scroll().horizontal);
+        mask.buffer.set_scroll(scroll);
+        mask.buffer.shape_until_scroll(font_system, false);
+        if text.wrap() == cosmic_text::Wrap::None
+            && let Some(width) = width
+        {
+            let line_w = mask
+                .buffer
+                .layout_runs()
+                .map(|run| run.line_w)
+                .fold(0., f32::max);
+            if let Some(x) = caret_position(&mask.buffer, mask.mask_cursor(cursor)).map(|p| p.x) {
+                scroll.horizontal = scroll.horizontal.min(x).max(x - width);
+            }
+            scroll.horizontal = scroll.horizontal.min(line_w - width).max(0.);
+            mask.buffer.set_scroll(scroll);
+        }
+        mask
+    }
+
+    /// Maps a cursor in the text to the mask
+    pub(crate) fn mask_cursor(&self, cursor: Cursor) -> Cursor {
+        let graphemes = self.boundaries.get(cursor.line).map_or(0, |boundaries| {
+            boundaries.partition_point(|&boundary| boundary < cursor.index)
+        });
+        Cursor::new_with_affinity(cursor.line, graphemes * self.mask_len, cursor.affinity)
+    }
+
+    /// Maps a cursor in the mask to the text
+    pub(crate) fn text_cursor(&self, cursor: Cursor) -> Cursor {
+        let index = self.boundaries.get(cursor.line).map_or(0, |boundaries| {
+            boundaries
+                .get(cursor.index / self.mask_len)
+                .or(boundaries.last())
+                .copied()
+                .unwrap_or(0)
+        });
+        Cursor::new_with_affinity(cursor.line, index, cursor.affinity)
+    }
+
+    /// Applies a pointer edit using the mask's layout. Double and triple clicks select all of the text,
+    /// selecting a word would reveal where the text's spaces are.
+    ///
+    /// Returns false if the edit isn't a pointer edit.
+    pub(crate) fn apply_pointer_edit(
+        &self,
+        editor: &mut BorrowedWithFontSystem<Editor<'static>>,
+        edit: &TextInputEdit,
+    ) -> bool {
+        let hit = |x: i32, y: i32, editor: &BorrowedWithFontSystem<Editor<'static>>| {
+            let cursor = self.text_cursor(self.buffer.hit(x as f32, y as f32)?);
+            Some(editor.with_buffer(|buffer| clamp_cursor(buffer, cursor)))
+        };
+        match *edit {
+            TextInputEdit::Click { x, y } => {
+                editor.set_selection(Selection::None);
+                if let Some(cursor) = hit(x, y, editor) {
+                    editor.set_cursor(cursor);
+                }
+            }
+            TextInputEdit::DoubleClick { .. } | TextInputEdit::TripleClick { .. } => {
+                editor.set_selection(Selection::Normal(Cursor::default()));
+                editor.action(Action::Motion(Motion::BufferEnd));
+            }
+            TextInputEdit::Drag { x, y } => {
+                if editor.selection() == Selection::None {
+                    let cursor = editor.cursor();
+                    editor.set_selection(Selection::Normal(cursor));
+                }
+                if let Some(cursor) = hit(x, y, editor) {
+                    editor.set_cursor(cursor);
+                }
+            }
+            _ => return false,
+        }
+        editor.set_redraw(true);
+        true
+    }
+}
+
+/// Returns the char offset of a cursor into the buffer's text, counting each line break as one char.
+pub(crate) fn char_offset_at_cursor(buffer: &cosmic_text::Buffer, cursor: Cursor) -> usize {
+    let preceding = buffer
+        .lines
+        .iter()
+        .take(cursor.line)
+        .map(|line| line.text().chars().count() + 1)
+        .sum::<usize>();
+    let column = buffer.lines.get(cursor.line).map_or(0, |line| {
+        line.text()
+            .get(..cursor.index)
+            .map_or(0, |text| text.chars().count())
+    });
+    preceding + column
+}
+
+/// Clamps a cursor to the buffer's last line and to a char boundary within its line.
+pub(crate) fn clamp_cursor(buffer: &cosmic_text::Buffer, cursor: Cursor) -> Cursor {
+    let Some(last) = buffer.lines.len().checked_sub(1) else {
+        return Cursor::default();
+    };
+    let line = cursor.line.min(last);
+    let text = buffer.lines[line].text();
+    let mut index = cursor.index.min(text.len());
+    while !text.is_char_boundary(index) {
+        index -= 1;
+    }
+    Cursor {
+        line,
+        index,
+        ..cursor
+    }
+}
+
 pub(crate) fn is_buffer_empty(buffer: &cosmic_text::Buffer) -> bool {
     buffer.lines.is_empty() || (buffer.lines.len() == 1 && buffer.lines[0].text().is_empty())
 }
 
+/// Pointer position relative to the top left corner of the input's text area, in physical pixels.
+/// Round it to the nearest pixel for the editor's pointer actions. The editor places the cursor after a
+/// glyph when its right half is clicked, truncating would shift clicks near the middle to the left half.
+fn text_area_position(
+    pointer_position: Vec2,
+    node: &ComputedNode,
+    transform: &UiGlobalTransform,
+    gutter: Option<&TextInputGutter>,
+) -> Vec2 {
+    let scale_factor = node.inverse_scale_factor().recip();
+    let rect = Rect::from_center_size(transform.translation, node.size());
+    pointer_position * scale_factor
+        - rect.min
+        - TextInputGutter::inset(gutter, scale_factor) * Vec2::X
+}
+
 pub(crate) fn on_drag_text_input(
     trigger: On<Pointer<Drag>>,
     mut node_query: Query<(
         &ComputedNode,
         &UiGlobalTransform,
         &mut TextInputBuffer,
+        &mut TextInputCursorBlink,
         &TextInputNode,
+        Option<&TextInputGutter>,
     )>,
     mut text_input_pipeline: ResMut<TextInputPipeline>,
     input_focus: Res<InputFocus>,
@@ -116,36 +857,49 @@ pub(crate) fn on_drag_text_input(
         return;
     }
 
-    if input_focus
-        .0
-        .is_none_or(|input_focus_entity| input_focus_entity != trigger.entity)
-    {
+    if !is_focused(trigger.entity, &input_focus) {
         return;
     }
 
-    let Ok((node, transform, mut buffer, input)) = node_query.get_mut(trigger.entity) else {
+    let Ok((node, transform, mut buffer, mut blink, input, gutter)) =
+        node_query.get_mut(trigger.entity)
+    else {
         return;
     };
 
-    if !input.is_enabled || !input.focus_on_pointer_down {
+    if !input.is_enabled || !input.focus_on_pointer_down || !input.allow_selection {
         return;
     }
 
-    let rect = Rect::from_center_size(transform.translation, node.size());
+    let position = text_area_position(trigger.pointer_location.position, node, transform, gutter);
+    if !position.is_finite() {
+        return;
+    }
 
-    let position =
-        trigger.pointer_location.position * node.inverse_scale_factor().recip() - rect.min;
+    let scroll = buffer.horizontal_scroll();
+    let TextInputBuffer { editor, mask, .. } = &mut *buffer;
+    let mut editor = editor.borrow_with(&mut text_input_pipeline.font_system);
 
-    let mut editor = buffer
-        .editor
-        .borrow_with(&mut text_input_pipeline.font_system);
+    let size = editor.with_buffer(|buffer| buffer.size());
 
-    let scroll = editor.with_buffer(|buffer| buffer.scroll());
+    // While the pointer is outside of the text area, extend the selection to the nearest edge.
+    // Just above the text area hits the start of the first line and just below the end of the last.
+    let max = Vec2::new(
+        size.0.unwrap_or(node.size().x),
+        size.1.unwrap_or(node.size().y),
+    ) + 1.;
+    let position = position.clamp(Vec2::splat(-1.), max);
 
-    editor.action(Action::Drag {
-        x: position.x as i32 + scroll.horizontal as i32,
-        y: position.y as i32,
-    });
+    let x = (position.x + scroll).round() as i32;
+    let y = position.y.round() as i32;
+    match mask.as_ref() {
+        Some(mask) => {
+            mask.apply_pointer_edit(&mut editor, &TextInputEdit::Drag { x, y });
+        }
+        None => editor.action(Action::Drag { x, y }),
+    }
+
+    blink.reset();
 }
 
 pub(crate) fn on_text_input_pressed(
@@ -154,16 +908,21 @@ pub(crate) fn on_text_input_pressed(
         &ComputedNode,
         &UiGlobalTransform,
         &mut TextInputBuffer,
+        &mut TextInputCursorBlink,
         &TextInputNode,
+        Option<&TextInputGutter>,
     )>,
     mut text_input_pipeline: ResMut<TextInputPipeline>,
     mut input_focus: ResMut<InputFocus>,
+    mut global_state: ResMut<TextInputGlobalState>,
 ) {
     if trigger.button != PointerButton::Primary {
         return;
     }
 
-    let Ok((node, transform, mut buffer, input)) = node_query.get_mut(trigger.entity) else {
+    let Ok((node, transform, mut buffer, mut blink, input, gutter)) =
+        node_query.get_mut(trigger.entity)
+    else {
         return;
     };
 
@@ -171,126 +930,378 @@ pub(crate) fn on_text_input_pressed(
         return;
     }
 
-    if input_focus
-        .get()
-        .is_none_or(|active_input| active_input != trigger.entity)
-    {
+    if !is_focused(trigger.entity, &input_focus) {
         input_focus.set(trigger.entity);
+        global_state.pointer_focused = Some(trigger.entity);
     }
 
-    let rect = Rect::from_center_size(transform.translation, node.size());
-
-    let position =
-        trigger.pointer_location.position * node.inverse_scale_factor().recip() - rect.min;
+    let position = text_area_position(trigger.pointer_location.position, node, transform, gutter);
 
-    let mut editor = buffer
-        .editor
-        .borrow_with(&mut text_input_pipeline.font_system);
+    let scroll = buffer.horizontal_scroll();
+    let TextInputBuffer { editor, mask, .. } = &mut *buffer;
+    let mut editor = editor.borrow_with(&mut text_input_pipeline.font_system);
 
-    let scroll = editor.with_buffer(|buffer| buffer.scroll());
+    let x = (position.x + scroll).round() as i32;
+    let y = position.y.round() as i32;
+    match mask.as_ref() {
+        Some(mask) => {
+            mask.apply_pointer_edit(&mut editor, &TextInputEdit::Click { x, y });
+        }
+        None => editor.action(Action::Click { x, y }),
+    }
 
-    editor.action(Action::Click {
-        x: position.x as i32 + scroll.horizontal as i32,
-        y: position.y as i32,
-    });
+    blink.reset();
 }
 
-/// Updates the scroll position of scrollable nodes in response to mouse input
-pub fn mouse_wheel_scroll(
-    mut mouse_wheel_events: MessageReader<MouseWheel>,
-    hover_map: Res<HoverMap>,
-    mut node_query: Query<(&TextInputBuffer, &TextInputNode, &mut TextInputQueue)>,
+pub(crate) fn on_text_input_middle_pressed(
+    trigger: On<Pointer<Press>>,
+    mut node_query: Query<(
+        &ComputedNode,
+        &UiGlobalTransform,
+        &TextInputBuffer,
+        &TextInputNode,
+        &mut TextInputQueue,
+        Option<&TextInputGutter>,
+    )>,
+    mut input_focus: ResMut<InputFocus>,
+    mut global_state: ResMut<TextInputGlobalState>,
 ) {
-    for mouse_wheel_event in mouse_wheel_events.read() {
-        for (_, pointer_map) in hover_map.iter() {
-            for (entity, _) in pointer_map.iter() {
-                let Ok((buffer, input, mut queue)) = node_query.get_mut(*entity) else {
-                    continue;
-                };
+    if trigger.button != PointerButton::Middle {
+        return;
+    }
 
-                if !matches!(input.mode, TextInputMode::MultiLine { .. }) {
-                    continue;
-                }
+    let Ok((node, transform, buffer, input, mut queue, gutter)) =
+        node_query.get_mut(trigger.entity)
+    else {
+        return;
+    };
 
-                match mouse_wheel_event.unit {
-                    MouseScrollUnit::Line => {
-                        let line_height = buffer
-                            .editor
-                            .with_buffer(|buffer| buffer.metrics().line_height);
+    if !input.is_enabled || !input.focus_on_pointer_down || !input.middle_click_paste {
+        return;
+    }
 
-                        queue.add(TextInputAction::Edit(TextInputEdit::Scroll {
-                            pixels: -mouse_wheel_event.y * line_height,
-                        }));
-                    }
-                    MouseScrollUnit::Pixel => {
-                        queue.add(TextInputAction::Edit(TextInputEdit::Scroll {
-                            pixels: -mouse_wheel_event.y,
-                        }));
-                    }
-                };
-            }
-        }
+    if !is_focused(trigger.entity, &input_focus) {
+        input_focus.set(trigger.entity);
+        global_state.pointer_focused = Some(trigger.entity);
     }
-}
 
-const MULTI_CLICK_PERIOD: f32 = 0.5; // seconds
+    let position = text_area_position(trigger.pointer_location.position, node, transform, gutter);
 
-#[derive(Component)]
-pub struct MultiClickData {
-    last_click_time: f32,
-    click_count: usize,
+    let scroll = buffer.horizontal_scroll();
+
+    queue.add(TextInputAction::Edit(TextInputEdit::Click {
+        x: (position.x + scroll).round() as i32,
+        y: position.y.round() as i32,
+    }));
+    queue.add(TextInputAction::PastePrimary);
 }
 
-pub fn on_multi_click_set_selection(
-    click: On<Pointer<Click>>,
-    time: Res<Time>,
-    mut text_input_nodes: Query<(
-        &TextInputNode,
-        &mut TextInputQueue,
-        &mut TextInputBuffer,
-        &UiGlobalTransform,
-        &ComputedNode,
-    )>,
-    mut multi_click_datas: Query<&mut MultiClickData>,
+/// Scrolls the cursor of a text input that just gained keyboard focus into view, if its
+/// `scroll_to_caret_on_focus` is set. Runs before `select_all_on_focus_system`, which clears the
+/// pointer focus it reads.
+pub fn scroll_to_caret_on_focus_system(
+    input_focus: Res<InputFocus>,
+    mut previous_focus: Local<Option<Entity>>,
+    global_state: Res<TextInputGlobalState>,
     mut text_input_pipeline: ResMut<TextInputPipeline>,
-    mut commands: Commands,
+    mut query: Query<(&TextInputNode, &mut TextInputBuffer)>,
 ) {
-    if click.button != PointerButton::Primary {
+    if input_focus.get() == *previous_focus {
         return;
     }
+    *previous_focus = input_focus.get();
 
-    let entity = click.entity;
-
-    let Ok((input, mut queue, mut buffer, transform, node)) = text_input_nodes.get_mut(entity)
+    let Some((input, mut buffer)) = input_focus
+        .get()
+        .and_then(|entity| query.get_mut(entity).ok())
     else {
         return;
     };
 
-    if !input.is_enabled || !input.focus_on_pointer_down {
-        return;
+    if input.scroll_to_caret_on_focus && global_state.pointer_focused != input_focus.get() {
+        let cursor = buffer.editor.cursor();
+        buffer.editor.with_buffer_mut(|buffer| {
+            // The buffer has no lines until its text is first set
+            if cursor.line < buffer.lines.len() {
+                buffer.shape_until_cursor(&mut text_input_pipeline.font_system, cursor, false);
+            }
+        });
+    }
+}
+
+/// Queues a select all for a text input that just gained focus, if its `select_all_on_focus` setting allows
+/// it for how it was focused. Focus set by a pointer press on the input is pointer focus, any other
+/// focus change is treated as keyboard focus.
+pub fn select_all_on_focus_system(
+    input_focus: Res<InputFocus>,
+    mut previous_focus: Local<Option<Entity>>,
+    mut global_state: ResMut<TextInputGlobalState>,
+    mut query: Query<(&TextInputNode, &mut TextInputQueue)>,
+) {
+    let pointer_focused = global_state.pointer_focused.take();
+    if input_focus.get() == *previous_focus {
+        return;
+    }
+    *previous_focus = input_focus.get();
+
+    let Some((input, mut queue)) = input_focus
+        .get()
+        .and_then(|entity| query.get_mut(entity).ok())
+    else {
+        return;
+    };
+
+    let select_all = match input.select_all_on_focus {
+        SelectAllOnFocus::Never => false,
+        SelectAllOnFocus::Always => true,
+        SelectAllOnFocus::KeyboardOnly => pointer_focused != input_focus.get(),
+    };
+    if select_all {
+        queue.add(TextInputAction::Edit(TextInputEdit::SelectAll));
+    }
+}
+
+/// Unfocuses the focused text input if it has `blur_on_outside_click` set and the primary mouse button
+/// is pressed on an entity that isn't the input or marked with `KeepTextInputFocus`, or a descendant of either.
+pub fn blur_on_outside_click_system(
+    mut press_reader: MessageReader<Pointer<Press>>,
+    mut input_focus: ResMut<InputFocus>,
+    input_query: Query<&TextInputNode>,
+    keep_focus_query: Query<(), With<KeepTextInputFocus>>,
+    parent_query: Query<&ChildOf>,
+) {
+    for press in press_reader.read() {
+        if press.button != PointerButton::Primary {
+            continue;
+        }
+
+        let Some(focused_entity) = input_focus.get() else {
+            continue;
+        };
+
+        if !input_query
+            .get(focused_entity)
+            .is_ok_and(|input| input.blur_on_outside_click)
+        {
+            continue;
+        }
+
+        if parent_query
+            .iter_ancestors(press.entity)
+            .chain(core::iter::once(press.entity))
+            .any(|entity| entity == focused_entity || keep_focus_query.contains(entity))
+        {
+            continue;
+        }
+
+        input_focus.clear();
+    }
+}
+
+/// The window cursor icon replaced by `text_input_cursor_icon_system`
+#[derive(Default)]
+pub struct TextInputCursorIconState {
+    /// The window showing the text icon, and its icon before it was replaced
+    window: Option<(Entity, Option<CursorIcon>)>,
+    /// The primary mouse button is held down
+    pressed: bool,
+    /// The primary mouse button was pressed over an enabled text input and hasn't been released
+    dragging: bool,
+}
+
+/// Shows the text cursor icon while the mouse pointer is over an enabled text input, or while dragging
+/// a selection that started on one, and restores the window's previous icon after.
+/// Disabled inputs keep the window's icon.
+pub fn text_input_cursor_icon_system(
+    hover_map: Res<HoverMap>,
+    pointer_query: Query<(&PointerId, &PointerLocation, &PointerPress)>,
+    node_query: Query<&TextInputNode, Without<ManualInput>>,
+    window_query: Query<Option<&CursorIcon>, With<Window>>,
+    mut state: Local<TextInputCursorIconState>,
+    mut commands: Commands,
+) {
+    let Some((_, location, press)) = pointer_query
+        .iter()
+        .find(|(pointer_id, ..)| **pointer_id == PointerId::Mouse)
+    else {
+        return;
+    };
+
+    let hovered = hover_map.get(&PointerId::Mouse).is_some_and(|hovered| {
+        hovered
+            .keys()
+            .any(|entity| node_query.get(*entity).is_ok_and(|input| input.is_enabled))
+    });
+
+    if press.is_primary_pressed() {
+        if !state.pressed {
+            state.pressed = true;
+            state.dragging = hovered;
+        }
+    } else {
+        state.pressed = false;
+        state.dragging = false;
+    }
+
+    let window = location
+        .location()
+        .and_then(|location| match location.target {
+            NormalizedRenderTarget::Window(window) => Some(window.entity()),
+            _ => None,
+        })
+        .filter(|_| hovered || state.dragging);
+
+    if state.window.as_ref().map(|(window, _)| *window) == window {
+        return;
+    }
+
+    if let Some((window, icon)) = state.window.take()
+        && let Ok(mut window) = commands.get_entity(window)
+    {
+        match icon {
+            Some(icon) => window.try_insert(icon),
+            None => window.try_remove::<CursorIcon>(),
+        };
+    }
+
+    if let Some(window) = window
+        && let Ok(icon) = window_query.get(window)
+    {
+        state.window = Some((window, icon.cloned()));
+        commands
+            .entity(window)
+            .insert(CursorIcon::from(SystemCursorIcon::Text));
+    }
+}
+
+/// True if the buffer can't scroll any further in the direction of `pixels`, positive pixels scroll down.
+/// Lines below the laid out text are assumed to be more than can be shown.
+pub(crate) fn is_scrolled_to_extent(buffer: &cosmic_text::Buffer, pixels: f32) -> bool {
+    let scroll = buffer.scroll();
+    if pixels < 0. {
+        return scroll.line == 0 && scroll.vertical <= 0.;
+    }
+    let Some(height) = buffer.size().1 else {
+        return true;
+    };
+    let mut bottom = -scroll.vertical;
+    for line in buffer.lines.iter().skip(scroll.line) {
+        let Some(layout) = line.layout_opt() else {
+            return false;
+        };
+        bottom += layout
+            .iter()
+            .map(|layout_line| {
+                layout_line
+                    .line_height_opt
+                    .unwrap_or(buffer.metrics().line_height)
+            })
+            .sum::<f32>();
+        if height < bottom {
+            return false;
+        }
+    }
+    true
+}
+
+/// Scrolls a multi-line text input with the mouse wheel. Once the input can't scroll any further
+/// the event is left to bubble up, so that a scrollable parent can scroll instead.
+pub(crate) fn on_scroll_text_input(
+    mut scroll: On<Pointer<Scroll>>,
+    config: Res<TextInputConfig>,
+    mut node_query: Query<(&TextInputBuffer, &TextInputNode, &mut TextInputQueue)>,
+) {
+    let Ok((buffer, input, mut queue)) = node_query.get_mut(scroll.entity) else {
+        return;
+    };
+
+    if !matches!(input.mode, TextInputMode::MultiLine { .. }) {
+        return;
+    }
+
+    let pixels = match scroll.unit {
+        MouseScrollUnit::Line => -scroll.y * buffer.line_height() * config.scroll_speed,
+        MouseScrollUnit::Pixel => -scroll.y * config.scroll_speed,
+    };
+
+    if pixels == 0.
+        || buffer
+            .editor
+            .with_buffer(|buffer| is_scrolled_to_extent(buffer, pixels))
+    {
+        return;
+    }
+
+    queue.add(TextInputAction::Edit(TextInputEdit::Scroll { pixels }));
+    scroll.propagate(false);
+}
+
+const MULTI_CLICK_PERIOD: f32 = 0.5; // seconds
+
+#[derive(Component)]
+pub struct MultiClickData {
+    last_click_time: f32,
+    click_count: usize,
+    /// Pointer position of the first click
+    position: Vec2,
+}
+
+pub fn on_multi_click_set_selection(
+    click: On<Pointer<Click>>,
+    time: Res<Time>,
+    config: Res<TextInputConfig>,
+    mut text_input_nodes: Query<(
+        &TextInputNode,
+        &mut TextInputQueue,
+        &mut TextInputBuffer,
+        &mut TextInputCursorBlink,
+        &UiGlobalTransform,
+        &ComputedNode,
+        Option<&TextInputGutter>,
+    )>,
+    mut multi_click_datas: Query<&mut MultiClickData>,
+    mut text_input_pipeline: ResMut<TextInputPipeline>,
+    mut commands: Commands,
+) {
+    if click.button != PointerButton::Primary {
+        return;
+    }
+
+    let entity = click.entity;
+
+    let Ok((input, mut queue, mut buffer, mut blink, transform, node, gutter)) =
+        text_input_nodes.get_mut(entity)
+    else {
+        return;
+    };
+
+    if !input.is_enabled || !input.focus_on_pointer_down || !input.allow_selection {
+        return;
     }
 
     let now = time.elapsed_secs();
     if let Ok(mut multi_click_data) = multi_click_datas.get_mut(entity)
         && now - multi_click_data.last_click_time
             <= MULTI_CLICK_PERIOD * multi_click_data.click_count as f32
+        && click
+            .pointer_location
+            .position
+            .distance(multi_click_data.position)
+            <= config.multi_click_distance
     {
-        let rect = Rect::from_center_size(transform.translation, node.size());
-
-        let position =
-            click.pointer_location.position * node.inverse_scale_factor().recip() - rect.min;
+        let position = text_area_position(click.pointer_location.position, node, transform, gutter);
+        let scroll = buffer.horizontal_scroll();
         let mut editor = buffer
             .editor
             .borrow_with(&mut text_input_pipeline.font_system);
-        let scroll = editor.with_buffer(|buffer| buffer.scroll());
         match multi_click_data.click_count {
             1 => {
                 multi_click_data.click_count += 1;
                 multi_click_data.last_click_time = now;
 
                 queue.add(TextInputAction::Edit(TextInputEdit::DoubleClick {
-                    x: position.x as i32 + scroll.horizontal as i32,
-                    y: position.y as i32,
+                    x: (position.x + scroll).round() as i32,
+                    y: position.y.round() as i32,
                 }));
                 return;
             }
@@ -299,6 +1310,7 @@ pub fn on_multi_click_set_selection(
                 let cursor = editor.cursor();
                 editor.set_selection(Selection::Normal(cursor));
                 editor.action(Action::Motion(Motion::ParagraphEnd));
+                blink.reset();
                 if let Ok(mut entity) = commands.get_entity(entity) {
                     entity.try_remove::<MultiClickData>();
                 }
@@ -311,49 +1323,74 @@ pub fn on_multi_click_set_selection(
         entity.try_insert(MultiClickData {
             last_click_time: now,
             click_count: 1,
+            position: click.pointer_location.position,
         });
     }
 }
 
-pub fn on_move_clear_multi_click(move_: On<Pointer<Move>>, mut commands: Commands) {
-    if let Ok(mut entity) = commands.get_entity(move_.entity) {
+pub fn on_move_clear_multi_click(
+    move_: On<Pointer<Move>>,
+    config: Res<TextInputConfig>,
+    multi_click_datas: Query<&MultiClickData>,
+    mut commands: Commands,
+) {
+    let Ok(multi_click_data) = multi_click_datas.get(move_.entity) else {
+        return;
+    };
+    let moved = move_
+        .pointer_location
+        .position
+        .distance(multi_click_data.position);
+    if config.multi_click_distance < moved
+        && let Ok(mut entity) = commands.get_entity(move_.entity)
+    {
         entity.try_remove::<MultiClickData>();
     }
 }
 
+/// Tracks the modifier keys in `TextInputGlobalState` from all keyboard input, so that their state is
+/// correct when a text input is focused while a modifier is held down.
+pub fn track_modifier_keys_system(
+    mut keyboard_reader: MessageReader<KeyboardInput>,
+    mut global_state: ResMut<TextInputGlobalState>,
+) {
+    for keyboard_input in keyboard_reader.read() {
+        let pressed = keyboard_input.state == ButtonState::Pressed;
+        match keyboard_input.logical_key {
+            Key::Shift => global_state.shift = pressed,
+            Key::Control => global_state.command = pressed,
+            #[cfg(target_os = "macos")]
+            Key::Super => global_state.command = pressed,
+            Key::Alt => global_state.alt = pressed,
+            _ => {}
+        }
+    }
+}
+
+/// Queues the actions for a key press on a focused text input. Modifier keys are read from
+/// `modifiers`, which is kept up to date by `track_modifier_keys_system`.
 pub fn queue_text_input_action(
-    input_mode: &TextInputMode,
+    input: &TextInputNode,
     input_buffer: &TextInputBuffer,
-    shift_pressed: &mut bool,
+    modifiers: &TextInputGlobalState,
     overwrite_mode: &mut bool,
-    command_pressed: &mut bool,
     keyboard_input: &KeyboardInput,
     mut queue: impl FnMut(TextInputAction),
 ) {
-    match keyboard_input.logical_key {
-        Key::Shift => {
-            *shift_pressed = keyboard_input.state == ButtonState::Pressed;
-            return;
-        }
-        Key::Control => {
-            *command_pressed = keyboard_input.state == ButtonState::Pressed;
-            return;
-        }
-        #[cfg(target_os = "macos")]
-        Key::Super => {
-            *command_pressed = keyboard_input.state == ButtonState::Pressed;
-            return;
-        }
-        _ => {}
-    };
+    let TextInputGlobalState {
+        shift: shift_pressed,
+        command: command_pressed,
+        alt: alt_pressed,
+        ..
+    } = *modifiers;
 
     if keyboard_input.state.is_pressed() {
-        if *command_pressed {
+        if command_pressed {
             match &keyboard_input.logical_key {
                 Key::Character(str) => {
                     if let Some(char) = str.chars().next() {
                         // convert to lowercase so that the commands work with capslock on
-                        match (char.to_ascii_lowercase(), *shift_pressed) {
+                        match (char.to_ascii_lowercase(), shift_pressed) {
                             ('c', false) => {
                                 // copy
                                 queue(TextInputAction::Copy);
@@ -380,6 +1417,17 @@ pub fn queue_text_input_action(
                                 // select all
                                 queue(TextInputAction::Edit(TextInputEdit::SelectAll));
                             }
+                            ('t', false) => {
+                                queue(TextInputAction::Edit(TextInputEdit::TransposeChars));
+                            }
+                            ('k', true) => {
+                                queue(TextInputAction::Edit(TextInputEdit::DeleteLine));
+                            }
+                            ('d', true) => {
+                                if matches!(input.mode, TextInputMode::MultiLine { .. }) {
+                                    queue(TextInputAction::Edit(TextInputEdit::DuplicateLine));
+                                }
+                            }
                             _ => {
                                 // not recognised, ignore
                             }
@@ -389,47 +1437,88 @@ pub fn queue_text_input_action(
                 Key::ArrowLeft => {
                     queue(TextInputAction::Edit(TextInputEdit::Motion(
                         Motion::PreviousWord,
-                        *shift_pressed,
+                        shift_pressed,
                     )));
                 }
                 Key::ArrowRight => {
                     queue(TextInputAction::Edit(TextInputEdit::Motion(
                         Motion::NextWord,
-                        *shift_pressed,
+                        shift_pressed,
                     )));
                 }
                 Key::ArrowUp => {
-                    if matches!(input_mode, TextInputMode::MultiLine { .. }) {
-                        let line_height = input_buffer
-                            .editor
-                            .with_buffer(|buffer| buffer.metrics().line_height);
+                    if matches!(input.mode, TextInputMode::MultiLine { .. }) {
+                        if shift_pressed {
+                            // Extend the selection to the previous paragraph start, skipping back over
+                            // the line break if the cursor is already at the start of its paragraph
+                            if input_buffer.editor.cursor().index == 0 {
+                                queue(TextInputAction::Edit(TextInputEdit::Motion(
+                                    Motion::Left,
+                                    true,
+                                )));
+                            }
+                            queue(TextInputAction::Edit(TextInputEdit::Motion(
+                                Motion::ParagraphStart,
+                                true,
+                            )));
+                        } else {
+                            let line_height = input_buffer.line_height();
 
-                        queue(TextInputAction::Edit(TextInputEdit::Scroll {
-                            pixels: -line_height,
-                        }));
+                            queue(TextInputAction::Edit(TextInputEdit::Scroll {
+                                pixels: -line_height,
+                            }));
+                        }
                     }
                 }
                 Key::ArrowDown => {
-                    if matches!(input_mode, TextInputMode::MultiLine { .. }) {
-                        let line_height = input_buffer
-                            .editor
-                            .with_buffer(|buffer| buffer.metrics().line_height);
+                    if matches!(input.mode, TextInputMode::MultiLine { .. }) {
+                        if shift_pressed {
+                            // Extend the selection to the next paragraph end, skipping over the
+                            // line break if the cursor is already at the end of its paragraph
+                            let cursor = input_buffer.editor.cursor();
+                            if input_buffer.editor.with_buffer(|buffer| {
+                                buffer
+                                    .lines
+                                    .get(cursor.line)
+                                    .is_some_and(|line| cursor.index == line.text().len())
+                            }) {
+                                queue(TextInputAction::Edit(TextInputEdit::Motion(
+                                    Motion::Right,
+                                    true,
+                                )));
+                            }
+                            queue(TextInputAction::Edit(TextInputEdit::Motion(
+                                Motion::ParagraphEnd,
+                                true,
+                            )));
+                        } else {
+                            let line_height = input_buffer.line_height();
 
-                        queue(TextInputAction::Edit(TextInputEdit::Scroll {
-                            pixels: line_height,
-                        }));
+                            queue(TextInputAction::Edit(TextInputEdit::Scroll {
+                                pixels: line_height,
+                            }));
+                        }
                     }
                 }
+                // Shift + Enter inserts soft breaks, so they move a Shift submit to Ctrl
+                Key::Enter
+                    if matches!(input.mode, TextInputMode::MultiLine { .. })
+                        && (input.submit_modifier == SubmitModifier::Ctrl
+                            || (input.soft_breaks
+                                && input.submit_modifier == SubmitModifier::Shift)) =>
+                {
+                    queue(TextInputAction::Submit);
+                }
                 Key::Home => {
                     queue(TextInputAction::Edit(TextInputEdit::Motion(
                         Motion::BufferStart,
-                        *shift_pressed,
+                        shift_pressed,
                     )));
                 }
                 Key::End => {
                     queue(TextInputAction::Edit(TextInputEdit::Motion(
                         Motion::BufferEnd,
-                        *shift_pressed,
+                        shift_pressed,
                     )));
                 }
                 _ => {
@@ -451,8 +1540,15 @@ pub fn queue_text_input_action(
                         )));
                     }
                 }
-                Key::Enter => match (*shift_pressed, input_mode) {
-                    (false, TextInputMode::MultiLine { .. }) => {
+                Key::Enter => match input.mode {
+                    TextInputMode::MultiLine { .. } if input.soft_breaks && shift_pressed => {
+                        queue(TextInputAction::Edit(TextInputEdit::SoftBreak));
+                    }
+                    TextInputMode::MultiLine { .. }
+                        if input.soft_breaks
+                            || !(shift_pressed
+                                && input.submit_modifier == SubmitModifier::Shift) =>
+                    {
                         queue(TextInputAction::Edit(TextInputEdit::Enter));
                     }
                     _ => {
@@ -463,7 +1559,7 @@ pub fn queue_text_input_action(
                     queue(TextInputAction::Edit(TextInputEdit::Backspace));
                 }
                 Key::Delete => {
-                    if *shift_pressed {
+                    if shift_pressed {
                         queue(TextInputAction::Cut);
                     } else {
                         queue(TextInputAction::Edit(TextInputEdit::Delete));
@@ -472,67 +1568,82 @@ pub fn queue_text_input_action(
                 Key::PageUp => {
                     queue(TextInputAction::Edit(TextInputEdit::Motion(
                         Motion::PageUp,
-                        *shift_pressed,
+                        shift_pressed,
                     )));
                 }
                 Key::PageDown => {
                     queue(TextInputAction::Edit(TextInputEdit::Motion(
                         Motion::PageDown,
-                        *shift_pressed,
+                        shift_pressed,
                     )));
                 }
                 Key::ArrowLeft => {
                     queue(TextInputAction::Edit(TextInputEdit::Motion(
                         Motion::Left,
-                        *shift_pressed,
+                        shift_pressed,
                     )));
                 }
                 Key::ArrowRight => {
                     queue(TextInputAction::Edit(TextInputEdit::Motion(
                         Motion::Right,
-                        *shift_pressed,
+                        shift_pressed,
                     )));
                 }
                 Key::ArrowUp => {
-                    queue(TextInputAction::Edit(TextInputEdit::Motion(
-                        Motion::Up,
-                        *shift_pressed,
-                    )));
+                    if alt_pressed && matches!(input.mode, TextInputMode::MultiLine { .. }) {
+                        if !shift_pressed {
+                            queue(TextInputAction::Edit(TextInputEdit::MoveLineUp));
+                        }
+                    } else {
+                        queue(TextInputAction::Edit(TextInputEdit::Motion(
+                            Motion::Up,
+                            shift_pressed,
+                        )));
+                    }
                 }
                 Key::ArrowDown => {
-                    queue(TextInputAction::Edit(TextInputEdit::Motion(
-                        Motion::Down,
-                        *shift_pressed,
-                    )));
+                    if alt_pressed && matches!(input.mode, TextInputMode::MultiLine { .. }) {
+                        if shift_pressed {
+                            queue(TextInputAction::Edit(TextInputEdit::DuplicateLine));
+                        } else {
+                            queue(TextInputAction::Edit(TextInputEdit::MoveLineDown));
+                        }
+                    } else {
+                        queue(TextInputAction::Edit(TextInputEdit::Motion(
+                            Motion::Down,
+                            shift_pressed,
+                        )));
+                    }
                 }
                 Key::Home => {
                     queue(TextInputAction::Edit(TextInputEdit::Motion(
                         Motion::Home,
-                        *shift_pressed,
+                        shift_pressed,
                     )));
                 }
                 Key::End => {
                     queue(TextInputAction::Edit(TextInputEdit::Motion(
                         Motion::End,
-                        *shift_pressed,
+                        shift_pressed,
                     )));
                 }
                 Key::Escape => {
                     queue(TextInputAction::Edit(TextInputEdit::Escape));
+                    if input.blur_on_escape {
+                        queue(TextInputAction::Blur);
+                    }
                 }
                 Key::Tab => {
-                    if matches!(input_mode, TextInputMode::MultiLine { .. }) {
-                        if *shift_pressed {
+                    if matches!(input.mode, TextInputMode::MultiLine { .. }) {
+                        if shift_pressed {
                             queue(TextInputAction::Edit(TextInputEdit::Unindent));
                         } else {
                             queue(TextInputAction::Edit(TextInputEdit::Indent));
                         }
                     }
                 }
-                Key::Insert => {
-                    if !*shift_pressed {
-                        *overwrite_mode = !*overwrite_mode;
-                    }
+                Key::Insert if !shift_pressed => {
+                    *overwrite_mode = !*overwrite_mode;
                 }
                 _ => {}
             }
@@ -542,119 +1653,2667 @@ pub fn queue_text_input_action(
 
 /// updates the cursor blink time for text inputs
 pub fn cursor_blink_system(
-    mut query: Query<(&mut TextInputBuffer, &TextInputStyle, &TextInputQueue)>,
+    mut query: Query<(
+        &mut TextInputCursorBlink,
+        &TextInputNode,
+        &TextInputStyle,
+        &TextInputQueue,
+    )>,
+    config: Res<TextInputConfig>,
     time: Res<Time>,
+    real_time: Res<Time<Real>>,
 ) {
-    for (mut buffer, style, queue) in query.iter_mut() {
-        buffer.cursor_blink_time = if queue.is_empty() {
-            (buffer.cursor_blink_time + time.delta_secs()).rem_euclid(style.blink_interval * 2.)
+    let delta_secs = match config.blink_clock {
+        BlinkClock::Virtual => time.delta_secs(),
+        BlinkClock::Real => real_time.delta_secs(),
+    };
+    for (mut blink, input, style, queue) in query.iter_mut() {
+        let blink_interval = style.blink_interval.unwrap_or(config.blink_interval);
+        if queue.is_empty() {
+            blink.time_since_input =
+                (blink.time_since_input + delta_secs).min(input.blink_pause_while_typing);
+        } else {
+            blink.time_since_input = 0.;
+        }
+        blink.time = if queue.is_empty() && input.blink_pause_while_typing <= blink.time_since_input
+        {
+            (blink.time + delta_secs).rem_euclid(blink_interval * 2.)
         } else {
             0.
         };
     }
 }
 
-pub fn process_text_input_queues(
+/// Sends a `CaretMoved` message for each text input whose cursor moved since the last frame,
+/// and restarts its cursor blink.
+pub fn caret_moved_system(
+    mut query: Query<(Entity, &mut TextInputBuffer, &mut TextInputCursorBlink)>,
+    mut caret_moved_writer: MessageWriter<CaretMoved>,
+) {
+    for (entity, mut buffer, mut blink) in query.iter_mut() {
+        let cursor = buffer.editor.cursor();
+        let last_cursor = buffer.last_cursor;
+        if (cursor.line, cursor.index) == (last_cursor.line, last_cursor.index) {
+            continue;
+        }
+        buffer.last_cursor = cursor;
+        // Show the cursor immediately after it moves, including programmatic moves
+        blink.reset();
+
+        let (column, char_offset) = buffer.editor.with_buffer(|buffer| {
+            let column = buffer
+                .lines
+                .get(cursor.line)
+                .map(|line| line.text()[..cursor.index].chars().count())
+                .unwrap_or(0);
+            let preceding = buffer
+                .lines
+                .iter()
+                .take(cursor.line)
+                .map(|line| line.text().chars().count() + 1)
+                .sum::<usize>();
+            (column, preceding + column)
+        });
+
+        caret_moved_writer.write(CaretMoved {
+            entity,
+            line: cursor.line,
+            column,
+            char_offset,
+        });
+    }
+}
+
+/// Updates the `CaretRect` of text inputs from their layout, placing the cursor as it's drawn
+/// without its caret animation.
+pub fn caret_rect_system(
+    input_focus: Res<InputFocus>,
     mut query: Query<(
         Entity,
+        &mut CaretRect,
+        &ComputedNode,
+        &UiGlobalTransform,
         &TextInputNode,
+        &TextInputBuffer,
+        &TextInputStyle,
+        Option<&TextInputGutter>,
+    )>,
+) {
+    for (entity, mut caret_rect, uinode, global_transform, input, buffer, style, gutter) in
+        query.iter_mut()
+    {
+        let cursor_position = buffer
+            .drawn_cursor_position()
+            .filter(|_| input.is_enabled && is_focused(entity, &input_focus));
+        let rect = cursor_position.map(|(x, y)| {
+            let scale_factor = uinode.inverse_scale_factor().recip();
+            let inset = TextInputGutter::inset(gutter, scale_factor);
+            let scroll = if input.mode.wrap() == Wrap::None {
+                Vec2::new(buffer.horizontal_scroll(), 0.)
+            } else {
+                Vec2::ZERO
+            };
+            let text_width = buffer
+                .editor
+                .with_buffer(|buffer| buffer.size().0)
+                .unwrap_or(uinode.size().x - inset);
+            let line_height = buffer.line_height();
+            let width = style.cursor_width * scale_factor;
+            let size = Vec2::new(width, line_height * style.cursor_height);
+            // Kept inside the text area like the drawn cursor
+            let x = (x as f32).min(scroll.x + text_width - width).max(scroll.x);
+            let center = -0.5 * uinode.size() - scroll
+                + Vec2::new(inset + x + 0.5 * width, y as f32 + 0.5 * line_height)
+                + style.cursor_offset * scale_factor;
+            // Bounds of the transformed corners, in case the node is rotated
+            let rect = [
+                Vec2::NEG_ONE,
+                Vec2::new(1., -1.),
+                Vec2::new(-1., 1.),
+                Vec2::ONE,
+            ]
+            .into_iter()
+            .map(|corner| global_transform.transform_point2(center + 0.5 * corner * size))
+            .fold(Rect::EMPTY, |rect, point| rect.union_point(point));
+            Rect {
+                min: rect.min * uinode.inverse_scale_factor(),
+                max: rect.max * uinode.inverse_scale_factor(),
+            }
+        });
+        if caret_rect.0 != rect {
+            caret_rect.0 = rect;
+        }
+    }
+}
+
+/// Triggers `OnBeforeEdit` for each queued edit of the text inputs with `InterceptEdits`,
+/// removing the edits cancelled by observers from their queues.
+pub fn intercept_text_input_edits(world: &mut World) {
+    let entities: Vec<Entity> = world
+        .query_filtered::<Entity, (With<InterceptEdits>, With<TextInputQueue>)>()
+        .iter(world)
+        .collect();
+
+    for entity in entities {
+        let Some(mut queue) = world.get_mut::<TextInputQueue>(entity) else {
+            continue;
+        };
+        let actions = core::mem::take(&mut queue.actions);
+        let mut kept = VecDeque::with_capacity(actions.len());
+        for action in actions {
+            match action {
+                TextInputAction::Edit(edit) => {
+                    let mut event = OnBeforeEdit {
+                        entity,
+                        edit,
+                        cancel: false,
+                    };
+                    world.trigger_ref(&mut event);
+                    if !event.cancel {
+                        kept.push_back(TextInputAction::Edit(event.edit));
+                    }
+                }
+                action => kept.push_back(action),
+            }
+        }
+
+        if let Some(mut queue) = world.get_mut::<TextInputQueue>(entity) {
+            // Keep any actions queued by the observers after the intercepted ones
+            kept.append(&mut queue.actions);
+            queue.actions = kept;
+        }
+    }
+}
+
+/// Copied text formatted by the input's `CopyFormatter`, if it has one
+fn format_copied_text(formatter: Option<&CopyFormatter>, text: String) -> String {
+    match formatter {
+        Some(formatter) => formatter.format(&text),
+        None => text,
+    }
+}
+
+pub fn process_text_input_queues(
+    mut query: Query<(
+        Entity,
+        &mut TextInputNode,
         &mut TextInputBuffer,
         &mut TextInputQueue,
         Option<&TextInputFilter>,
+        Option<&TextInputCompletion>,
+        Has<MirrorTextInput>,
+        Option<&mut TextInputRecorder>,
+        Option<&TextInputMotionMask>,
+        Option<&CopyFormatter>,
     )>,
     mut text_input_pipeline: ResMut<TextInputPipeline>,
     mut submit_writer: MessageWriter<SubmitText>,
+    mut paste_failed_writer: MessageWriter<PasteFailed>,
+    mut paste_too_large_writer: MessageWriter<PasteTooLarge>,
+    mut rejected_writer: MessageWriter<TextInputRejected>,
+    mut completion_writer: MessageWriter<CompletionCandidates>,
     mut clipboard: ResMut<Clipboard>,
+    mut input_focus: ResMut<InputFocus>,
+    time: Res<Time>,
+    #[cfg(feature = "regex")] regex_query: Query<&TextInputRegex>,
 ) {
     let font_system = &mut text_input_pipeline.font_system;
 
-    for (entity, node, mut buffer, mut actions_queue, maybe_filter) in query.iter_mut() {
+    for (
+        entity,
+        mut node,
+        mut buffer,
+        mut actions_queue,
+        maybe_filter,
+        completion,
+        is_mirror,
+        mut recorder,
+        motion_mask,
+        copy_formatter,
+    ) in query.iter_mut()
+    {
+        if actions_queue.is_empty() {
+            continue;
+        }
+
         let TextInputBuffer {
-            editor, changes, ..
+            editor,
+            changes,
+            preedit,
+            vertical_motion_cursor,
+            mask,
+            ..
         } = &mut *buffer;
         let mut editor = editor.borrow_with(font_system);
+        let max_chars = node.max_chars;
+        let secure = node.is_secure();
+        let allowed_control_chars = node.sanitize.then_some(&node.allowed_control_chars);
+        #[cfg(feature = "regex")]
+        let regex = regex_query.get(entity).ok();
+        #[cfg(feature = "regex")]
+        let keystroke_regex = regex
+            .filter(|regex| regex.mode == RegexValidateMode::EachKeystroke)
+            .map(|regex| |text: &str| regex.pattern.is_match(text));
+        #[cfg(not(feature = "regex"))]
+        let keystroke_regex: Option<fn(&str) -> bool> = None;
+        let time = time.elapsed_secs_f64();
+        // Sanitizes and applies an edit, and records it if it wasn't rejected and the input has a
+        // `TextInputRecorder`. Secure inputs keep no history of their edits.
+        let mut apply =
+            |edit: TextInputEdit, editor: &mut BorrowedWithFontSystem<Editor<'static>>| {
+                let edit = match (edit, allowed_control_chars) {
+                    (TextInputEdit::Paste(text), Some(allowed)) => {
+                        TextInputEdit::Paste(sanitize_text(normalize_newlines(text), allowed))
+                    }
+                    (TextInputEdit::Insert(ch, _), Some(allowed))
+                        if is_disallowed_char(ch) && !allowed.contains(&ch) =>
+                    {
+                        return None;
+                    }
+                    (edit, _) => edit,
+                };
+                // Pointer edits on masked inputs hit the drawn mask, not the text's own layout
+                if let Some(mask) = mask.as_ref()
+                    && mask.apply_pointer_edit(editor, &edit)
+                {
+                    *vertical_motion_cursor = None;
+                    return None;
+                }
+                let recorded = (recorder.is_some() && !secure).then(|| edit.clone());
+                let rejection = apply_edit_with_goal_column(
+                    edit,
+                    editor,
+                    changes,
+                    vertical_motion_cursor,
+                    max_chars,
+                    maybe_filter,
+                    keystroke_regex
+                        .as_ref()
+                        .map(|validate| validate as &dyn Fn(&str) -> bool),
+                );
+                if rejection.is_none()
+                    && let Some(recorder) = recorder.as_mut()
+                    && let Some(edit) = recorded
+                {
+                    recorder.record(time, edit);
+                }
+                if secure {
+                    *changes = cosmic_undo_2::Commands::default();
+                }
+                rejection
+            };
         while let Some(action) = actions_queue.next() {
+            if let TextInputAction::Edit(TextInputEdit::Motion(motion, _)) = action
+                && motion_mask.is_some_and(|mask| !mask.allows(motion))
+            {
+                continue;
+            }
+            if is_mirror && action.modifies_text() {
+                rejected_writer.write(TextInputRejected {
+                    entity,
+                    reason: TextInputRejection::ReadOnly,
+                });
+                continue;
+            }
             match action {
-                TextInputAction::Submit => {
-                    let text = editor.with_buffer(crate::get_text);
+                action @ (TextInputAction::Submit | TextInputAction::SubmitAndBlur) => {
+                    if let Some(preedit) = preedit.take()
+                        && !node.discard_preedit_on_submit
+                    {
+                        apply(TextInputEdit::Paste(preedit), &mut editor);
+                    }
+                    #[cfg(feature = "regex")]
+                    if let Some(regex) = regex
+                        && regex.mode == RegexValidateMode::OnSubmit
+                        && !regex.pattern.is_match(&editor.with_buffer(crate::get_text))
+                    {
+                        rejected_writer.write(TextInputRejected {
+                            entity,
+                            reason: TextInputRejection::Filter,
+                        });
+                        continue;
+                    }
+                    let text = node
+                        .output_newline
+                        .apply(editor.with_buffer(crate::get_text));
+                    if !node.submit_empty && text.trim().is_empty() {
+                        continue;
+                    }
                     submit_writer.write(SubmitText { entity, text });
                     if node.clear_on_submit {
-                        actions_queue.add_front(TextInputAction::Edit(TextInputEdit::Delete));
-                        actions_queue.add_front(TextInputAction::Edit(TextInputEdit::SelectAll));
+                        for edit in [TextInputEdit::SelectAll, TextInputEdit::Delete] {
+                            apply(edit, &mut editor);
+                        }
+                    }
+                    if matches!(action, TextInputAction::SubmitAndBlur)
+                        && is_focused(entity, &input_focus)
+                    {
+                        input_focus.clear();
                     }
                 }
                 TextInputAction::Cut => {
-                    if let Some(text) = editor.copy_selection() {
-                        let _ = clipboard.set_text(text);
-                        apply_text_input_edit(
-                            TextInputEdit::Delete,
-                            &mut editor,
-                            changes,
-                            node.max_chars,
-                            maybe_filter,
+                    if node.is_secure() {
+                        continue;
+                    }
+                    if node.allow_selection {
+                        if let Some(text) = editor.copy_selection() {
+                            let _ = clipboard.set_text(
+                                node.output_newline
+                                    .apply(format_copied_text(copy_formatter, text)),
+                            );
+                            apply(TextInputEdit::Delete, &mut editor);
+                        }
+                    } else if node.copy_whole_value {
+                        let text = editor.with_buffer(crate::get_text);
+                        let _ = clipboard.set_text(
+                            node.output_newline
+                                .apply(format_copied_text(copy_formatter, text)),
                         );
+                        for edit in [TextInputEdit::SelectAll, TextInputEdit::Delete] {
+                            apply(edit, &mut editor);
+                        }
                     }
                 }
                 TextInputAction::Copy => {
-                    if let Some(text) = editor.copy_selection() {
-                        let _ = clipboard.set_text(text);
+                    if node.is_secure() {
+                        continue;
+                    }
+                    if node.allow_selection {
+                        if let Some(text) = editor.copy_selection() {
+                            let _ = clipboard.set_text(
+                                node.output_newline
+                                    .apply(format_copied_text(copy_formatter, text)),
+                            );
+                        }
+                    } else if node.copy_whole_value {
+                        let text = editor.with_buffer(crate::get_text);
+                        let _ = clipboard.set_text(
+                            node.output_newline
+                                .apply(format_copied_text(copy_formatter, text)),
+                        );
                     }
                 }
                 TextInputAction::Paste => {
                     actions_queue.add_front(TextInputAction::PasteDeferred(clipboard.fetch_text()));
                 }
-                TextInputAction::PasteDeferred(mut clipboard_read) => {
-                    if let Some(text) = clipboard_read.poll_result() {
-                        if let Ok(text) = text {
-                            apply_text_input_edit(
-                                TextInputEdit::Paste(text),
-                                &mut editor,
-                                changes,
-                                node.max_chars,
-                                maybe_filter,
+                TextInputAction::PastePrimary => {
+                    actions_queue.add_front(TextInputAction::PasteDeferred(
+                        clipboard.fetch_primary_text(),
+                    ));
+                }
+                TextInputAction::PasteDeferred(mut clipboard_read) => match clipboard_read.poll() {
+                    ClipboardReadStatus::Text(mut text) => {
+                        if let Some(max) = node.max_paste_chars
+                            && let Some((end, _)) = text.char_indices().nth(max)
+                        {
+                            paste_too_large_writer.write(PasteTooLarge {
+                                entity,
+                                size: max + text[end..].chars().count(),
+                            });
+                            text.truncate(end);
+                        }
+                        if node.paste_indentation != PasteIndentation::Keep || node.reindent_paste {
+                            let cursor = editor.cursor();
+                            // The buffer has no lines until the text input is first laid out
+                            let line_indent = editor.with_buffer(|buffer| {
+                                buffer
+                                    .lines
+                                    .get(cursor.line)
+                                    .map_or("", |line| leading_whitespace(line.text()))
+                                    .to_string()
+                            });
+                            text = normalize_pasted_text(
+                                &text,
+                                node.paste_indentation,
+                                node.reindent_paste.then_some(line_indent.as_str()),
                             );
                         }
-                    } else {
+                        if let Some(max_chars) = node.max_chars
+                            && node.paste_overflow == PasteOverflow::Truncate
+                        {
+                            // Sanitize first so removed characters don't use up the space left
+                            if let Some(allowed) = allowed_control_chars {
+                                text = sanitize_text(normalize_newlines(text), allowed);
+                            }
+                            truncate_paste(&editor, &mut text, max_chars);
+                        }
+                        if let Some(reason) = apply(TextInputEdit::Paste(text), &mut editor) {
+                            rejected_writer.write(TextInputRejected { entity, reason });
+                        }
+                    }
+                    ClipboardReadStatus::NoText => {
+                        paste_failed_writer.write(PasteFailed {
+                            entity,
+                            reason: PasteFailure::NoText,
+                        });
+                    }
+                    ClipboardReadStatus::Error(error) => {
+                        paste_failed_writer.write(PasteFailed {
+                            entity,
+                            reason: PasteFailure::Error(error),
+                        });
+                    }
+                    ClipboardReadStatus::Pending => {
                         // Add the clipboard read back to the queue, process it and the remaining actions next frame.
                         actions_queue.add_front(TextInputAction::PasteDeferred(clipboard_read));
                         break;
                     }
+                },
+                TextInputAction::Complete => {
+                    let Some(completion) = completion else {
+                        continue;
+                    };
+                    let (text, cursor_offset) = editor.with_buffer(|buffer| {
+                        (
+                            crate::get_text(buffer),
+                            char_offset_at_cursor(buffer, editor.cursor()),
+                        )
+                    });
+                    let mut candidates = completion.candidates(&text, cursor_offset);
+                    if 1 < candidates.len() {
+                        completion_writer.write(CompletionCandidates { entity, candidates });
+                    } else if let Some(candidate) = candidates.pop()
+                        && let Some(reason) = apply(TextInputEdit::Paste(candidate), &mut editor)
+                    {
+                        rejected_writer.write(TextInputRejected { entity, reason });
+                    }
                 }
-                TextInputAction::Edit(text_input_edit) => {
-                    apply_text_input_edit(
-                        text_input_edit,
-                        &mut editor,
-                        changes,
-                        node.max_chars,
-                        maybe_filter,
-                    );
+                TextInputAction::Focus => {
+                    if node.is_enabled && !is_focused(entity, &input_focus) {
+                        input_focus.set(entity);
+                    }
                 }
-            }
-        }
-    }
-}
-
-pub fn on_focused_keyboard_input(
-    trigger: On<FocusedInput<KeyboardInput>>,
-    mut query: Query<(&TextInputBuffer, &TextInputNode, &mut TextInputQueue)>,
-    mut global_state: ResMut<TextInputGlobalState>,
+                TextInputAction::Blur => {
+                    if is_focused(entity, &input_focus) {
+                        input_focus.clear();
+                    }
+                }
+                TextInputAction::Edit(text_input_edit) => {
+                    let text_input_edit = if node.allow_selection {
+                        text_input_edit
+                    } else {
+                        match text_input_edit {
+                            TextInputEdit::Motion(motion, _) => {
+                                TextInputEdit::Motion(motion, false)
+                            }
+                            TextInputEdit::DoubleClick { x, y }
+                            | TextInputEdit::TripleClick { x, y } => TextInputEdit::Click { x, y },
+                            TextInputEdit::Drag { .. } | TextInputEdit::SelectAll => continue,
+                            text_input_edit => text_input_edit,
+                        }
+                    };
+                    let text_input_edit = match text_input_edit {
+                        TextInputEdit::Paste(mut text)
+                            if node.paste_overflow == PasteOverflow::Truncate =>
+                        {
+                            if let Some(max_chars) = node.max_chars {
+                                if let Some(allowed) = allowed_control_chars {
+                                    text = sanitize_text(normalize_newlines(text), allowed);
+                                }
+                                truncate_paste(&editor, &mut text, max_chars);
+                            }
+                            TextInputEdit::Paste(text)
+                        }
+                        text_input_edit => text_input_edit,
+                    };
+                    if let Some(reason) = apply(text_input_edit, &mut editor) {
+                        rejected_writer.write(TextInputRejected { entity, reason });
+                    }
+                }
+            }
+        }
+
+        if node.promote_on_newline
+            && node.mode == TextInputMode::SingleLine
+            && 1 < editor.with_buffer(|buffer| buffer.lines.len())
+        {
+            node.mode = TextInputMode::default();
+        }
+    }
+}
+
+pub fn on_focused_keyboard_input(
+    mut trigger: On<FocusedInput<KeyboardInput>>,
+    mut query: Query<(
+        &mut TextInputBuffer,
+        &TextInputNode,
+        &mut TextInputQueue,
+        Has<TextInputCompletion>,
+    )>,
+    global_state: Res<TextInputGlobalState>,
+    config: Res<TextInputConfig>,
 ) {
-    if let Ok((buffer, input, mut queue)) = query.get_mut(trigger.focused_entity) {
-        let TextInputGlobalState {
-            shift,
-            overwrite_mode,
-            command,
-        } = &mut *global_state;
+    if let Ok((mut buffer, input, mut queue, has_completion)) =
+        query.get_mut(trigger.focused_entity)
+    {
+        let TextInputGlobalState { shift, command, .. } = *global_state;
+        let keyboard_input = &trigger.event().input;
+        if keyboard_input.state.is_pressed()
+            && let Some(action) = config
+                .keymap
+                .action(keyboard_input.key_code, command, shift)
+        {
+            queue.add(action);
+            trigger.propagate(false);
+            return;
+        }
+        if has_completion
+            && input.mode == TextInputMode::SingleLine
+            && keyboard_input.state.is_pressed()
+            && keyboard_input.logical_key == Key::Tab
+            && !shift
+            && !command
+        {
+            queue.add(TextInputAction::Complete);
+            trigger.propagate(false);
+            return;
+        }
+        if input.tab_commits
+            && input.mode == TextInputMode::SingleLine
+            && keyboard_input.state.is_pressed()
+            && keyboard_input.logical_key == Key::Tab
+            && !command
+        {
+            queue.add(TextInputAction::SubmitAndBlur);
+            trigger.propagate(false);
+            return;
+        }
+        let mut overwrite_mode = buffer.overwrite_mode && input.allow_overwrite_mode;
         queue_text_input_action(
-            &input.mode,
-            buffer,
-            shift,
-            overwrite_mode,
-            command,
+            input,
+            &buffer,
+            &global_state,
+            &mut overwrite_mode,
             &trigger.event().input,
             |action| {
                 queue.add(action);
             },
         );
+        if input.allow_overwrite_mode && overwrite_mode != buffer.overwrite_mode {
+            buffer.overwrite_mode = overwrite_mode;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clipboard::{ClipboardBackend, ClipboardError, ClipboardRead};
+    use crate::tests::font_system;
+    use bevy::ecs::change_detection::Mut;
+    use bevy::ecs::message::Messages;
+    use bevy::ecs::system::RunSystemOnce;
+    use bevy::input::keyboard::KeyCode;
+
+    #[derive(Default)]
+    struct TestClipboard(String);
+
+    impl ClipboardBackend for TestClipboard {
+        fn get_text(&mut self) -> Result<String, ClipboardError> {
+            Ok(self.0.clone())
+        }
+
+        fn set_text(&mut self, text: String) -> Result<(), ClipboardError> {
+            self.0 = text;
+            Ok(())
+        }
+    }
+
+    /// A world with the resources `process_text_input_queues` needs
+    fn queue_world() -> World {
+        let mut world = World::new();
+        world.init_resource::<InputFocus>();
+        world.insert_resource(TextInputPipeline {
+            font_system: font_system(),
+            ..Default::default()
+        });
+        world.insert_resource(Clipboard::with_backend(Box::new(TestClipboard::default())));
+        world.init_resource::<Messages<SubmitText>>();
+        world.init_resource::<Messages<PasteFailed>>();
+        world.init_resource::<Messages<PasteTooLarge>>();
+        world.init_resource::<Messages<TextInputRejected>>();
+        world.init_resource::<Messages<CompletionCandidates>>();
+        world.init_resource::<Messages<crate::TextChanged>>();
+        world.init_resource::<Time>();
+        world.init_resource::<TextInputConfig>();
+        world
+    }
+
+    fn press(logical_key: Key, key_code: KeyCode) -> KeyboardInput {
+        KeyboardInput {
+            key_code,
+            logical_key,
+            state: ButtonState::Pressed,
+            text: None,
+            repeat: false,
+            window: Entity::PLACEHOLDER,
+        }
+    }
+
+    /// Spawns a text input with `ManualInput` holding `text`, with the cursor at its end
+    fn spawn_input(world: &mut World, input: TextInputNode, text: &str) -> Entity {
+        let entity = world.spawn((input, ManualInput)).id();
+        world.flush();
+        world
+            .get_mut::<TextInputBuffer>(entity)
+            .unwrap()
+            .set_text(text);
+        entity
+    }
+
+    /// Queues `actions` on the input and processes them
+    fn process(
+        world: &mut World,
+        entity: Entity,
+        actions: impl IntoIterator<Item = TextInputAction>,
+    ) {
+        let mut queue = world.get_mut::<TextInputQueue>(entity).unwrap();
+        for action in actions {
+            queue.add(action);
+        }
+        world.run_system_once(process_text_input_queues).unwrap();
+    }
+
+    fn text(world: &World, entity: Entity) -> String {
+        world.get::<TextInputBuffer>(entity).unwrap().get_text()
+    }
+
+    /// Lays out the input's text in a 500x100 area, so that pointer positions hit its glyphs.
+    /// Returns the width of the first glyph.
+    fn layout(world: &mut World, entity: Entity) -> f32 {
+        world.resource_scope(|world, mut pipeline: Mut<TextInputPipeline>| {
+            let mut buffer = world.get_mut::<TextInputBuffer>(entity).unwrap();
+            buffer.editor.with_buffer_mut(|buffer| {
+                buffer.set_size(&mut pipeline.font_system, Some(500.), Some(100.));
+                buffer.shape_until_scroll(&mut pipeline.font_system, false);
+                buffer.layout_runs().next().unwrap().glyphs[0].w
+            })
+        })
+    }
+
+    /// A pointer event on `entity`, at `position` relative to its text area
+    fn pointer<E: Clone + core::fmt::Debug + bevy::reflect::Reflect>(
+        entity: Entity,
+        position: Vec2,
+        event: E,
+    ) -> Pointer<E> {
+        let location = bevy::picking::pointer::Location {
+            target: NormalizedRenderTarget::None {
+                width: 500,
+                height: 100,
+            },
+            position,
+        };
+        Pointer::new(PointerId::Mouse, location, event, entity)
+    }
+
+    /// A pointer press on `entity`, at `position` relative to its text area
+    fn pointer_press(entity: Entity, button: PointerButton, position: Vec2) -> Pointer<Press> {
+        let hit = bevy::picking::backend::HitData::new(Entity::PLACEHOLDER, 0., None, None);
+        pointer(entity, position, Press { button, hit })
+    }
+
+    /// Triggers a pointer press on `entity`, at `position` relative to its text area
+    fn press_pointer(world: &mut World, entity: Entity, button: PointerButton, position: Vec2) {
+        world.trigger(pointer_press(entity, button, position));
+        world.flush();
+    }
+
+    /// The cursor's line and index, ignoring its affinity
+    fn cursor_position(buffer: &TextInputBuffer) -> (usize, usize) {
+        let cursor = buffer.editor.cursor();
+        (cursor.line, cursor.index)
+    }
+
+    /// Sends the keyboard inputs to the focused entity's observers and processes the queued actions.
+    /// The world needs a primary window and `Messages<KeyboardInput>`.
+    fn dispatch_keys(world: &mut World, inputs: impl IntoIterator<Item = KeyboardInput>) {
+        for input in inputs {
+            world.write_message(input);
+        }
+        world
+            .run_system_once(bevy::input_focus::dispatch_focused_input::<KeyboardInput>)
+            .unwrap();
+        // Each one-shot run reads every buffered message, don't dispatch these again
+        world.resource_mut::<Messages<KeyboardInput>>().clear();
+        world.flush();
+        world.run_system_once(process_text_input_queues).unwrap();
+    }
+
+    /// The actions queued for a key press with the given modifiers held
+    fn queued_actions(
+        input: &TextInputNode,
+        buffer: &TextInputBuffer,
+        keyboard_input: &KeyboardInput,
+        shift: bool,
+        command: bool,
+    ) -> Vec<TextInputAction> {
+        let mut actions = Vec::new();
+        queue_text_input_action(
+            input,
+            buffer,
+            &TextInputGlobalState {
+                shift,
+                command,
+                ..Default::default()
+            },
+            &mut false,
+            keyboard_input,
+            |action| actions.push(action),
+        );
+        actions
+    }
+
+    #[test]
+    fn ctrl_enter_submits_with_ctrl_submit_modifier() {
+        let input = TextInputNode {
+            submit_modifier: SubmitModifier::Ctrl,
+            ..Default::default()
+        };
+        let mut buffer = TextInputBuffer::default();
+        let enter = press(Key::Enter, KeyCode::Enter);
+
+        let actions = queued_actions(&input, &buffer, &enter, false, true);
+        assert!(matches!(actions[..], [TextInputAction::Submit]));
+
+        for shift in [false, true] {
+            let actions = queued_actions(&input, &buffer, &enter, shift, false);
+            assert!(matches!(
+                actions[..],
+                [TextInputAction::Edit(TextInputEdit::Enter)]
+            ));
+        }
+
+        let mut font_system = font_system();
+        buffer.apply_edit(
+            TextInputEdit::Paste("a".into()),
+            &mut font_system,
+            None,
+            None,
+        );
+        buffer.apply_edit(TextInputEdit::Enter, &mut font_system, None, None);
+        assert_eq!(buffer.get_text(), "a\n");
+    }
+
+    #[test]
+    fn shift_enter_inserts_soft_break() {
+        let input = TextInputNode {
+            soft_breaks: true,
+            ..Default::default()
+        };
+        let mut buffer = TextInputBuffer::default();
+        let actions = queued_actions(
+            &input,
+            &buffer,
+            &press(Key::Enter, KeyCode::Enter),
+            true,
+            false,
+        );
+        assert!(matches!(
+            actions[..],
+            [TextInputAction::Edit(TextInputEdit::SoftBreak)]
+        ));
+
+        // Submitting moves from Shift + Enter to Ctrl + Enter
+        let actions = queued_actions(
+            &input,
+            &buffer,
+            &press(Key::Enter, KeyCode::Enter),
+            false,
+            true,
+        );
+        assert!(matches!(actions[..], [TextInputAction::Submit]));
+
+        let mut font_system = font_system();
+        for edit in [
+            TextInputEdit::Paste("a".into()),
+            TextInputEdit::SoftBreak,
+            TextInputEdit::Paste("b".into()),
+        ] {
+            buffer.apply_edit(edit, &mut font_system, None, None);
+        }
+        assert_eq!(buffer.get_text(), "a\u{2028}b");
+    }
+
+    #[test]
+    fn soft_breaks_round_trip_through_set_text() {
+        let mut buffer = TextInputBuffer::default();
+        buffer.set_text("a\u{2028}b\nc");
+        assert_eq!(buffer.get_text(), "a\u{2028}b\nc");
+        let endings: Vec<_> = buffer
+            .editor
+            .with_buffer(|buffer| buffer.lines.iter().map(|line| line.ending()).collect());
+        assert_eq!(
+            endings,
+            [crate::SOFT_BREAK, LineEnding::Lf, LineEnding::None]
+        );
+
+        let text = buffer.get_text();
+        buffer.set_text(&text);
+        assert_eq!(buffer.get_text(), text);
+
+        // A line feed followed by a carriage return isn't read as a soft break
+        buffer.set_text("a\n\rb");
+        assert_eq!(buffer.get_text(), "a\n\nb");
+    }
+
+    #[test]
+    fn move_and_duplicate_line_keep_soft_breaks() {
+        let mut font_system = font_system();
+        let mut buffer = TextInputBuffer::default();
+        buffer.set_text("a\u{2028}b\nc");
+        buffer.editor.set_cursor(Cursor::new(0, 0));
+
+        buffer.apply_edit(TextInputEdit::MoveLineDown, &mut font_system, None, None);
+        assert_eq!(buffer.get_text(), "b\u{2028}a\nc");
+
+        buffer.editor.set_cursor(Cursor::new(0, 0));
+        buffer.apply_edit(TextInputEdit::DuplicateLine, &mut font_system, None, None);
+        assert_eq!(buffer.get_text(), "b\u{2028}b\u{2028}a\nc");
+
+        // The last line has no break of its own, its copy is a new paragraph
+        buffer.editor.set_cursor(Cursor::new(3, 0));
+        buffer.apply_edit(TextInputEdit::DuplicateLine, &mut font_system, None, None);
+        assert_eq!(buffer.get_text(), "b\u{2028}b\u{2028}a\nc\nc");
+    }
+
+    #[test]
+    fn paste_tabs_to_spaces_before_first_layout() {
+        let mut world = queue_world();
+        let entity = world
+            .spawn((
+                TextInputNode {
+                    paste_indentation: PasteIndentation::TabsToSpaces { tab_width: 4 },
+                    ..Default::default()
+                },
+                ManualInput,
+            ))
+            .id();
+        // Queued on the frame the input is spawned, so its buffer has no lines yet
+        world
+            .get_mut::<TextInputQueue>(entity)
+            .unwrap()
+            .add(TextInputAction::PasteDeferred(ClipboardRead::Ready(Ok(
+                "\tif x:\n\t\ty".into(),
+            ))));
+        world.run_system_once(process_text_input_queues).unwrap();
+        assert_eq!(
+            world.get::<TextInputBuffer>(entity).unwrap().get_text(),
+            "    if x:\n        y"
+        );
+    }
+
+    #[test]
+    fn vertical_motion_keeps_goal_column() {
+        let mut font_system = font_system();
+        let mut buffer = TextInputBuffer::default();
+        buffer.set_text("abcdef\nab\nabcdef");
+        buffer.editor.set_cursor(Cursor::new(0, 5));
+
+        for (motion, line, index) in [
+            (Motion::Down, 1, 2),
+            (Motion::Down, 2, 5),
+            (Motion::Up, 1, 2),
+            (Motion::Up, 0, 5),
+        ] {
+            buffer.apply_edit(
+                TextInputEdit::Motion(motion, false),
+                &mut font_system,
+                None,
+                None,
+            );
+            assert_eq!(cursor_position(&buffer), (line, index));
+        }
+
+        // Moving the cursor horizontally replaces the remembered column
+        for motion in [Motion::Left, Motion::Down, Motion::Down] {
+            buffer.apply_edit(
+                TextInputEdit::Motion(motion, false),
+                &mut font_system,
+                None,
+                None,
+            );
+        }
+        assert_eq!(cursor_position(&buffer), (2, 4));
+    }
+
+    #[test]
+    fn duplicate_line_below_keeps_column() {
+        let mut font_system = font_system();
+        let mut buffer = TextInputBuffer::default();
+        buffer.set_text("one\ntwo\nthree");
+        buffer.editor.set_cursor(Cursor::new(1, 2));
+
+        buffer.apply_edit(TextInputEdit::DuplicateLine, &mut font_system, None, None);
+        assert_eq!(buffer.get_text(), "one\ntwo\ntwo\nthree");
+        assert_eq!(cursor_position(&buffer), (2, 2));
+
+        buffer.apply_edit(TextInputEdit::Undo, &mut font_system, None, None);
+        assert_eq!(buffer.get_text(), "one\ntwo\nthree");
+    }
+
+    #[test]
+    fn move_line_down_swaps_and_caret_follows() {
+        let mut font_system = font_system();
+        let mut buffer = TextInputBuffer::default();
+        buffer.set_text("one\ntwo\nthree");
+        buffer.editor.set_cursor(Cursor::new(0, 2));
+
+        buffer.apply_edit(TextInputEdit::MoveLineDown, &mut font_system, None, None);
+        assert_eq!(buffer.get_text(), "two\none\nthree");
+        assert_eq!(cursor_position(&buffer), (1, 2));
+
+        buffer.apply_edit(TextInputEdit::MoveLineDown, &mut font_system, None, None);
+        assert_eq!(buffer.get_text(), "two\nthree\none");
+        assert_eq!(cursor_position(&buffer), (2, 2));
+
+        // Moving the last line down does nothing
+        buffer.apply_edit(TextInputEdit::MoveLineDown, &mut font_system, None, None);
+        assert_eq!(buffer.get_text(), "two\nthree\none");
+        assert_eq!(cursor_position(&buffer), (2, 2));
+
+        // Nor does moving the first line up
+        buffer.editor.set_cursor(Cursor::new(0, 1));
+        buffer.apply_edit(TextInputEdit::MoveLineUp, &mut font_system, None, None);
+        assert_eq!(buffer.get_text(), "two\nthree\none");
+        assert_eq!(cursor_position(&buffer), (0, 1));
+    }
+
+    #[test]
+    fn shift_held_before_focus_selects() {
+        let mut world = queue_world();
+        world.init_resource::<Messages<KeyboardInput>>();
+        world.init_resource::<TextInputGlobalState>();
+        world.spawn((bevy::window::Window::default(), bevy::window::PrimaryWindow));
+        let entity = world.spawn(TextInputNode::default()).id();
+        world.flush();
+        world
+            .get_mut::<TextInputBuffer>(entity)
+            .unwrap()
+            .set_text("abc");
+
+        // Shift is pressed while no text input is focused, then an input is focused and Left pressed
+        for (focus, key, key_code) in [
+            (None, Key::Shift, KeyCode::ShiftLeft),
+            (Some(entity), Key::ArrowLeft, KeyCode::ArrowLeft),
+        ] {
+            world.resource_mut::<InputFocus>().0 = focus;
+            world.resource_mut::<Messages<KeyboardInput>>().clear();
+            world.write_message(press(key, key_code));
+            world.run_system_once(track_modifier_keys_system).unwrap();
+            world
+                .run_system_once(bevy::input_focus::dispatch_focused_input::<KeyboardInput>)
+                .unwrap();
+            world.flush();
+            world.run_system_once(process_text_input_queues).unwrap();
+        }
+
+        let buffer = world.get::<TextInputBuffer>(entity).unwrap();
+        assert_eq!(buffer.editor.copy_selection().as_deref(), Some("c"));
+    }
+
+    #[test]
+    fn keymap_takes_precedence() {
+        let mut world = queue_world();
+        world.insert_resource(TextInputConfig {
+            keymap: crate::TextInputKeymap::default().bind(KeyCode::KeyA, false, false, || {
+                TextInputAction::Edit(TextInputEdit::Insert('b', false))
+            }),
+            ..Default::default()
+        });
+        world.init_resource::<Messages<KeyboardInput>>();
+        world.init_resource::<TextInputGlobalState>();
+        world.spawn((bevy::window::Window::default(), bevy::window::PrimaryWindow));
+        let entity = world.spawn(TextInputNode::default()).id();
+        world.flush();
+        world.resource_mut::<InputFocus>().0 = Some(entity);
+
+        dispatch_keys(
+            &mut world,
+            [
+                press(Key::Character("a".into()), KeyCode::KeyA),
+                press(Key::Character("c".into()), KeyCode::KeyC),
+            ],
+        );
+
+        let buffer = world.get::<TextInputBuffer>(entity).unwrap();
+        assert_eq!(buffer.get_text(), "bc");
+    }
+
+    #[test]
+    fn idle_focused_input_buffer_unchanged() {
+        use crate::TextInputContents;
+        use bevy::asset::Assets;
+        use bevy::ecs::schedule::{IntoScheduleConfigs, Schedule};
+        use bevy::ecs::{query::Changed, resource::Resource};
+        use bevy::image::{Image, TextureAtlasLayout};
+        use bevy::text::{Font, FontAtlasSet, TextFont};
+        use bevy::time::Real;
+        use bevy::ui::ComputedNode;
+
+        #[derive(Resource, Default)]
+        struct ChangedBuffers(usize);
+
+        fn count_changed_buffers(
+            query: Query<(), Changed<TextInputBuffer>>,
+            mut changed: ResMut<ChangedBuffers>,
+        ) {
+            changed.0 += query.iter().count();
+        }
+
+        let mut world = queue_world();
+        world.init_resource::<Assets<Font>>();
+        world.init_resource::<Assets<Image>>();
+        world.init_resource::<Assets<TextureAtlasLayout>>();
+        world.init_resource::<FontAtlasSet>();
+        world.init_resource::<Messages<CaretMoved>>();
+        world.init_resource::<Time<Real>>();
+        world.init_resource::<TextInputConfig>();
+        world.init_resource::<ChangedBuffers>();
+        let font = world.resource_mut::<Assets<Font>>().add(
+            Font::try_from_bytes(include_bytes!("../assets/fonts/FiraMono-Medium.ttf").to_vec())
+                .unwrap(),
+        );
+        let entity = world
+            .spawn((
+                TextInputNode::default(),
+                TextFont {
+                    font,
+                    font_size: 16.,
+                    ..Default::default()
+                },
+                ComputedNode {
+                    size: bevy::math::Vec2::new(100., 30.),
+                    inverse_scale_factor: 1.,
+                    ..Default::default()
+                },
+            ))
+            .id();
+        world.resource_mut::<InputFocus>().set(entity);
+        world
+            .get_mut::<TextInputQueue>(entity)
+            .unwrap()
+            .add(TextInputAction::Edit(TextInputEdit::Paste("hello".into())));
+
+        let mut schedule = Schedule::default();
+        schedule.add_systems(
+            (
+                cursor_blink_system,
+                process_text_input_queues,
+                count_changed_buffers,
+                crate::update_text_input_contents,
+                caret_moved_system,
+                crate::text_input_pipeline::text_input_system,
+            )
+                .chain(),
+        );
+        // The paste and the first layout change the buffer
+        schedule.run(&mut world);
+        schedule.run(&mut world);
+        let changed = world.resource::<ChangedBuffers>().0;
+        assert!(0 < changed);
+
+        // While idle the cursor keeps blinking, but the buffer isn't touched
+        for _ in 0..10 {
+            schedule.run(&mut world);
+        }
+        assert_eq!(world.resource::<ChangedBuffers>().0, changed);
+        assert_eq!(
+            world.get::<TextInputContents>(entity).unwrap().get(),
+            "hello"
+        );
+    }
+
+    #[test]
+    fn focus_scrolls_caret_into_view() {
+        let mut world = queue_world();
+        world.init_resource::<TextInputGlobalState>();
+        let entity = world.spawn(TextInputNode::default()).id();
+        world.resource_scope(|world, mut pipeline: Mut<TextInputPipeline>| {
+            let mut buffer = world.get_mut::<TextInputBuffer>(entity).unwrap();
+            let text: Vec<_> = (0..20).map(|line| format!("line {line}")).collect();
+            buffer.set_text(&text.join("\n"));
+            buffer.editor.set_cursor(Cursor::new(19, 0));
+            buffer.editor.with_buffer_mut(|buffer| {
+                buffer.set_size(&mut pipeline.font_system, Some(200.), Some(60.));
+                buffer.set_scroll(Default::default());
+                buffer.shape_until_scroll(&mut pipeline.font_system, false);
+            });
+        });
+        let caret_visible = |world: &World| {
+            world
+                .get::<TextInputBuffer>(entity)
+                .unwrap()
+                .editor
+                .with_buffer(|buffer| buffer.layout_runs().any(|run| run.line_i == 19))
+        };
+        assert!(!caret_visible(&world));
+
+        world.resource_mut::<InputFocus>().set(entity);
+        world
+            .run_system_once(scroll_to_caret_on_focus_system)
+            .unwrap();
+        assert!(caret_visible(&world));
+    }
+
+    #[test]
+    fn paste_without_text_is_a_no_op() {
+        let mut world = queue_world();
+        let entity = spawn_input(&mut world, TextInputNode::default(), "abc");
+        process(&mut world, entity, [TextInputAction::Paste]);
+
+        assert_eq!(text(&world, entity), "abc");
+        let failures: Vec<_> = world
+            .resource_mut::<Messages<PasteFailed>>()
+            .drain()
+            .collect();
+        assert!(matches!(
+            failures[..],
+            [PasteFailed {
+                entity: failed,
+                reason: PasteFailure::NoText,
+            }] if failed == entity
+        ));
+    }
+
+    #[test]
+    fn transpose_swaps_graphemes() {
+        let mut world = queue_world();
+        for (initial, index, expected) in [
+            ("abc", 1, "bac"),
+            ("abc", 3, "acb"),
+            ("abc", 0, "abc"),
+            ("a", 1, "a"),
+            ("e\u{301}x", 3, "xe\u{301}"),
+            ("x👨‍👩‍👧", "x👨‍👩‍👧".len(), "👨‍👩‍👧x"),
+        ] {
+            let entity = spawn_input(&mut world, TextInputNode::default(), initial);
+            world
+                .get_mut::<TextInputBuffer>(entity)
+                .unwrap()
+                .editor
+                .set_cursor(Cursor::new(0, index));
+            process(
+                &mut world,
+                entity,
+                [TextInputAction::Edit(TextInputEdit::TransposeChars)],
+            );
+            assert_eq!(text(&world, entity), expected, "{initial:?} at {index}");
+        }
+    }
+
+    #[test]
+    fn middle_click_pastes_at_position() {
+        let mut world = queue_world();
+        world.init_resource::<TextInputGlobalState>();
+        world.add_observer(on_text_input_middle_pressed);
+        world.insert_resource(Clipboard::with_backend(Box::new(TestClipboard(
+            "XY".into(),
+        ))));
+        let input = TextInputNode {
+            middle_click_paste: true,
+            ..Default::default()
+        };
+        let entity = spawn_input(&mut world, input, "abcd");
+        let width = layout(&mut world, entity);
+
+        press_pointer(
+            &mut world,
+            entity,
+            PointerButton::Middle,
+            Vec2::new(2. * width, 5.),
+        );
+        world.run_system_once(process_text_input_queues).unwrap();
+
+        assert_eq!(text(&world, entity), "abXYcd");
+        assert_eq!(world.resource::<InputFocus>().0, Some(entity));
+
+        // Inputs that don't focus on pointer down ignore middle clicks like other presses
+        world.resource_mut::<InputFocus>().clear();
+        let input = TextInputNode {
+            middle_click_paste: true,
+            focus_on_pointer_down: false,
+            ..Default::default()
+        };
+        let entity = spawn_input(&mut world, input, "abcd");
+        press_pointer(
+            &mut world,
+            entity,
+            PointerButton::Middle,
+            Vec2::new(2. * width, 5.),
+        );
+        world.run_system_once(process_text_input_queues).unwrap();
+
+        assert_eq!(text(&world, entity), "abcd");
+        assert_eq!(world.resource::<InputFocus>().0, None);
+    }
+
+    #[test]
+    fn shift_arrow_moves_without_selection() {
+        let mut world = queue_world();
+        let input = TextInputNode {
+            allow_selection: false,
+            ..Default::default()
+        };
+        let entity = spawn_input(&mut world, input.clone(), "abc");
+
+        let buffer = world.get::<TextInputBuffer>(entity).unwrap();
+        let actions = queued_actions(
+            &input,
+            buffer,
+            &press(Key::ArrowLeft, KeyCode::ArrowLeft),
+            true,
+            false,
+        );
+        process(&mut world, entity, actions);
+
+        let buffer = world.get::<TextInputBuffer>(entity).unwrap();
+        assert_eq!(buffer.editor.selection(), Selection::None);
+        assert_eq!(cursor_position(buffer), (0, 2));
+    }
+
+    #[test]
+    fn insert_toggles_overwrite_of_focused_input_only() {
+        let mut world = queue_world();
+        world.init_resource::<Messages<KeyboardInput>>();
+        world.init_resource::<TextInputGlobalState>();
+        world.spawn((bevy::window::Window::default(), bevy::window::PrimaryWindow));
+        let a = world.spawn(TextInputNode::default()).id();
+        let b = world.spawn(TextInputNode::default()).id();
+        world.flush();
+        world.resource_mut::<InputFocus>().0 = Some(a);
+
+        dispatch_keys(&mut world, [press(Key::Insert, KeyCode::Insert)]);
+
+        let overwrite_mode = |entity| {
+            world
+                .get::<TextInputBuffer>(entity)
+                .unwrap()
+                .overwrite_mode()
+        };
+        assert!(overwrite_mode(a));
+        assert!(!overwrite_mode(b));
+    }
+
+    #[test]
+    fn navigation_keeps_contents_version() {
+        let mut world = queue_world();
+        let entity = spawn_input(&mut world, TextInputNode::default(), "abc");
+        let version = |world: &mut World| {
+            world
+                .run_system_once(crate::update_text_input_contents)
+                .unwrap();
+            world.flush();
+            world
+                .get::<crate::TextInputContents>(entity)
+                .unwrap()
+                .version()
+        };
+        let initial = version(&mut world);
+
+        process(
+            &mut world,
+            entity,
+            [
+                TextInputAction::Edit(TextInputEdit::Motion(Motion::Left, false)),
+                TextInputAction::Edit(TextInputEdit::Motion(Motion::Home, true)),
+            ],
+        );
+        assert_eq!(version(&mut world), initial);
+
+        process(
+            &mut world,
+            entity,
+            [TextInputAction::Edit(TextInputEdit::Insert('x', false))],
+        );
+        assert_eq!(version(&mut world), initial + 1);
+    }
+
+    #[test]
+    fn observer_cancels_insert() {
+        let mut world = queue_world();
+        let entity = spawn_input(&mut world, TextInputNode::default(), "");
+        world
+            .entity_mut(entity)
+            .insert(InterceptEdits)
+            .observe(|mut event: On<OnBeforeEdit>| {
+                if matches!(event.edit, TextInputEdit::Insert('x', _)) {
+                    event.cancel = true;
+                }
+            });
+
+        let mut queue = world.get_mut::<TextInputQueue>(entity).unwrap();
+        for ch in ['a', 'x', 'b'] {
+            queue.add(TextInputAction::Edit(TextInputEdit::Insert(ch, false)));
+        }
+        world.run_system_once(intercept_text_input_edits).unwrap();
+        world.run_system_once(process_text_input_queues).unwrap();
+
+        assert_eq!(text(&world, entity), "ab");
+    }
+
+    #[test]
+    fn click_outside_blurs() {
+        let mut world = queue_world();
+        world.init_resource::<Messages<Pointer<Press>>>();
+        let input = TextInputNode {
+            blur_on_outside_click: true,
+            ..Default::default()
+        };
+        let entity = spawn_input(&mut world, input, "");
+        let button = world.spawn(KeepTextInputFocus).id();
+        let background = world.spawn_empty().id();
+
+        for (target, focused) in [(entity, true), (button, true), (background, false)] {
+            world.resource_mut::<InputFocus>().0 = Some(entity);
+            world.resource_mut::<Messages<Pointer<Press>>>().clear();
+            world.write_message(pointer_press(target, PointerButton::Primary, Vec2::ZERO));
+            world.run_system_once(blur_on_outside_click_system).unwrap();
+            assert_eq!(world.resource::<InputFocus>().0.is_some(), focused);
+        }
+    }
+
+    #[test]
+    fn backspace_removes_whole_emoji() {
+        let mut world = queue_world();
+        let entity = spawn_input(&mut world, TextInputNode::default(), "a👨‍👩‍👧");
+        process(
+            &mut world,
+            entity,
+            [TextInputAction::Edit(TextInputEdit::Backspace)],
+        );
+        assert_eq!(text(&world, entity), "a");
+
+        process(
+            &mut world,
+            entity,
+            [TextInputAction::Edit(TextInputEdit::Undo)],
+        );
+        assert_eq!(text(&world, entity), "a👨‍👩‍👧");
+    }
+
+    #[test]
+    fn click_restarts_blink() {
+        let mut world = queue_world();
+        world.init_resource::<TextInputGlobalState>();
+        world.add_observer(on_text_input_pressed);
+        let entity = spawn_input(&mut world, TextInputNode::default(), "abcd");
+        let width = layout(&mut world, entity);
+        // Midway through the blink's hidden phase
+        world.get_mut::<TextInputCursorBlink>(entity).unwrap().time = 0.7;
+
+        press_pointer(
+            &mut world,
+            entity,
+            PointerButton::Primary,
+            Vec2::new(width, 5.),
+        );
+
+        assert_eq!(world.get::<TextInputCursorBlink>(entity).unwrap().time, 0.);
+        let buffer = world.get::<TextInputBuffer>(entity).unwrap();
+        assert_eq!(cursor_position(buffer), (0, 1));
+    }
+
+    #[test]
+    fn submit_commits_or_discards_preedit() {
+        let mut world = queue_world();
+        for (discard_preedit_on_submit, expected) in [(false, "abcd"), (true, "ab")] {
+            let input = TextInputNode {
+                discard_preedit_on_submit,
+                ..Default::default()
+            };
+            let entity = spawn_input(&mut world, input, "ab");
+            world
+                .get_mut::<TextInputBuffer>(entity)
+                .unwrap()
+                .set_preedit(Some("cd".into()));
+            process(&mut world, entity, [TextInputAction::Submit]);
+
+            let submitted: Vec<_> = world
+                .resource_mut::<Messages<SubmitText>>()
+                .drain()
+                .map(|submit| submit.text)
+                .collect();
+            assert_eq!(submitted, [expected]);
+            let buffer = world.get::<TextInputBuffer>(entity).unwrap();
+            assert_eq!(buffer.preedit(), None);
+        }
+    }
+
+    #[test]
+    fn caret_stays_solid_while_typing() {
+        let mut world = queue_world();
+        world.init_resource::<Time<Real>>();
+        let input = TextInputNode {
+            blink_pause_while_typing: 0.5,
+            ..Default::default()
+        };
+        let entity = spawn_input(&mut world, input, "");
+        let frame = |world: &mut World, key: Option<char>| {
+            if let Some(ch) = key {
+                let mut queue = world.get_mut::<TextInputQueue>(entity).unwrap();
+                queue.add(TextInputAction::Edit(TextInputEdit::Insert(ch, false)));
+            }
+            world
+                .resource_mut::<Time>()
+                .advance_by(core::time::Duration::from_secs_f32(0.125));
+            world.run_system_once(cursor_blink_system).unwrap();
+            world.run_system_once(process_text_input_queues).unwrap();
+            world.get::<TextInputCursorBlink>(entity).unwrap().time
+        };
+
+        // Typing for longer than the blink interval keeps the caret solid
+        for ch in "abcdefgh".chars() {
+            assert_eq!(frame(&mut world, Some(ch)), 0.);
+        }
+        // and it stays solid for the pause after the last keystroke
+        for _ in 0..3 {
+            assert_eq!(frame(&mut world, None), 0.);
+        }
+        assert!(0. < frame(&mut world, None));
+    }
+
+    #[test]
+    fn state_reflects_focus_and_content_in_the_same_frame() {
+        use bevy::ecs::schedule::{IntoScheduleConfigs, Schedule};
+
+        let mut world = queue_world();
+        let entity = spawn_input(&mut world, TextInputNode::default(), "");
+        let mut schedule = Schedule::default();
+        schedule.add_systems((process_text_input_queues, crate::update_text_input_state).chain());
+        schedule.run(&mut world);
+        let state = |world: &World| world.get::<crate::TextInputState>(entity).unwrap().clone();
+        assert!(state(&world).is_empty && !state(&world).focused);
+
+        world.resource_mut::<InputFocus>().0 = Some(entity);
+        world
+            .get_mut::<TextInputQueue>(entity)
+            .unwrap()
+            .add(TextInputAction::Edit(TextInputEdit::Paste("ab".into())));
+        schedule.run(&mut world);
+        let typed = state(&world);
+        assert!(typed.focused && !typed.is_empty && !typed.has_selection);
+        assert_eq!(typed.char_count, 2);
+
+        world.resource_mut::<InputFocus>().0 = None;
+        world
+            .get_mut::<TextInputQueue>(entity)
+            .unwrap()
+            .add(TextInputAction::Edit(TextInputEdit::SelectAll));
+        schedule.run(&mut world);
+        let selected = state(&world);
+        assert!(!selected.focused && selected.has_selection);
+    }
+
+    #[test]
+    fn manual_input_ignores_keyboard() {
+        let mut world = queue_world();
+        world.init_resource::<Messages<KeyboardInput>>();
+        world.init_resource::<TextInputGlobalState>();
+        world.spawn((bevy::window::Window::default(), bevy::window::PrimaryWindow));
+        let entity = spawn_input(&mut world, TextInputNode::default(), "");
+        world.resource_mut::<InputFocus>().0 = Some(entity);
+
+        dispatch_keys(
+            &mut world,
+            [press(Key::Character("a".into()), KeyCode::KeyA)],
+        );
+        assert_eq!(text(&world, entity), "");
+
+        process(
+            &mut world,
+            entity,
+            [TextInputAction::Edit(TextInputEdit::Insert('b', false))],
+        );
+        assert_eq!(text(&world, entity), "b");
+    }
+
+    #[test]
+    fn rejected_keystroke_updates_hint() {
+        use bevy::ecs::schedule::{IntoScheduleConfigs, Schedule};
+        use bevy::ui::widget::Text;
+
+        let mut world = queue_world();
+        let hint = world.spawn(Text::default()).id();
+        let entity = spawn_input(&mut world, TextInputNode::default(), "1");
+        world.entity_mut(entity).insert((
+            TextInputFilter::Integer,
+            crate::TextInputHint::new(hint, "Enter a number").with_invalid("Digits only"),
+        ));
+        let mut schedule = Schedule::default();
+        schedule.add_systems(
+            (
+                process_text_input_queues,
+                crate::update_text_input_contents,
+                crate::update_text_input_state,
+                crate::text_input_hint_system,
+            )
+                .chain(),
+        );
+        schedule.run(&mut world);
+        assert_eq!(world.get::<Text>(hint).unwrap().0, "Enter a number");
+
+        world
+            .get_mut::<TextInputQueue>(entity)
+            .unwrap()
+            .add(TextInputAction::Edit(TextInputEdit::Insert('x', false)));
+        schedule.run(&mut world);
+        assert_eq!(world.get::<Text>(hint).unwrap().0, "Digits only");
+    }
+
+    #[test]
+    fn multi_line_hint_at_limit_ignores_line_breaks() {
+        use bevy::ecs::schedule::{IntoScheduleConfigs, Schedule};
+        use bevy::ui::widget::Text;
+
+        let mut world = queue_world();
+        let hint = world.spawn(Text::default()).id();
+        let input = TextInputNode {
+            max_chars: Some(10),
+            ..Default::default()
+        };
+        let entity = spawn_input(&mut world, input, "abcd\nefgh\n");
+        world.entity_mut(entity).insert(
+            crate::TextInputHint::new(hint, "Up to 10 characters").with_at_limit("Limit reached"),
+        );
+        let mut schedule = Schedule::default();
+        schedule.add_systems(
+            (
+                process_text_input_queues,
+                crate::update_text_input_contents,
+                crate::update_text_input_state,
+                crate::text_input_hint_system,
+            )
+                .chain(),
+        );
+        let mut insert = |world: &mut World, ch: char| {
+            world
+                .get_mut::<TextInputQueue>(entity)
+                .unwrap()
+                .add(TextInputAction::Edit(TextInputEdit::Insert(ch, false)));
+            schedule.run(world);
+            world.get::<Text>(hint).unwrap().0.clone()
+        };
+
+        // 8 characters and 2 line breaks
+        assert_eq!(insert(&mut world, 'i'), "Up to 10 characters");
+        assert_eq!(insert(&mut world, 'j'), "Limit reached");
+        assert_eq!(text(&world, entity), "abcd\nefgh\nij");
+    }
+
+    #[test]
+    fn drag_outside_selects_to_the_nearest_edge() {
+        let mut world = queue_world();
+        world.add_observer(on_drag_text_input);
+        let entity = spawn_input(&mut world, TextInputNode::default(), "ab\ncd\nef");
+        layout(&mut world, entity);
+        world.resource_mut::<InputFocus>().0 = Some(entity);
+        world
+            .get_mut::<TextInputBuffer>(entity)
+            .unwrap()
+            .editor
+            .set_cursor(Cursor::new(1, 1));
+
+        let mut drag = |position: Vec2| {
+            world.trigger(pointer(
+                entity,
+                position,
+                Drag {
+                    button: PointerButton::Primary,
+                    distance: Vec2::ZERO,
+                    delta: Vec2::ZERO,
+                },
+            ));
+            let buffer = world.get::<TextInputBuffer>(entity).unwrap();
+            buffer
+                .editor
+                .selection_bounds()
+                .map(|(start, end)| ((start.line, start.index), (end.line, end.index)))
+        };
+
+        // Above the text, then below it after passing back through the middle
+        assert_eq!(drag(Vec2::new(30., -50.)), Some(((0, 0), (1, 1))));
+        assert!(drag(Vec2::new(30., 30.)).is_some());
+        assert_eq!(drag(Vec2::new(30., 500.)), Some(((1, 1), (2, 2))));
+    }
+
+    #[test]
+    fn paste_beyond_max_paste_chars_is_truncated() {
+        let mut world = queue_world();
+        world.insert_resource(Clipboard::with_backend(Box::new(TestClipboard(
+            "abcdéfgh".into(),
+        ))));
+        let input = TextInputNode {
+            max_paste_chars: Some(5),
+            ..Default::default()
+        };
+        let entity = spawn_input(&mut world, input, "");
+        process(&mut world, entity, [TextInputAction::Paste]);
+
+        assert_eq!(text(&world, entity), "abcdé");
+        let too_large: Vec<_> = world
+            .resource_mut::<Messages<PasteTooLarge>>()
+            .drain()
+            .collect();
+        assert_eq!(too_large, [PasteTooLarge { entity, size: 8 }]);
+    }
+
+    #[test]
+    fn multi_line_paste_promotes_single_line_input() {
+        let mut world = queue_world();
+        world.insert_resource(Clipboard::with_backend(Box::new(TestClipboard(
+            "one\ntwo".into(),
+        ))));
+        let input = TextInputNode {
+            mode: TextInputMode::SingleLine,
+            promote_on_newline: true,
+            ..Default::default()
+        };
+        let entity = spawn_input(&mut world, input, "");
+        process(&mut world, entity, [TextInputAction::Paste]);
+
+        assert_eq!(text(&world, entity), "one\ntwo");
+        assert_eq!(
+            world.get::<TextInputNode>(entity).unwrap().mode,
+            TextInputMode::default()
+        );
+    }
+
+    #[test]
+    fn undo_recordable_set_text() {
+        let mut world = queue_world();
+        let input = TextInputNode::default();
+        for (undo, expected, cursor) in [
+            (crate::SetTextUndo::Recordable, "hello", (0, 2)),
+            (crate::SetTextUndo::Reset, "world", (0, 5)),
+        ] {
+            let entity = spawn_input(&mut world, input.clone(), "hello");
+            let mut buffer = world.get_mut::<TextInputBuffer>(entity).unwrap();
+            buffer.editor.set_cursor(Cursor::new(0, 2));
+            buffer.set_text_with("world", undo);
+
+            let ctrl_z = queued_actions(
+                &input,
+                &buffer,
+                &press(Key::Character("z".into()), KeyCode::KeyZ),
+                false,
+                true,
+            );
+            process(&mut world, entity, ctrl_z);
+
+            assert_eq!(text(&world, entity), expected, "{undo:?}");
+            let buffer = world.get::<TextInputBuffer>(entity).unwrap();
+            assert_eq!(cursor_position(buffer), cursor, "{undo:?}");
+        }
+    }
+
+    #[test]
+    fn truncated_paste_fills_max_chars() {
+        let mut world = queue_world();
+        let input = TextInputNode {
+            max_chars: Some(5),
+            paste_overflow: PasteOverflow::Truncate,
+            ..Default::default()
+        };
+        let entity = spawn_input(&mut world, input, "ab");
+        process(
+            &mut world,
+            entity,
+            [TextInputAction::Edit(TextInputEdit::Paste("çdéfg".into()))],
+        );
+        assert_eq!(text(&world, entity), "abçdé");
+    }
+
+    #[test]
+    fn multi_byte_paste_fits_max_chars() {
+        let mut world = queue_world();
+        world.insert_resource(Clipboard::with_backend(Box::new(TestClipboard(
+            "приве".into(),
+        ))));
+        let input = TextInputNode {
+            max_chars: Some(5),
+            ..Default::default()
+        };
+        let entity = spawn_input(&mut world, input.clone(), "");
+        process(&mut world, entity, [TextInputAction::Paste]);
+        assert_eq!(text(&world, entity), "приве");
+
+        let input = TextInputNode {
+            paste_overflow: PasteOverflow::Truncate,
+            ..input
+        };
+        let entity = spawn_input(&mut world, input, "");
+        process(
+            &mut world,
+            entity,
+            [TextInputAction::Edit(TextInputEdit::Paste(
+                "a\u{202E}bcdef".into(),
+            ))],
+        );
+        assert_eq!(text(&world, entity), "abcde");
+    }
+
+    #[test]
+    fn insert_then_blur_applies_edit_and_clears_focus() {
+        let mut world = queue_world();
+        let entity = spawn_input(&mut world, TextInputNode::default(), "ab");
+        process(&mut world, entity, [TextInputAction::Focus]);
+        assert_eq!(world.resource::<InputFocus>().get(), Some(entity));
+
+        process(
+            &mut world,
+            entity,
+            [
+                TextInputAction::Edit(TextInputEdit::Insert('c', false)),
+                TextInputAction::Blur,
+            ],
+        );
+        assert_eq!(text(&world, entity), "abc");
+        assert_eq!(world.resource::<InputFocus>().get(), None);
+
+        world.get_mut::<TextInputNode>(entity).unwrap().is_enabled = false;
+        process(&mut world, entity, [TextInputAction::Focus]);
+        assert_eq!(world.resource::<InputFocus>().get(), None);
+    }
+
+    #[test]
+    fn single_completion_is_inserted_at_cursor() {
+        let mut world = queue_world();
+        let entity = spawn_input(&mut world, TextInputNode::default(), "hel world");
+        world
+            .entity_mut(entity)
+            .insert(TextInputCompletion::new(|text, cursor| {
+                ["hello", "help"]
+                    .into_iter()
+                    .filter(|word| word.starts_with(&text[..cursor]) && word.len() > cursor)
+                    .map(|word| word[cursor..].to_string())
+                    .collect()
+            }));
+        world
+            .get_mut::<TextInputBuffer>(entity)
+            .unwrap()
+            .editor
+            .set_cursor(Cursor::new(0, 3));
+        process(&mut world, entity, [TextInputAction::Complete]);
+        let candidates: Vec<_> = world
+            .resource_mut::<Messages<CompletionCandidates>>()
+            .drain()
+            .collect();
+        assert_eq!(
+            candidates,
+            [CompletionCandidates {
+                entity,
+                candidates: vec!["lo".into(), "p".into()],
+            }]
+        );
+        assert_eq!(text(&world, entity), "hel world");
+
+        world
+            .get_mut::<TextInputBuffer>(entity)
+            .unwrap()
+            .set_text("hell");
+        process(&mut world, entity, [TextInputAction::Complete]);
+        assert_eq!(text(&world, entity), "hello");
+        let buffer = world.get::<TextInputBuffer>(entity).unwrap();
+        assert_eq!(cursor_position(buffer), (0, 5));
+    }
+
+    #[test]
+    fn ctrl_shift_k_deletes_middle_line() {
+        let mut world = queue_world();
+        let input = TextInputNode::default();
+        let entity = spawn_input(&mut world, input.clone(), "one\ntwo\nthree");
+        let mut buffer = world.get_mut::<TextInputBuffer>(entity).unwrap();
+        buffer.editor.set_cursor(Cursor::new(1, 2));
+        let delete_line = queued_actions(
+            &input,
+            &buffer,
+            &press(Key::Character("K".into()), KeyCode::KeyK),
+            true,
+            true,
+        );
+        process(&mut world, entity, delete_line);
+
+        assert_eq!(text(&world, entity), "one\nthree");
+        let buffer = world.get::<TextInputBuffer>(entity).unwrap();
+        assert_eq!(cursor_position(buffer), (1, 0));
+
+        process(
+            &mut world,
+            entity,
+            [TextInputAction::Edit(TextInputEdit::Undo)],
+        );
+        assert_eq!(text(&world, entity), "one\ntwo\nthree");
+    }
+
+    #[test]
+    fn mirror_follows_source_and_rejects_edits() {
+        use bevy::ecs::schedule::{IntoScheduleConfigs, Schedule};
+
+        let mut world = queue_world();
+        let source = spawn_input(&mut world, TextInputNode::default(), "ab");
+        let mirror = spawn_input(&mut world, TextInputNode::default(), "");
+        world.entity_mut(mirror).insert(MirrorTextInput { source });
+        let mut schedule = Schedule::default();
+        schedule.add_systems(
+            (
+                process_text_input_queues,
+                crate::update_text_input_contents,
+                crate::mirror_text_inputs,
+            )
+                .chain(),
+        );
+        schedule.run(&mut world);
+        assert_eq!(text(&world, mirror), "ab");
+
+        world
+            .get_mut::<TextInputQueue>(source)
+            .unwrap()
+            .add(TextInputAction::Edit(TextInputEdit::Insert('c', false)));
+        schedule.run(&mut world);
+        assert_eq!(text(&world, mirror), "abc");
+
+        world
+            .get_mut::<TextInputQueue>(mirror)
+            .unwrap()
+            .add(TextInputAction::Edit(TextInputEdit::Insert('d', false)));
+        schedule.run(&mut world);
+        assert_eq!(text(&world, mirror), "abc");
+        let rejections: Vec<_> = world
+            .resource_mut::<Messages<TextInputRejected>>()
+            .drain()
+            .collect();
+        assert_eq!(
+            rejections,
+            [TextInputRejected {
+                entity: mirror,
+                reason: TextInputRejection::ReadOnly,
+            }]
+        );
+    }
+
+    #[test]
+    fn keyboard_focus_selects_all_and_click_places_caret() {
+        use bevy::ecs::schedule::{IntoScheduleConfigs, Schedule};
+
+        let mut world = queue_world();
+        world.init_resource::<TextInputGlobalState>();
+        world.add_observer(on_text_input_pressed);
+        let input = TextInputNode {
+            select_all_on_focus: SelectAllOnFocus::KeyboardOnly,
+            ..Default::default()
+        };
+        let tabbed = spawn_input(&mut world, input.clone(), "abcd");
+        let clicked = spawn_input(&mut world, input, "abcd");
+        let width = layout(&mut world, clicked);
+        let mut schedule = Schedule::default();
+        schedule.add_systems((select_all_on_focus_system, process_text_input_queues).chain());
+        let selection = |world: &World, entity| {
+            world
+                .get::<TextInputBuffer>(entity)
+                .unwrap()
+                .editor
+                .selection_bounds()
+                .map(|(start, end)| (start.index, end.index))
+        };
+
+        // Tab navigation sets the focus directly
+        world.resource_mut::<InputFocus>().set(tabbed);
+        schedule.run(&mut world);
+        assert_eq!(selection(&world, tabbed), Some((0, 4)));
+
+        press_pointer(
+            &mut world,
+            clicked,
+            PointerButton::Primary,
+            Vec2::new(2. * width, 5.),
+        );
+        schedule.run(&mut world);
+        assert_eq!(world.resource::<InputFocus>().get(), Some(clicked));
+        assert_eq!(selection(&world, clicked), None);
+        let buffer = world.get::<TextInputBuffer>(clicked).unwrap();
+        assert_eq!(cursor_position(buffer), (0, 2));
+    }
+
+    #[test]
+    fn replaying_recording_reproduces_text_and_cursor() {
+        let mut world = queue_world();
+        world.init_resource::<Time>();
+        let entity = spawn_input(&mut world, TextInputNode::default(), "");
+        world.entity_mut(entity).insert(TextInputRecorder::new(16));
+        process(
+            &mut world,
+            entity,
+            [
+                TextInputAction::Edit(TextInputEdit::Insert('a', false)),
+                TextInputAction::Edit(TextInputEdit::Paste("bcd\nef".into())),
+                TextInputAction::Edit(TextInputEdit::Motion(Motion::Previous, false)),
+                TextInputAction::Edit(TextInputEdit::Backspace),
+                TextInputAction::Edit(TextInputEdit::Insert('x', false)),
+            ],
+        );
+        assert_eq!(text(&world, entity), "abcd\nxf");
+
+        let mut replayed = TextInputBuffer::default();
+        world.resource_scope(|world, mut pipeline: Mut<TextInputPipeline>| {
+            let recorder = world.get::<TextInputRecorder>(entity).unwrap();
+            assert_eq!(recorder.dropped(), 0);
+            recorder.replay(&mut replayed, &mut pipeline.font_system);
+        });
+        let buffer = world.get::<TextInputBuffer>(entity).unwrap();
+        assert_eq!(replayed.get_text(), buffer.get_text());
+        assert_eq!(cursor_position(&replayed), cursor_position(buffer));
+    }
+
+    #[test]
+    fn crlf_paste_stored_as_lf_and_submitted_as_crlf() {
+        let mut world = queue_world();
+        world.insert_resource(Clipboard::with_backend(Box::new(TestClipboard(
+            "a\r\nb\r\nc".into(),
+        ))));
+        let input = TextInputNode {
+            output_newline: crate::NewlineStyle::CrLf,
+            ..Default::default()
+        };
+        let entity = spawn_input(&mut world, input, "");
+        process(&mut world, entity, [TextInputAction::Paste]);
+        assert_eq!(text(&world, entity), "a\nb\nc");
+        world
+            .get::<TextInputBuffer>(entity)
+            .unwrap()
+            .editor
+            .with_buffer(|buffer| {
+                assert_eq!(buffer.lines.len(), 3);
+                assert!(buffer.lines.iter().all(|line| !line.text().contains('\r')));
+            });
+
+        process(&mut world, entity, [TextInputAction::Submit]);
+        let submitted: Vec<_> = world
+            .resource_mut::<Messages<SubmitText>>()
+            .drain()
+            .map(|submit| submit.text)
+            .collect();
+        assert_eq!(submitted, ["a\r\nb\r\nc"]);
+    }
+
+    #[test]
+    fn secure_input_keeps_no_history_and_ignores_copy() {
+        let mut world = queue_world();
+        world.insert_resource(Clipboard::with_backend(Box::new(TestClipboard(
+            "clip".into(),
+        ))));
+        let input = TextInputNode {
+            secure: true,
+            ..Default::default()
+        };
+        let entity = spawn_input(&mut world, input.clone(), "");
+        process(
+            &mut world,
+            entity,
+            "secret"
+                .chars()
+                .map(|ch| TextInputAction::Edit(TextInputEdit::Insert(ch, false))),
+        );
+        assert_eq!(text(&world, entity), "secret");
+        let buffer = world.get::<TextInputBuffer>(entity).unwrap();
+        assert_eq!(buffer.changes.current_command_index(), None);
+
+        process(
+            &mut world,
+            entity,
+            [TextInputAction::Edit(TextInputEdit::SelectAll)],
+        );
+        let buffer = world.get::<TextInputBuffer>(entity).unwrap();
+        let ctrl_c = queued_actions(
+            &input,
+            buffer,
+            &press(Key::Character("c".into()), KeyCode::KeyC),
+            false,
+            true,
+        );
+        assert!(matches!(ctrl_c[..], [TextInputAction::Copy]));
+        process(&mut world, entity, ctrl_c);
+        let clipboard = world.resource_mut::<Clipboard>().fetch_text().poll_result();
+        assert!(matches!(clipboard, Some(Ok(text)) if text == "clip"));
+    }
+
+    #[test]
+    fn click_places_caret_on_nearer_side_of_glyph() {
+        let mut world = queue_world();
+        world.init_resource::<TextInputGlobalState>();
+        world.add_observer(on_text_input_pressed);
+        for (fraction, expected) in [(1.25, 1), (1.75, 2), (2.1, 2), (2.9, 3)] {
+            let entity = spawn_input(&mut world, TextInputNode::default(), "abcd");
+            let width = layout(&mut world, entity);
+            press_pointer(
+                &mut world,
+                entity,
+                PointerButton::Primary,
+                Vec2::new(fraction * width, 5.),
+            );
+            let buffer = world.get::<TextInputBuffer>(entity).unwrap();
+            assert_eq!(cursor_position(buffer), (0, expected), "{fraction}");
+        }
+    }
+
+    #[test]
+    fn ctrl_shift_down_extends_selection_to_paragraph_end() {
+        let mut world = queue_world();
+        let input = TextInputNode::default();
+        let entity = spawn_input(&mut world, input.clone(), "one two\nthree four");
+        layout(&mut world, entity);
+        world
+            .get_mut::<TextInputBuffer>(entity)
+            .unwrap()
+            .editor
+            .set_cursor(Cursor::new(0, 2));
+        let selection = |world: &World| {
+            world
+                .get::<TextInputBuffer>(entity)
+                .unwrap()
+                .editor
+                .selection_bounds()
+                .map(|(start, end)| ((start.line, start.index), (end.line, end.index)))
+        };
+
+        for expected in [((0, 2), (0, 7)), ((0, 2), (1, 10))] {
+            let buffer = world.get::<TextInputBuffer>(entity).unwrap();
+            let ctrl_shift_down = queued_actions(
+                &input,
+                buffer,
+                &press(Key::ArrowDown, KeyCode::ArrowDown),
+                true,
+                true,
+            );
+            process(&mut world, entity, ctrl_shift_down);
+            assert_eq!(selection(&world), Some(expected));
+        }
+    }
+
+    #[test]
+    fn sanitize_strips_nul_and_bidi_overrides() {
+        assert_eq!(
+            sanitize_text("a\0b\u{202E}c\td\ne".into(), &['\t', '\n']),
+            "abc\td\ne"
+        );
+
+        let mut world = queue_world();
+        let entity = spawn_input(&mut world, TextInputNode::default(), "");
+        process(
+            &mut world,
+            entity,
+            [TextInputAction::Edit(TextInputEdit::Paste(
+                "a\0b\u{202E}c\td\ne".into(),
+            ))],
+        );
+        assert_eq!(text(&world, entity), "abc\td\ne");
+    }
+
+    #[test]
+    fn backspace_and_delete_join_lines() {
+        let mut world = queue_world();
+        let entity = spawn_input(&mut world, TextInputNode::default(), "ab\ncd\nef");
+        let edit_at = |world: &mut World, (line, index), edit| {
+            world
+                .get_mut::<TextInputBuffer>(entity)
+                .unwrap()
+                .editor
+                .set_cursor(Cursor::new(line, index));
+            process(world, entity, [TextInputAction::Edit(edit)]);
+            (
+                text(world, entity),
+                cursor_position(world.get::<TextInputBuffer>(entity).unwrap()),
+            )
+        };
+
+        let joined = edit_at(&mut world, (1, 0), TextInputEdit::Backspace);
+        assert_eq!(joined, ("abcd\nef".into(), (0, 2)));
+        let joined = edit_at(&mut world, (0, 4), TextInputEdit::Delete);
+        assert_eq!(joined, ("abcdef".into(), (0, 4)));
+
+        // Nothing to join at the start and end of the text
+        let unchanged = edit_at(&mut world, (0, 0), TextInputEdit::Backspace);
+        assert_eq!(unchanged, ("abcdef".into(), (0, 0)));
+        let unchanged = edit_at(&mut world, (0, 6), TextInputEdit::Delete);
+        assert_eq!(unchanged, ("abcdef".into(), (0, 6)));
+
+        // The filter checks the joined text
+        world
+            .get_mut::<TextInputBuffer>(entity)
+            .unwrap()
+            .set_text("ab\ncd");
+        world
+            .entity_mut(entity)
+            .insert(TextInputFilter::custom(|text| !text.contains("bc")));
+        let rejected = edit_at(&mut world, (1, 0), TextInputEdit::Backspace);
+        assert_eq!(rejected.0, "ab\ncd");
+    }
+
+    #[test]
+    fn tab_indents_each_selected_line() {
+        let mut world = queue_world();
+        let input = TextInputNode::default();
+        let entity = spawn_input(&mut world, input.clone(), "a\nb\nc\nd");
+        let mut buffer = world.get_mut::<TextInputBuffer>(entity).unwrap();
+        buffer
+            .editor
+            .set_selection(Selection::Normal(Cursor::new(0, 0)));
+        buffer.editor.set_cursor(Cursor::new(2, 1));
+        let press_tab = |world: &mut World, shift| {
+            let buffer = world.get::<TextInputBuffer>(entity).unwrap();
+            let tab = queued_actions(&input, buffer, &press(Key::Tab, KeyCode::Tab), shift, false);
+            process(world, entity, tab);
+        };
+
+        press_tab(&mut world, false);
+        let indented = text(&world, entity);
+        let lines: Vec<_> = indented.lines().collect();
+        let indent = lines[0].strip_suffix('a').unwrap();
+        assert!(!indent.is_empty() && indent.trim().is_empty());
+        assert_eq!(
+            lines,
+            [
+                format!("{indent}a"),
+                format!("{indent}b"),
+                format!("{indent}c"),
+                "d".into()
+            ]
+        );
+        let selection = world
+            .get::<TextInputBuffer>(entity)
+            .unwrap()
+            .editor
+            .selection_bounds()
+            .map(|(start, end)| ((start.line, start.index), (end.line, end.index)));
+        // The selection still covers the text of all three lines
+        assert_eq!(selection, Some(((0, indent.len()), (2, indent.len() + 1))));
+
+        press_tab(&mut world, true);
+        assert_eq!(text(&world, entity), "a\nb\nc\nd");
+    }
+
+    #[test]
+    fn password_input_masks_and_keeps_no_history_or_copy() {
+        use crate::text_input_pipeline::tests::layout_world;
+        use crate::text_input_pipeline::text_input_system;
+
+        let (mut masked_world, masked) = layout_world(TextInputNode::new_password(), "pw");
+        masked_world.run_system_once(text_input_system).unwrap();
+        let buffer = masked_world.get::<TextInputBuffer>(masked).unwrap();
+        assert_eq!(buffer.get_text(), "pw");
+        let mask = buffer.mask.as_ref().expect("password inputs are masked");
+        assert_eq!(mask.buffer.lines[0].text(), "\u{2022}\u{2022}");
+
+        let mut world = queue_world();
+        world.insert_resource(Clipboard::with_backend(Box::new(TestClipboard(
+            "clip".into(),
+        ))));
+        let entity = spawn_input(&mut world, TextInputNode::new_password(), "");
+        process(
+            &mut world,
+            entity,
+            "pw".chars()
+                .map(|ch| TextInputAction::Edit(TextInputEdit::Insert(ch, false)))
+                .chain([
+                    TextInputAction::Edit(TextInputEdit::SelectAll),
+                    TextInputAction::Copy,
+                    TextInputAction::Cut,
+                ]),
+        );
+        assert_eq!(text(&world, entity), "pw");
+        let buffer = world.get::<TextInputBuffer>(entity).unwrap();
+        assert_eq!(buffer.changes.current_command_index(), None);
+        let clipboard = world.resource_mut::<Clipboard>().fetch_text().poll_result();
+        assert!(matches!(clipboard, Some(Ok(text)) if text == "clip"));
+    }
+
+    #[cfg(feature = "regex")]
+    #[test]
+    fn regex_rejects_bad_value_on_submit() {
+        use crate::{RegexValidateMode, TextInputRegex};
+
+        let mut world = queue_world();
+        let sku = regex::Regex::new(r"^[A-Z]{3}-\d{4}$").unwrap();
+        let mut submitted = Vec::new();
+        for (value, accepted) in [("AB-12", false), ("ABC-1234", true)] {
+            let entity = spawn_input(&mut world, TextInputNode::default(), "");
+            world.entity_mut(entity).insert(TextInputRegex::new(
+                sku.clone(),
+                RegexValidateMode::OnSubmit,
+            ));
+            process(
+                &mut world,
+                entity,
+                value
+                    .chars()
+                    .map(|ch| TextInputAction::Edit(TextInputEdit::Insert(ch, false))),
+            );
+            // Partial values aren't checked while typing
+            assert_eq!(text(&world, entity), value);
+            process(&mut world, entity, [TextInputAction::Submit]);
+
+            let rejections: Vec<_> = world
+                .resource_mut::<Messages<TextInputRejected>>()
+                .drain()
+                .collect();
+            if accepted {
+                assert!(rejections.is_empty());
+            } else {
+                assert_eq!(
+                    rejections,
+                    [TextInputRejected {
+                        entity,
+                        reason: TextInputRejection::Filter,
+                    }]
+                );
+            }
+            submitted.extend(
+                world
+                    .resource_mut::<Messages<SubmitText>>()
+                    .drain()
+                    .map(|submit| submit.text),
+            );
+        }
+        assert_eq!(submitted, ["ABC-1234"]);
+    }
+
+    #[test]
+    fn wheel_scrolls_parent_once_input_reaches_bottom() {
+        use bevy::ecs::resource::Resource;
+        use bevy::picking::events::Scroll;
+
+        #[derive(Resource, Default)]
+        struct ParentScrolls(usize);
+
+        let mut world = queue_world();
+        world.init_resource::<ParentScrolls>();
+        world.add_observer(on_scroll_text_input);
+        // Pointer events only propagate in worlds that know the `Window` component
+        world.register_component::<Window>();
+        let parent = world
+            .spawn_empty()
+            .observe(
+                |_: On<Pointer<Scroll>>, mut parent_scrolls: ResMut<ParentScrolls>| {
+                    parent_scrolls.0 += 1;
+                },
+            )
+            .id();
+        let lines: Vec<_> = (0..20).map(|i| i.to_string()).collect();
+        let entity = spawn_input(&mut world, TextInputNode::default(), &lines.join("\n"));
+        world.entity_mut(entity).insert(ChildOf(parent));
+        layout(&mut world, entity);
+        let scroll_down = |world: &mut World| {
+            let hit = bevy::picking::backend::HitData::new(Entity::PLACEHOLDER, 0., None, None);
+            world.trigger(pointer(
+                entity,
+                Vec2::new(5., 5.),
+                Scroll {
+                    unit: MouseScrollUnit::Pixel,
+                    x: 0.,
+                    y: -1000.,
+                    hit,
+                },
+            ));
+            world.flush();
+            process(world, entity, []);
+            layout(world, entity);
+            world.resource::<ParentScrolls>().0
+        };
+
+        // The input scrolls to its bottom, then the parent scrolls
+        assert_eq!(scroll_down(&mut world), 0);
+        assert_eq!(scroll_down(&mut world), 1);
+    }
+
+    #[test]
+    fn tab_commit_submits_and_blurs() {
+        let mut world = queue_world();
+        world.spawn((bevy::window::Window::default(), bevy::window::PrimaryWindow));
+        world.init_resource::<Messages<KeyboardInput>>();
+        world.init_resource::<TextInputGlobalState>();
+        world.add_observer(on_focused_keyboard_input);
+        let input = TextInputNode {
+            mode: TextInputMode::SingleLine,
+            tab_commits: true,
+            clear_on_submit: false,
+            ..Default::default()
+        };
+        let entity = spawn_input(&mut world, input, "cell");
+        world.resource_mut::<InputFocus>().set(entity);
+
+        dispatch_keys(&mut world, [press(Key::Tab, KeyCode::Tab)]);
+
+        let submitted: Vec<_> = world
+            .resource_mut::<Messages<SubmitText>>()
+            .drain()
+            .map(|submit| (submit.entity, submit.text))
+            .collect();
+        assert_eq!(submitted, [(entity, "cell".to_string())]);
+        assert_eq!(world.resource::<InputFocus>().get(), None);
+    }
+
+    #[test]
+    fn real_blink_clock_blinks_while_game_time_paused() {
+        use core::time::Duration;
+
+        let mut world = queue_world();
+        // Game time never advances, as if it were paused
+        world.init_resource::<Time>();
+        let mut real_time = Time::<Real>::default();
+        // The first update only starts the clock
+        real_time.update_with_duration(Duration::ZERO);
+        world.insert_resource(real_time);
+        let entity = spawn_input(&mut world, TextInputNode::default(), "");
+        let interval = world.resource::<TextInputConfig>().blink_interval;
+        let blink_after = |world: &mut World, blink_clock| {
+            world.resource_mut::<TextInputConfig>().blink_clock = blink_clock;
+            world.get_mut::<TextInputCursorBlink>(entity).unwrap().time = 0.;
+            let mut visible = Vec::new();
+            for _ in 0..4 {
+                world
+                    .resource_mut::<Time<Real>>()
+                    .update_with_duration(Duration::from_secs_f32(0.6 * interval));
+                world.run_system_once(cursor_blink_system).unwrap();
+                visible.push(world.get::<TextInputCursorBlink>(entity).unwrap().time < interval);
+            }
+            visible
+        };
+
+        assert_eq!(
+            blink_after(&mut world, BlinkClock::Real),
+            [true, false, false, true]
+        );
+        assert_eq!(
+            blink_after(&mut world, BlinkClock::Virtual),
+            [true, true, true, true]
+        );
+    }
+
+    #[test]
+    fn text_changed_observer_fires_once_per_change() {
+        use crate::OnTextChanged;
+        use bevy::ecs::resource::Resource;
+        use bevy::ecs::schedule::{IntoScheduleConfigs, Schedule};
+
+        #[derive(Resource, Default)]
+        struct Changes(Vec<String>);
+
+        let mut world = queue_world();
+        world.init_resource::<Changes>();
+        let entity = spawn_input(&mut world, TextInputNode::default(), "");
+        world.entity_mut(entity).observe(
+            |changed: On<OnTextChanged>, mut changes: ResMut<Changes>| {
+                changes.0.push(changed.text.clone());
+            },
+        );
+        let mut schedule = Schedule::default();
+        schedule
+            .add_systems((process_text_input_queues, crate::update_text_input_contents).chain());
+        let mut run = |world: &mut World, actions: Vec<TextInputAction>| {
+            let mut queue = world.get_mut::<TextInputQueue>(entity).unwrap();
+            for action in actions {
+                queue.add(action);
+            }
+            schedule.run(world);
+        };
+
+        run(
+            &mut world,
+            vec![TextInputAction::Edit(TextInputEdit::Insert('a', false))],
+        );
+        run(
+            &mut world,
+            vec![TextInputAction::Edit(TextInputEdit::Insert('b', false))],
+        );
+        run(
+            &mut world,
+            vec![
+                TextInputAction::Edit(TextInputEdit::Motion(Motion::BufferStart, false)),
+                TextInputAction::Edit(TextInputEdit::SelectAll),
+            ],
+        );
+        run(&mut world, vec![]);
+
+        assert_eq!(world.resource::<Changes>().0, ["a", "ab"]);
+        // The `TextChanged` message is sent for the same changes
+        let messages: Vec<_> = world
+            .resource_mut::<Messages<crate::TextChanged>>()
+            .drain()
+            .map(|changed| (changed.entity, changed.text))
+            .collect();
+        assert_eq!(
+            messages,
+            [(entity, String::from("a")), (entity, String::from("ab"))]
+        );
+    }
+
+    #[test]
+    fn arrows_move_past_whole_grapheme() {
+        let mut world = queue_world();
+        let input = TextInputNode::default();
+        let family = "👨‍👩‍👧";
+        let entity = spawn_input(&mut world, input.clone(), &format!("a{family}b"));
+        layout(&mut world, entity);
+        world
+            .get_mut::<TextInputBuffer>(entity)
+            .unwrap()
+            .editor
+            .set_cursor(Cursor::new(0, 1));
+
+        for (key, code, expected) in [
+            (Key::ArrowRight, KeyCode::ArrowRight, 1 + family.len()),
+            (Key::ArrowLeft, KeyCode::ArrowLeft, 1),
+        ] {
+            let buffer = world.get::<TextInputBuffer>(entity).unwrap();
+            let arrow = queued_actions(&input, buffer, &press(key, code), false, false);
+            process(&mut world, entity, arrow);
+            let buffer = world.get::<TextInputBuffer>(entity).unwrap();
+            assert_eq!(cursor_position(buffer), (0, expected));
+        }
+    }
+
+    #[test]
+    fn submit_empty_controls_blank_submissions() {
+        let mut world = queue_world();
+        world.spawn((bevy::window::Window::default(), bevy::window::PrimaryWindow));
+        world.init_resource::<Messages<KeyboardInput>>();
+        world.init_resource::<TextInputGlobalState>();
+        world.add_observer(on_focused_keyboard_input);
+
+        for submit_empty in [false, true] {
+            let input = TextInputNode {
+                mode: TextInputMode::SingleLine,
+                tab_commits: true,
+                submit_empty,
+                ..Default::default()
+            };
+            let entity = spawn_input(&mut world, input, "x");
+            process(
+                &mut world,
+                entity,
+                [TextInputEdit::SelectAll, TextInputEdit::Delete].map(TextInputAction::Edit),
+            );
+            for key in [
+                press(Key::Enter, KeyCode::Enter),
+                press(Key::Tab, KeyCode::Tab),
+            ] {
+                world.resource_mut::<InputFocus>().set(entity);
+                dispatch_keys(&mut world, [key]);
+
+                let submitted: Vec<_> = world
+                    .resource_mut::<Messages<SubmitText>>()
+                    .drain()
+                    .map(|submit| (submit.entity, submit.text))
+                    .collect();
+                if submit_empty {
+                    assert_eq!(submitted, [(entity, String::new())]);
+                } else {
+                    assert!(submitted.is_empty());
+                    // A suppressed tab commit doesn't unfocus the input either
+                    assert_eq!(world.resource::<InputFocus>().get(), Some(entity));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn double_click_survives_pointer_jitter() {
+        use bevy::picking::events::{Click, Move};
+
+        let mut world = queue_world();
+        world.add_observer(on_multi_click_set_selection);
+        world.add_observer(on_move_clear_multi_click);
+        let hit = || bevy::picking::backend::HitData::new(Entity::PLACEHOLDER, 0., None, None);
+        let click = |entity, position| {
+            pointer(
+                entity,
+                position,
+                Click {
+                    button: PointerButton::Primary,
+                    hit: hit(),
+                    duration: Default::default(),
+                },
+            )
+        };
+
+        // 2px of jitter stays within the default multi-click distance, 10px doesn't
+        for (jitter, double_clicked) in [(2., true), (10., false)] {
+            let entity = spawn_input(&mut world, TextInputNode::default(), "hello world");
+            let width = layout(&mut world, entity);
+            let first = Vec2::new(2.5 * width, 5.);
+            let second = first + Vec2::new(jitter, 0.);
+            world.trigger(click(entity, first));
+            world.flush();
+            world.trigger(pointer(
+                entity,
+                second,
+                Move {
+                    hit: hit(),
+                    delta: Vec2::new(jitter, 0.),
+                },
+            ));
+            world.flush();
+            world.trigger(click(entity, second));
+            world.flush();
+            process(&mut world, entity, []);
+
+            let selected = world
+                .get_mut::<TextInputBuffer>(entity)
+                .unwrap()
+                .editor
+                .copy_selection();
+            assert_eq!(
+                selected.as_deref() == Some("hello"),
+                double_clicked,
+                "{jitter}"
+            );
+        }
+    }
+
+    #[test]
+    fn motion_mask_ignores_disallowed_motions() {
+        let mut world = queue_world();
+        for (mask, expected) in [
+            (None, (0, 0)),
+            (Some(TextInputMotionMask::WORD), (0, 5)),
+            (Some(TextInputMotionMask::empty()), (0, 10)),
+        ] {
+            let entity = spawn_input(&mut world, TextInputNode::default(), "hello world");
+            if let Some(mask) = mask {
+                world.entity_mut(entity).insert(mask);
+            }
+            process(
+                &mut world,
+                entity,
+                [
+                    Motion::ParagraphStart,
+                    Motion::PreviousWord,
+                    Motion::Previous,
+                ]
+                .map(|motion| TextInputAction::Edit(TextInputEdit::Motion(motion, false))),
+            );
+            let buffer = world.get::<TextInputBuffer>(entity).unwrap();
+            assert_eq!(cursor_position(buffer), expected, "{mask:?}");
+        }
+    }
+
+    #[test]
+    fn copy_formatter_formats_copied_and_cut_text() {
+        let mut world = queue_world();
+        let entity = spawn_input(&mut world, TextInputNode::default(), "ab\ncd");
+        world.entity_mut(entity).insert(CopyFormatter::new(|text| {
+            text.lines()
+                .enumerate()
+                .map(|(i, line)| format!("{} {line}", i + 1))
+                .collect::<Vec<_>>()
+                .join("\n")
+        }));
+        let clipboard = |world: &mut World| {
+            world
+                .resource_mut::<Clipboard>()
+                .fetch_text()
+                .poll_result()
+                .unwrap()
+                .unwrap()
+        };
+
+        process(
+            &mut world,
+            entity,
+            [
+                TextInputAction::Edit(TextInputEdit::SelectAll),
+                TextInputAction::Copy,
+            ],
+        );
+        assert_eq!(clipboard(&mut world), "1 ab\n2 cd");
+        assert_eq!(text(&world, entity), "ab\ncd");
+
+        // Cut deletes the unformatted selection
+        process(&mut world, entity, [TextInputAction::Cut]);
+        assert_eq!(clipboard(&mut world), "1 ab\n2 cd");
+        assert_eq!(text(&world, entity), "");
+    }
+
+    #[test]
+    fn plugin_clipboard_backend_copies_and_pastes() {
+        use std::sync::{Arc, Mutex};
+
+        struct SharedClipboard(Arc<Mutex<String>>);
+
+        impl ClipboardBackend for SharedClipboard {
+            fn get_text(&mut self) -> Result<String, ClipboardError> {
+                Ok(self.0.lock().unwrap().clone())
+            }
+
+            fn set_text(&mut self, text: String) -> Result<(), ClipboardError> {
+                *self.0.lock().unwrap() = text;
+                Ok(())
+            }
+        }
+
+        let contents = Arc::new(Mutex::new(String::new()));
+        let mut app = bevy::app::App::new();
+        app.add_plugins(
+            crate::TextInputPlugin::headless()
+                .with_clipboard_backend(SharedClipboard(contents.clone())),
+        );
+        let clipboard = app.world_mut().remove_resource::<Clipboard>().unwrap();
+
+        let mut world = queue_world();
+        world.insert_resource(clipboard);
+        let entity = spawn_input(&mut world, TextInputNode::default(), "abc");
+        process(
+            &mut world,
+            entity,
+            [
+                TextInputAction::Edit(TextInputEdit::SelectAll),
+                TextInputAction::Copy,
+            ],
+        );
+        assert_eq!(*contents.lock().unwrap(), "abc");
+
+        *contents.lock().unwrap() = "xyz".into();
+        process(&mut world, entity, [TextInputAction::Paste]);
+        assert_eq!(text(&world, entity), "xyz");
     }
 }
@@ -0,0 +1,145 @@
+use bevy::ecs::component::Component;
+use bevy::ecs::entity::Entity;
+use bevy::ecs::query::QueryData;
+use bevy::ecs::system::Query;
+use bevy::platform::collections::HashMap;
+
+use crate::TextInputBuffer;
+
+/// Groups text inputs into a form, for saving and restoring their values together with
+/// [`collect_form`] and [`apply_form`].
+#[derive(Component, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct TextInputGroup(pub u32);
+
+/// Stable app-provided id for a text input in a form.
+///
+/// Entities change between sessions, ids don't, so forms collected from inputs with ids can be
+/// saved and applied again after a restart.
+#[derive(Component, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct TextInputId(pub String);
+
+/// Key of a text input's value in a form snapshot
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum FormKey {
+    /// The input's `TextInputId`
+    Id(String),
+    /// The input's entity, for inputs without a `TextInputId`
+    Entity(Entity),
+}
+
+/// Access to the text inputs in a form, for [`collect_form`] and [`apply_form`].
+///
+/// ```ignore
+/// fn save(fields: Query<TextInputFormField>) {
+///     let form = collect_form(TextInputGroup(0), &fields);
+/// }
+/// ```
+#[derive(QueryData)]
+#[query_data(mutable)]
+pub struct TextInputFormField {
+    pub entity: Entity,
+    pub group: &'static TextInputGroup,
+    pub id: Option<&'static TextInputId>,
+    pub buffer: &'static mut TextInputBuffer,
+}
+
+fn form_key(entity: Entity, id: Option<&TextInputId>) -> FormKey {
+    id.map_or(FormKey::Entity(entity), |id| FormKey::Id(id.0.clone()))
+}
+
+/// Snapshot of the text of every input in `group`, keyed by `TextInputId` where present and by
+/// entity otherwise
+pub fn collect_form(
+    group: TextInputGroup,
+    fields: &Query<TextInputFormField>,
+) -> HashMap<FormKey, String> {
+    fields
+        .iter()
+        .filter(|field| *field.group == group)
+        .map(|field| (form_key(field.entity, field.id), field.buffer.get_text()))
+        .collect()
+}
+
+/// Set the text of each input in `group` that has a value in `form`, using
+/// [`TextInputBuffer::set_text`]. Inputs without a value are left unchanged.
+///
+/// Returns the number of inputs whose text was set.
+pub fn apply_form(
+    group: TextInputGroup,
+    form: &HashMap<FormKey, String>,
+    fields: &mut Query<TextInputFormField>,
+) -> usize {
+    let mut count = 0;
+    for mut field in fields.iter_mut() {
+        if *field.group != group {
+            continue;
+        }
+        if let Some(text) = form.get(&form_key(field.entity, field.id)) {
+            field.buffer.set_text(text);
+            count += 1;
+        }
+    }
+    count
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bevy::ecs::system::RunSystemOnce;
+    use bevy::ecs::world::World;
+
+    fn spawn_field(world: &mut World, group: u32, id: Option<&str>, text: &str) -> Entity {
+        let mut buffer = TextInputBuffer::default();
+        buffer.set_text(text);
+        let mut field = world.spawn((TextInputGroup(group), buffer));
+        if let Some(id) = id {
+            field.insert(TextInputId(id.into()));
+        }
+        field.id()
+    }
+
+    fn text(world: &World, entity: Entity) -> String {
+        world.get::<TextInputBuffer>(entity).unwrap().get_text()
+    }
+
+    #[test]
+    fn form_round_trip() {
+        let mut world = World::new();
+        let name = spawn_field(&mut world, 0, Some("name"), "Ada");
+        let notes = spawn_field(&mut world, 0, None, "line one\nline two");
+        let other = spawn_field(&mut world, 1, Some("name"), "other form");
+
+        let form = world
+            .run_system_once(|fields: Query<TextInputFormField>| {
+                collect_form(TextInputGroup(0), &fields)
+            })
+            .unwrap();
+        assert_eq!(
+            form,
+            HashMap::from_iter([
+                (FormKey::Id("name".into()), "Ada".to_string()),
+                (FormKey::Entity(notes), "line one\nline two".to_string()),
+            ])
+        );
+
+        for entity in [name, notes, other] {
+            world
+                .get_mut::<TextInputBuffer>(entity)
+                .unwrap()
+                .set_text("edited");
+        }
+        // Inputs are matched by id, so a new input with the same id is restored too
+        let restored_name = spawn_field(&mut world, 0, Some("name"), "");
+
+        let applied = world
+            .run_system_once(move |mut fields: Query<TextInputFormField>| {
+                apply_form(TextInputGroup(0), &form, &mut fields)
+            })
+            .unwrap();
+        assert_eq!(applied, 3);
+        assert_eq!(text(&world, name), "Ada");
+        assert_eq!(text(&world, restored_name), "Ada");
+        assert_eq!(text(&world, notes), "line one\nline two");
+        assert_eq!(text(&world, other), "edited");
+    }
+}
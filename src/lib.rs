@@ -1,90 +1,281 @@
 pub mod actions;
 pub mod clipboard;
 pub mod edit;
+pub mod form;
+pub mod query;
 pub mod render;
 pub mod text_input_pipeline;
 
 use std::collections::VecDeque;
+use std::ops::Range;
+use std::sync::{Mutex, PoisonError};
 
-use actions::TextInputAction;
-use bevy::app::{Plugin, PostUpdate};
+use actions::{TextInputAction, TextInputEdit, TextInputRejection};
+use bevy::app::{Plugin, PostUpdate, PreUpdate};
 use bevy::asset::AssetEventSystems;
 use bevy::color::Color;
 use bevy::color::palettes::css::SKY_BLUE;
 use bevy::color::palettes::tailwind::GRAY_400;
+use bevy::ecs::change_detection::{DetectChanges, DetectChangesMut};
 use bevy::ecs::component::Component;
 use bevy::ecs::entity::Entity;
+use bevy::ecs::event::EntityEvent;
 use bevy::ecs::lifecycle::HookContext;
-use bevy::ecs::message::Message;
+use bevy::ecs::message::{Message, MessageReader, MessageWriter};
 use bevy::ecs::observer::Observer;
 use bevy::ecs::query::Changed;
 use bevy::ecs::resource::Resource;
 use bevy::ecs::schedule::IntoScheduleConfigs;
-use bevy::ecs::system::{Commands, Query};
-use bevy::ecs::world::DeferredWorld;
-use bevy::input_focus::InputFocus;
+use bevy::ecs::schedule::common_conditions::{any_with_component, resource_exists};
+use bevy::ecs::system::{Commands, Query, Res};
+use bevy::ecs::world::{DeferredWorld, Ref};
+use bevy::input::keyboard::KeyCode;
+use bevy::input_focus::{InputFocus, InputFocusSystems};
 use bevy::math::{Rect, Vec2};
+use bevy::picking::hover::HoverMap;
+use bevy::platform::collections::HashMap;
 use bevy::prelude::ReflectComponent;
 use bevy::reflect::{Reflect, std_traits::ReflectDefault};
 use bevy::render::{ExtractSchedule, RenderApp};
 use bevy::text::{GlyphAtlasInfo, LineHeight, TextFont};
 use bevy::text::{Justify, TextColor};
-use bevy::ui::{Node, UiSystems};
+use bevy::ui::widget::Text;
+use bevy::ui::{ComputedNode, Node, UiSystems};
 use bevy::ui_render::{RenderUiSystems, extract_text_sections};
-use cosmic_text::{Buffer, Change, Edit, Editor, Metrics, Wrap};
+use clipboard::ClipboardBackend;
+use cosmic_text::{
+    BorrowedWithFontSystem, Buffer, Change, Cursor, Edit, Editor, LineEnding, Metrics, Motion,
+    Selection, Wrap,
+};
 use edit::{
-    cursor_blink_system, mouse_wheel_scroll, on_drag_text_input, on_focused_keyboard_input,
-    on_move_clear_multi_click, on_multi_click_set_selection, on_text_input_pressed,
-    process_text_input_queues,
+    blur_on_outside_click_system, caret_moved_system, caret_rect_system, cursor_blink_system,
+    intercept_text_input_edits, on_drag_text_input, on_focused_keyboard_input,
+    on_move_clear_multi_click, on_multi_click_set_selection, on_scroll_text_input,
+    on_text_input_middle_pressed, on_text_input_pressed, process_text_input_queues,
+    scroll_to_caret_on_focus_system, select_all_on_focus_system, text_input_cursor_icon_system,
+    track_modifier_keys_system,
 };
-use render::{extract_text_input_nodes, extract_text_input_prompts};
+use render::{extract_text_input_gutters, extract_text_input_nodes, extract_text_input_prompts};
 use text_input_pipeline::{
-    TextInputPipeline, remove_dropped_font_atlas_sets_from_text_input_pipeline,
+    TextInputPipeline, relayout_text_inputs_on_font_events,
+    remove_dropped_font_atlas_sets_from_text_input_pipeline, text_input_gutter_system,
     text_input_prompt_system, text_input_system,
 };
+use unicode_segmentation::UnicodeSegmentation;
+
+/// Adds text input support to Bevy UI.
+///
+/// App-wide defaults can be set using the builder methods:
+/// ```ignore
+/// app.add_plugins(TextInputPlugin::default().with_blink_interval(0.6));
+/// ```
+pub struct TextInputPlugin {
+    /// Global configuration, inserted as the `TextInputConfig` resource
+    pub config: TextInputConfig,
+    /// Add bevy's `InputDispatchPlugin` if the app doesn't have it when this plugin is built
+    pub add_input_dispatch: bool,
+    /// Also process queued actions in `PreUpdate`, see `with_actions_in_pre_update`
+    pub process_actions_in_pre_update: bool,
+    /// Add the systems that extract text inputs for rendering, see `headless`
+    pub extract: bool,
+    /// Clipboard used instead of the system clipboard, set by `with_clipboard_backend`.
+    /// Taken when the plugin is built, which only has shared access to the plugin.
+    clipboard_backend: Mutex<Option<Box<dyn ClipboardBackend>>>,
+}
+
+impl Default for TextInputPlugin {
+    fn default() -> Self {
+        Self {
+            config: TextInputConfig::default(),
+            add_input_dispatch: true,
+            process_actions_in_pre_update: false,
+            extract: true,
+            clipboard_backend: Mutex::new(None),
+        }
+    }
+}
+
+impl TextInputPlugin {
+    /// Plugin for apps without rendering, such as servers or headless simulations.
+    ///
+    /// Text inputs are still edited, laid out and have their `TextInputContents` updated, but no
+    /// extraction systems are added to the `RenderApp`.
+    pub fn headless() -> Self {
+        Self {
+            extract: false,
+            ..Default::default()
+        }
+    }
+
+    /// Set the default cursor blink interval in seconds.
+    /// Overridden by `TextInputStyle::blink_interval`.
+    pub fn with_blink_interval(mut self, blink_interval: f32) -> Self {
+        self.config.blink_interval = blink_interval;
+        self
+    }
 
-pub struct TextInputPlugin;
+    /// Don't add bevy's `InputDispatchPlugin`. The app must add it itself, or otherwise provide the
+    /// `InputFocus` resource and dispatch `FocusedInput` keyboard events, before it runs.
+    ///
+    /// Use this if the app or another plugin group adds `InputDispatchPlugin` after this plugin,
+    /// otherwise it is added twice and bevy panics.
+    pub fn without_input_dispatch(mut self) -> Self {
+        self.add_input_dispatch = false;
+        self
+    }
+
+    /// Also process the queued actions of text inputs in `PreUpdate`, after input focus dispatch,
+    /// and update their `TextInputContents`. Keyboard edits are then visible to `Update` systems
+    /// in the same frame, instead of the next.
+    ///
+    /// Edited text is still only shaped and laid out in `PostUpdate`, so until then the buffer's
+    /// layout, such as `caret_pixel_for_offset` and `selection_bounds_rect`, is from before the edits.
+    /// Actions queued after `PreUpdate` are processed in `PostUpdate` as usual.
+    pub fn with_actions_in_pre_update(mut self) -> Self {
+        self.process_actions_in_pre_update = true;
+        self
+    }
+
+    /// Set the mouse wheel scroll speed multiplier.
+    pub fn with_scroll_speed(mut self, scroll_speed: f32) -> Self {
+        self.config.scroll_speed = scroll_speed;
+        self
+    }
+
+    /// Add key bindings that take precedence over the built-in ones.
+    pub fn with_keymap(mut self, keymap: TextInputKeymap) -> Self {
+        self.config.keymap = keymap;
+        self
+    }
+
+    /// Use `backend` for copying and pasting instead of the system clipboard.
+    pub fn with_clipboard_backend(mut self, backend: impl ClipboardBackend) -> Self {
+        self.clipboard_backend = Mutex::new(Some(Box::new(backend)));
+        self
+    }
+
+    /// Set the clock that advances the cursor blink.
+    /// Use `BlinkClock::Real` to keep the cursor blinking while virtual time is paused.
+    pub fn with_blink_clock(mut self, blink_clock: BlinkClock) -> Self {
+        self.config.blink_clock = blink_clock;
+        self
+    }
+
+    /// Set how far in logical pixels the pointer can move between the clicks of a double or
+    /// triple click.
+    pub fn with_multi_click_distance(mut self, multi_click_distance: f32) -> Self {
+        self.config.multi_click_distance = multi_click_distance;
+        self
+    }
+}
 
 impl Plugin for TextInputPlugin {
     fn build(&self, app: &mut bevy::app::App) {
-        if !app.is_plugin_added::<bevy::input_focus::InputDispatchPlugin>() {
+        if self.add_input_dispatch
+            && !app.is_plugin_added::<bevy::input_focus::InputDispatchPlugin>()
+        {
             app.add_plugins(bevy::input_focus::InputDispatchPlugin);
         }
 
-        app.add_message::<SubmitText>()
+        app.insert_resource(self.config.clone())
+            .add_message::<SubmitText>()
+            .add_message::<PasteFailed>()
+            .add_message::<PasteTooLarge>()
+            .add_message::<CaretMoved>()
+            .add_message::<TextChanged>()
+            .add_message::<CompletionCandidates>()
+            .add_message::<TextInputRejected>()
             .init_resource::<TextInputGlobalState>()
             .init_resource::<TextInputPipeline>()
-            .init_resource::<clipboard::Clipboard>()
+            .add_systems(
+                PreUpdate,
+                track_modifier_keys_system.before(InputFocusSystems::Dispatch),
+            )
             .add_systems(
                 PostUpdate,
                 (
                     remove_dropped_font_atlas_sets_from_text_input_pipeline
                         .before(AssetEventSystems),
+                    relayout_text_inputs_on_font_events.before(AssetEventSystems),
                     (
+                        // Pointer systems need picking's `InteractionPlugin`, which apps without
+                        // rendering may not add
+                        blur_on_outside_click_system.run_if(resource_exists::<HoverMap>),
                         cursor_blink_system,
-                        mouse_wheel_scroll,
+                        text_input_cursor_icon_system.run_if(resource_exists::<HoverMap>),
+                        scroll_to_caret_on_focus_system,
+                        select_all_on_focus_system,
+                        intercept_text_input_edits.run_if(any_with_component::<InterceptEdits>),
                         process_text_input_queues,
                         update_text_input_contents,
+                        mirror_text_inputs,
+                        caret_moved_system,
                         text_input_system,
+                        caret_rect_system,
                         text_input_prompt_system,
+                        text_input_gutter_system,
+                        update_text_input_state,
+                        text_input_hint_system,
                     )
                         .chain()
                         .in_set(UiSystems::PostLayout),
                 ),
             );
 
+        let clipboard_backend = self
+            .clipboard_backend
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .take();
+        if let Some(backend) = clipboard_backend {
+            app.insert_resource(clipboard::Clipboard::with_backend(backend));
+        } else {
+            app.init_resource::<clipboard::Clipboard>();
+        }
+
+        if self.process_actions_in_pre_update {
+            app.add_systems(
+                PreUpdate,
+                (
+                    intercept_text_input_edits.run_if(any_with_component::<InterceptEdits>),
+                    process_text_input_queues,
+                    update_text_input_contents,
+                )
+                    .chain()
+                    .after(InputFocusSystems::Dispatch),
+            );
+        }
+
+        if !self.extract {
+            return;
+        }
+
         let Some(render_app) = app.get_sub_app_mut(RenderApp) else {
             return;
         };
 
         render_app.add_systems(
             ExtractSchedule,
-            (extract_text_input_prompts, extract_text_input_nodes)
+            (
+                extract_text_input_prompts,
+                extract_text_input_gutters,
+                extract_text_input_nodes,
+            )
                 .chain()
                 .in_set(RenderUiSystems::ExtractText)
                 .after(extract_text_sections),
         );
     }
+
+    fn finish(&self, app: &mut bevy::app::App) {
+        // Checked once every plugin is built, so that `InputDispatchPlugin` can be added after this
+        // plugin when using `without_input_dispatch`
+        assert!(
+            app.world().contains_resource::<InputFocus>(),
+            "`TextInputPlugin` requires the `InputFocus` resource. \
+            Add bevy's `InputDispatchPlugin` when using `TextInputPlugin::without_input_dispatch`."
+        );
+    }
 }
 
 #[derive(Component, Debug, Clone)]
@@ -96,7 +287,9 @@ impl Plugin for TextInputPlugin {
     TextInputLayoutInfo,
     TextInputStyle,
     TextColor,
-    TextInputQueue
+    TextInputQueue,
+    TextInputState,
+    TextInputCursorBlink
 )]
 #[component(
     on_add = on_add_textinputnode,
@@ -106,13 +299,21 @@ pub struct TextInputNode {
     /// Whether the text should be cleared on submission
     /// (Shift-Enter or just Enter in single-line mode)
     pub clear_on_submit: bool,
+    /// Submit text that is empty or only whitespace. If false, such submissions are ignored,
+    /// no `SubmitText` is sent and the input isn't cleared.
+    pub submit_empty: bool,
+    /// Modifier key that submits with Enter in multi-line mode
+    pub submit_modifier: SubmitModifier,
     /// Type of text input
     pub mode: TextInputMode,
     /// Maximum number of characters that can entered into the input buffer
     pub max_chars: Option<usize>,
     /// Should overwrite mode be available
     pub allow_overwrite_mode: bool,
-    /// Can the text input be activated
+    /// Can the text input be focused and edited.
+    ///
+    /// This is independent of focus, a text input is focused when it is the entity held
+    /// by the `InputFocus` resource. Use [`is_focused`] to check.
     pub is_enabled: bool,
     /// Activate on pointer down
     pub focus_on_pointer_down: bool,
@@ -120,12 +321,91 @@ pub struct TextInputNode {
     pub unfocus_on_submit: bool,
     /// Text justification
     pub justification: Justify,
+    /// Middle-click places the cursor and pastes the primary selection.
+    /// Uses the regular clipboard on platforms without a primary selection.
+    /// Like other presses, ignored unless `focus_on_pointer_down` is set.
+    pub middle_click_paste: bool,
+    /// Can text be selected.
+    /// If false, clicks only place the cursor, dragging does nothing, shift + motions
+    /// move the cursor without selecting and select all is ignored.
+    pub allow_selection: bool,
+    /// If selection is disabled, copy and cut act on the whole value instead of doing nothing.
+    pub copy_whole_value: bool,
+    /// In multi-line mode, Shift+Enter inserts a soft line break and Enter a paragraph break.
+    /// Soft breaks are represented by `LINE_SEPARATOR` in the input's text.
+    /// With the default `SubmitModifier::Shift`, Ctrl+Enter submits instead.
+    pub soft_breaks: bool,
+    /// Limits the width of the text area to this many columns, measured using the advance width
+    /// of the font's '0' glyph. Lines wrap or scroll at this width even if the node is wider.
+    /// Exact for monospace fonts, only approximate for proportional fonts.
+    pub max_width_chars: Option<usize>,
+    /// Unfocus the input when the primary mouse button is pressed outside of it.
+    /// Presses on entities with `KeepTextInputFocus`, or their descendants, are ignored.
+    pub blur_on_outside_click: bool,
+    /// Unfocus the input when Escape is pressed
+    pub blur_on_escape: bool,
+    /// In single-line mode, Tab and Shift + Tab submit the text and unfocus the input, for
+    /// editing a single cell or label in place. The input stays focused if the submission is
+    /// rejected or suppressed.
+    ///
+    /// Takes precedence over tab navigation, the key event isn't propagated to the
+    /// `TabNavigation` observers. A `TextInputCompletion` on the input takes precedence over this.
+    pub tab_commits: bool,
+    /// Conversion applied to the indentation of pasted text
+    pub paste_indentation: PasteIndentation,
+    /// Re-indent the lines of pasted text after the first to the indentation of the cursor's line,
+    /// keeping their indentation relative to each other.
+    pub reindent_paste: bool,
+    /// On submission, discard the in-progress input method composition instead of committing it
+    /// before the text is read.
+    pub discard_preedit_on_submit: bool,
+    /// Time in seconds the cursor stays solid after the last input, before it resumes blinking
+    pub blink_pause_while_typing: f32,
+    /// Maximum number of characters inserted by a single paste from the clipboard.
+    /// Longer pastes are truncated to this length and a `PasteTooLarge` message is sent.
+    /// Independent of `max_chars`, which limits the length of the whole text.
+    pub max_paste_chars: Option<usize>,
+    /// In single-line mode, switch to multi-line mode with the default wrapping once the text
+    /// contains a line break, for example after pasting multiple lines. After the switch, Enter
+    /// behaves as in any multi-line input. The switch is one-way, the input stays multi-line
+    /// if the line breaks are removed.
+    pub promote_on_newline: bool,
+    /// What happens to a paste that would make the text longer than `max_chars`
+    pub paste_overflow: PasteOverflow,
+    /// When to select all of the text when the input gains focus
+    pub select_all_on_focus: SelectAllOnFocus,
+    /// Scroll the cursor into view when the input gains focus from the keyboard or programmatically.
+    /// Focus from a pointer press places the cursor where the input was pressed instead.
+    pub scroll_to_caret_on_focus: bool,
+    /// Line breaks used in submitted and copied text.
+    /// Pasted line breaks are always stored as `\n`, and `get_text` and `TextInputContents` use `\n`.
+    pub output_newline: NewlineStyle,
+    /// For passwords and other secrets. Edits aren't recorded in the undo history or by a
+    /// `TextInputRecorder`, and copy and cut do nothing.
+    pub secure: bool,
+    /// How a single-line input shows text that is wider than the input while it isn't focused
+    pub overflow_indicator: Overflow,
+    /// Remove control characters and bidirectional formatting characters from typed and pasted text,
+    /// apart from those in `allowed_control_chars`.
+    /// Programmatic changes with `TextInputBuffer::set_text` aren't sanitized.
+    pub sanitize: bool,
+    /// Characters kept by sanitization, `\n` and `\t` by default
+    pub allowed_control_chars: Vec<char>,
+    /// For password fields. Implies `secure`, and the text is drawn as one `mask_char` per grapheme
+    /// unless `show_password` is set. See [`TextInputNode::new_password`].
+    pub password: bool,
+    /// Draw the text of a `password` input instead of masking it. Its edits stay secure.
+    pub show_password: bool,
+    /// Character drawn in place of each grapheme of a masked `password` input, `•` by default
+    pub mask_char: char,
 }
 
 impl Default for TextInputNode {
     fn default() -> Self {
         Self {
             clear_on_submit: true,
+            submit_empty: true,
+            submit_modifier: SubmitModifier::Shift,
             mode: TextInputMode::default(),
             max_chars: None,
             allow_overwrite_mode: true,
@@ -133,16 +413,85 @@ impl Default for TextInputNode {
             focus_on_pointer_down: true,
             unfocus_on_submit: true,
             justification: Justify::Left,
+            middle_click_paste: false,
+            allow_selection: true,
+            copy_whole_value: false,
+            soft_breaks: false,
+            max_width_chars: None,
+            blur_on_outside_click: false,
+            blur_on_escape: false,
+            tab_commits: false,
+            paste_indentation: PasteIndentation::Keep,
+            reindent_paste: false,
+            discard_preedit_on_submit: false,
+            blink_pause_while_typing: 0.,
+            max_paste_chars: None,
+            promote_on_newline: false,
+            paste_overflow: PasteOverflow::Truncate,
+            select_all_on_focus: SelectAllOnFocus::Never,
+            scroll_to_caret_on_focus: true,
+            output_newline: NewlineStyle::Lf,
+            secure: false,
+            overflow_indicator: Overflow::Scroll,
+            sanitize: true,
+            allowed_control_chars: vec!['\n', '\t'],
+            password: false,
+            show_password: false,
+            mask_char: '\u{2022}',
+        }
+    }
+}
+
+impl TextInputNode {
+    /// A single-line `password` input
+    pub fn new_password() -> Self {
+        Self {
+            mode: TextInputMode::SingleLine,
+            password: true,
+            ..Default::default()
         }
     }
+
+    /// True if the input's edits aren't recorded and it can't be copied from, see `secure`
+    pub fn is_secure(&self) -> bool {
+        self.secure || self.password
+    }
+
+    /// True if the input's text is drawn masked, see `password`
+    pub fn is_masked(&self) -> bool {
+        self.password && !self.show_password
+    }
+}
+
+/// Returns true if `entity` is the currently focused entity.
+///
+/// Focus is tracked only by the `InputFocus` resource. A text input can be focused
+/// while disabled (see [`TextInputNode::is_enabled`]), but it won't accept any input.
+pub fn is_focused(entity: Entity, input_focus: &InputFocus) -> bool {
+    input_focus.0 == Some(entity)
+}
+
+/// Returns the focused entity if it is a text input, or `None` if nothing is focused
+/// or the focused entity is some other kind of widget.
+pub fn focused_text_input(
+    input_focus: &InputFocus,
+    query: &Query<&TextInputNode>,
+) -> Option<Entity> {
+    input_focus.0.filter(|entity| query.contains(*entity))
 }
 
 fn on_add_textinputnode(mut world: DeferredWorld, context: HookContext) {
+    if world.entity(context.entity).contains::<ManualInput>() {
+        return;
+    }
+
     for mut observer in [
         Observer::new(on_drag_text_input),
         Observer::new(on_text_input_pressed),
+        Observer::new(on_text_input_middle_pressed),
         Observer::new(on_multi_click_set_selection),
         Observer::new(on_move_clear_multi_click),
+        Observer::new(on_scroll_text_input),
         Observer::new(on_focused_keyboard_input),
     ] {
         observer.watch_entity(context.entity);
@@ -169,6 +518,225 @@ pub struct SubmitText {
     pub text: String,
 }
 
+/// Sent by `update_text_input_contents` when a text input's text changes, after its
+/// `TextInputContents` is updated. Not sent on cursor movement or selection.
+///
+/// Read it to handle the changes of all text inputs in one system, or observe [`OnTextChanged`]
+/// on a single input.
+#[derive(Message, Debug, Clone, PartialEq, Eq)]
+pub struct TextChanged {
+    /// The text input entity whose text changed
+    pub entity: Entity,
+    /// The new text
+    pub text: String,
+}
+
+/// Triggered on a text input by `update_text_input_contents` when its text changes, after its
+/// `TextInputContents` is updated, alongside the [`TextChanged`] message. Not triggered by cursor
+/// movement or selection.
+///
+/// Observe it on the input entity to keep per-input logic, such as live validation, with the
+/// input's spawn code:
+/// ```ignore
+/// commands
+///     .spawn(TextInputNode::default())
+///     .observe(|changed: On<OnTextChanged>| info!("{}", changed.text));
+/// ```
+#[derive(EntityEvent, Debug, Clone)]
+pub struct OnTextChanged {
+    /// The text input entity whose text changed
+    pub entity: Entity,
+    /// The new text
+    pub text: String,
+}
+
+/// Triggered on a text input with the `InterceptEdits` component before each of its queued edits
+/// is applied. Observers can inspect or replace the edit, or set `cancel` to skip it.
+///
+/// Pasted text is applied after the clipboard read completes and isn't intercepted,
+/// use a `TextInputFilter` to restrict pastes.
+#[derive(EntityEvent, Debug)]
+pub struct OnBeforeEdit {
+    /// The text input entity the edit will be applied to
+    pub entity: Entity,
+    /// The edit to apply
+    pub edit: TextInputEdit,
+    /// If true the edit is skipped
+    pub cancel: bool,
+}
+
+/// Marker component that enables `OnBeforeEdit` triggers for a text input.
+/// Inputs without it don't pay the cost of triggering an event per edit.
+#[derive(Component, Default, Debug, Clone, Copy)]
+pub struct InterceptEdits;
+
+/// Marker component that stops a text input from handling keyboard and pointer input itself,
+/// so the app can drive it by adding actions to its `TextInputQueue` instead.
+/// Layout and rendering are unaffected.
+///
+/// Must be inserted together with the `TextInputNode`, as the input observers are spawned when
+/// the `TextInputNode` is added.
+#[derive(Component, Default, Debug, Clone, Copy)]
+pub struct ManualInput;
+
+/// Marker component for entities, such as submit or clear buttons, that don't unfocus a text input
+/// with `TextInputNode::blur_on_outside_click` when pressed. Applies to the entity's descendants too.
+#[derive(Component, Default, Debug, Clone, Copy)]
+pub struct KeepTextInputFocus;
+
+/// Sent when a text input's cursor moves
+#[derive(Message, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CaretMoved {
+    /// The text input entity whose cursor moved
+    pub entity: Entity,
+    /// The line the cursor is on
+    pub line: usize,
+    /// The cursor's column in chars
+    pub column: usize,
+    /// The cursor's offset from the start of the text in chars, including newlines
+    pub char_offset: usize,
+}
+
+/// The on-screen rect of a text input's cursor, updated by `caret_rect_system` after layout.
+/// Add it to a text input to position floating UI, like completion popups, at its cursor.
+///
+/// In logical pixels from the top-left of the window, usable as a `Node`'s `left` and `top`.
+/// `None` while the input is unfocused or disabled, or its cursor is scrolled out of view.
+#[derive(Component, Default, Debug, Clone, Copy, PartialEq)]
+pub struct CaretRect(pub Option<Rect>);
+
+/// Sent when a keystroke or paste is rejected by a text input's filter or `max_chars` limit
+#[derive(Message, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TextInputRejected {
+    /// The text input entity that rejected the edit
+    pub entity: Entity,
+    /// Why the edit was rejected
+    pub reason: TextInputRejection,
+}
+
+/// Sent by `TextInputAction::Complete` when a `TextInputCompletion` returns more than one candidate,
+/// so the app can show them to the user
+#[derive(Message, Debug, Clone, PartialEq, Eq)]
+pub struct CompletionCandidates {
+    /// The text input entity being completed
+    pub entity: Entity,
+    /// Candidate completions, each would be inserted at the cursor
+    pub candidates: Vec<String>,
+}
+
+/// Sent when a paste from the clipboard is longer than the input's `max_paste_chars` and is truncated
+#[derive(Message, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PasteTooLarge {
+    /// The text input entity that the paste was targeting
+    pub entity: Entity,
+    /// Number of characters in the clipboard text before truncation
+    pub size: usize,
+}
+
+/// Sent when a paste into a text input fails
+#[derive(Message, Debug)]
+pub struct PasteFailed {
+    /// The text input entity that the paste was targeting
+    pub entity: Entity,
+    /// Why the paste failed
+    pub reason: PasteFailure,
+}
+
+/// Reason a paste failed
+#[derive(Debug, Clone)]
+pub enum PasteFailure {
+    /// The clipboard is empty or only holds non-text content, like an image.
+    /// The paste is a no-op.
+    NoText,
+    /// There was an error reading from the clipboard.
+    Error(clipboard::ClipboardError),
+}
+
+/// Modifier key held with Enter to submit a multi-line text input.
+/// Enter without the modifier inserts a newline.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum SubmitModifier {
+    /// Submit on Shift + Enter, or Ctrl + Enter if `TextInputNode::soft_breaks` is set
+    #[default]
+    Shift,
+    /// Submit on Ctrl + Enter (Command + Enter on macOS)
+    Ctrl,
+    /// Never submit from the keyboard, only by queuing `TextInputAction::Submit`
+    None,
+}
+
+/// What happens to a paste that would make the text longer than `max_chars`
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum PasteOverflow {
+    /// Reject the whole paste
+    Reject,
+    /// Insert as many characters as fit and drop the rest
+    #[default]
+    Truncate,
+}
+
+/// How a single-line text input shows text that doesn't fit
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum Overflow {
+    /// Show the scrolled part of the text
+    #[default]
+    Scroll,
+    /// While unfocused, show the start of the text and truncate it with "…" at the clipped edge.
+    /// The input scrolls as normal while focused, so the cursor stays visible.
+    Ellipsis,
+}
+
+/// Line break convention for text output by a text input
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum NewlineStyle {
+    /// `\n`
+    #[default]
+    Lf,
+    /// `\r\n`
+    CrLf,
+}
+
+impl NewlineStyle {
+    /// Convert text with `\n` line breaks to this style
+    pub fn apply(self, text: String) -> String {
+        match self {
+            NewlineStyle::Lf => text,
+            NewlineStyle::CrLf => text.replace('\n', "\r\n"),
+        }
+    }
+}
+
+/// When a text input selects all of its text on gaining focus
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum SelectAllOnFocus {
+    /// Never select all, the cursor stays where it was or where the input was clicked
+    #[default]
+    Never,
+    /// Select all however the input is focused
+    Always,
+    /// Select all when the input is focused by the keyboard, for example with tab navigation,
+    /// or programmatically. Pointer presses place the cursor instead.
+    KeyboardOnly,
+}
+
+/// Conversion applied to the leading indentation of each line of pasted text
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum PasteIndentation {
+    /// Paste the text unchanged
+    #[default]
+    Keep,
+    /// Replace tabs with spaces, up to the next tab stop
+    TabsToSpaces {
+        /// Number of columns between tab stops
+        tab_width: usize,
+    },
+    /// Replace each full tab stop of spaces with a tab
+    SpacesToTabs {
+        /// Number of columns between tab stops
+        tab_width: usize,
+    },
+}
+
 /// Mode of text input
 #[derive(Copy, Clone, Debug, PartialEq)]
 pub enum TextInputMode {
@@ -248,12 +816,119 @@ impl TextInputFilter {
         }
     }
 
+    /// Returns whether inserting `ch` anywhere except the start of a passing text keeps it passing,
+    /// or `None` if the whole text needs to be checked with `is_match`.
+    ///
+    /// The text must already pass the filter, the result says nothing about text that doesn't.
+    /// `TextInputBuffer::set_text` and `replace_all` don't check the filter, so text set with them
+    /// might not pass.
+    pub fn is_match_char(&self, ch: char) -> Option<bool> {
+        match self {
+            TextInputFilter::PositiveInteger => Some(ch.is_ascii_digit()),
+            TextInputFilter::Integer | TextInputFilter::Decimal if ch.is_ascii_digit() => {
+                Some(true)
+            }
+            TextInputFilter::Hex => Some(ch.is_ascii_hexdigit()),
+            TextInputFilter::Alphanumeric => Some(ch.is_ascii_alphanumeric()),
+            _ => None,
+        }
+    }
+
     /// Create a custom filter
     pub fn custom(filter_fn: impl Fn(&str) -> bool + Send + Sync + 'static) -> Self {
         Self::Custom(Box::new(filter_fn))
     }
 }
 
+/// Validates a text input's text with a regular expression, like a `TextInputFilter::Custom` filter
+/// using `Regex::is_match`. Can be combined with a `TextInputFilter`, the text must pass both.
+#[cfg(feature = "regex")]
+#[derive(Component, Debug, Clone)]
+pub struct TextInputRegex {
+    pub pattern: regex::Regex,
+    pub mode: RegexValidateMode,
+}
+
+#[cfg(feature = "regex")]
+impl TextInputRegex {
+    pub fn new(pattern: regex::Regex, mode: RegexValidateMode) -> Self {
+        Self { pattern, mode }
+    }
+}
+
+/// When a `TextInputRegex` is checked
+#[cfg(feature = "regex")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum RegexValidateMode {
+    /// Edits that would make the text fail to match are rejected.
+    /// Every partial value typed must match, including the empty text, so the pattern has to
+    /// accept prefixes of the complete value.
+    #[default]
+    EachKeystroke,
+    /// Edits aren't checked, submissions of text that doesn't match are rejected with
+    /// `TextInputRejection::Filter` and no `SubmitText` is sent.
+    OnSubmit,
+}
+
+bitflags::bitflags! {
+    /// The categories of cursor motions a text input allows. Optional component, without it every
+    /// motion is allowed.
+    ///
+    /// Queued motions in a disallowed category are ignored, whether from the keyboard or the app.
+    /// Single character and up and down motions are always allowed.
+    /// ```ignore
+    /// // Plain arrow keys only, Ctrl + arrows, Home, End and PageUp/PageDown do nothing
+    /// commands.spawn((TextInputNode::default(), TextInputMotionMask::empty()));
+    /// ```
+    #[derive(Component, Copy, Clone, Debug, PartialEq, Eq, Hash)]
+    pub struct TextInputMotionMask: u8 {
+        /// Previous and next word, Ctrl + Left and Ctrl + Right
+        const WORD = 1;
+        /// Line and paragraph start and end, Home and End
+        const LINE = 1 << 1;
+        /// Start and end of the text, Ctrl + Home and Ctrl + End
+        const BUFFER = 1 << 2;
+        /// Page up and down, PageUp and PageDown
+        const PAGE = 1 << 3;
+    }
+}
+
+impl Default for TextInputMotionMask {
+    fn default() -> Self {
+        Self::all()
+    }
+}
+
+impl TextInputMotionMask {
+    /// The category of `motion`, or `None` for motions that are always allowed
+    pub fn category(motion: Motion) -> Option<Self> {
+        match motion {
+            Motion::PreviousWord | Motion::NextWord | Motion::LeftWord | Motion::RightWord => {
+                Some(Self::WORD)
+            }
+            Motion::Home
+            | Motion::SoftHome
+            | Motion::End
+            | Motion::ParagraphStart
+            | Motion::ParagraphEnd => Some(Self::LINE),
+            Motion::BufferStart | Motion::BufferEnd | Motion::GotoLine(_) => Some(Self::BUFFER),
+            Motion::PageUp | Motion::PageDown | Motion::Vertical(_) => Some(Self::PAGE),
+            Motion::LayoutCursor(_)
+            | Motion::Previous
+            | Motion::Next
+            | Motion::Left
+            | Motion::Right
+            | Motion::Up
+            | Motion::Down => None,
+        }
+    }
+
+    /// True if the mask allows `motion`
+    pub fn allows(self, motion: Motion) -> bool {
+        Self::category(motion).is_none_or(|category| self.contains(category))
+    }
+}
+
 impl Default for TextInputMode {
     fn default() -> Self {
         Self::MultiLine {
@@ -271,150 +946,1098 @@ impl TextInputMode {
     }
 }
 
+/// A position in the text of a text input as a line and a column
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct LineCol {
+    /// Line index, counting both hard and soft line breaks
+    pub line: usize,
+    /// Column in chars from the start of the line
+    pub column: usize,
+}
+
 #[derive(Component, Debug)]
 pub struct TextInputBuffer {
     pub editor: Editor<'static>,
     pub(crate) selection_rects: Vec<Rect>,
-    pub(crate) cursor_blink_time: f32,
     pub(crate) needs_update: bool,
     pub(crate) prompt_buffer: Option<Buffer>,
+    pub(crate) gutter_buffer: Option<Buffer>,
+    /// Drawn in place of the text of masked inputs
+    pub(crate) mask: Option<edit::TextMask>,
     pub(crate) changes: cosmic_undo_2::Commands<Change>,
+    pub(crate) last_cursor: Cursor,
+    /// Cursor position after the last vertical motion, while it is unchanged the editor's
+    /// remembered column is used by the next vertical motion
+    pub(crate) vertical_motion_cursor: Option<Cursor>,
+    pub(crate) overwrite_mode: bool,
+    pub(crate) preedit: Option<String>,
+    pub(crate) max_lines: Option<usize>,
+    /// Advance of a space in the input's font, measured during layout
+    pub(crate) space_advance: f32,
 }
 
 impl TextInputBuffer {
+    /// The text of the input, with `\n` paragraph breaks and `LINE_SEPARATOR` soft breaks.
+    ///
+    /// A trailing line break is kept. In multi-line mode it is followed by an empty last line
+    /// that the cursor can be placed on.
     pub fn get_text(&self) -> String {
         self.editor.with_buffer(get_text)
     }
-}
 
-impl Default for TextInputBuffer {
-    fn default() -> Self {
-        Self {
-            editor: Editor::new(Buffer::new_empty(Metrics::new(20.0, 20.0))),
-            selection_rects: vec![],
-            cursor_blink_time: 0.,
-            needs_update: true,
-            prompt_buffer: None,
-            changes: cosmic_undo_2::Commands::default(),
+    /// Replace all non-overlapping occurrences of `pattern` with `replacement`.
+    ///
+    /// The replacements are recorded as a single undoable change and the cursor is placed
+    /// at the end of the last replacement. Nothing is replaced if the resulting text would be
+    /// longer than `max_chars` or fail the `filter`.
+    ///
+    /// Returns the number of occurrences replaced.
+    pub fn replace_all(
+        &mut self,
+        pattern: &str,
+        replacement: &str,
+        max_chars: Option<usize>,
+        filter: Option<&TextInputFilter>,
+    ) -> usize {
+        if pattern.is_empty() {
+            return 0;
         }
-    }
-}
 
-#[derive(Component, Debug, PartialEq, Eq, Default)]
-#[component(on_insert)]
-pub struct TextInputInsertValue(pub String);
+        let text = self.get_text();
+        let matches: Vec<usize> = text.match_indices(pattern).map(|(i, _)| i).collect();
+        let Some(&last) = matches.last() else {
+            return 0;
+        };
 
-impl TextInputInsertValue {
-    fn on_insert(mut world: DeferredWorld, context: HookContext) {
-        let set_value = world
-            .get::<TextInputInsertValue>(context.entity)
-            .expect("TextInputSetValue should exist, as it is what this hook is called from")
-            .0
-            .clone();
+        let new_text = text.replace(pattern, replacement);
+        if max_chars.is_some_and(|max_chars| max_chars < edit::inserted_len(&new_text))
+            || filter.is_some_and(|filter| !filter.is_match(&new_text))
+        {
+            return 0;
+        }
 
-        let text_input_buffer = world.get_mut::<TextInputBuffer>(context.entity);
+        self.editor.start_change();
+        for &start in matches.iter().rev() {
+            let (start, end) = self.editor.with_buffer(|buffer| {
+                (
+                    edit::cursor_at_byte_offset(buffer, start),
+                    edit::cursor_at_byte_offset(buffer, start + pattern.len()),
+                )
+            });
+            self.editor.delete_range(start, end);
+            self.editor
+                .insert_at(start, &to_buffer_text(replacement), None);
+        }
 
-        if let Some(mut text_input_buffer) = text_input_buffer {
-            text_input_buffer.editor.insert_string(&set_value, None);
-        };
+        // Each replacement before the last shifts it by the difference in length
+        let cursor_offset = last + (matches.len() - 1) * replacement.len() + replacement.len()
+            - (matches.len() - 1) * pattern.len();
+        let cursor = self
+            .editor
+            .with_buffer(|buffer| edit::cursor_at_byte_offset(buffer, cursor_offset));
+        self.editor.set_selection(Selection::None);
+        self.editor.set_cursor(cursor);
+
+        if let Some(change) = self.editor.finish_change() {
+            self.changes.push(change);
+        }
+        self.editor.set_redraw(true);
+
+        matches.len()
     }
-}
 
-/// Prompt displayed when the input is empty (including whitespace).
-/// Optional component.
-#[derive(Component, Clone, Debug, Reflect)]
-#[reflect(Component, Default, Debug)]
-#[require(TextInputPromptLayoutInfo)]
-pub struct TextInputPrompt {
-    /// Prompt's text
-    pub text: String,
-    /// The prompt's font.
-    /// If none, the text input's font is used.
-    pub font: Option<TextFont>,
-    /// The color of the prompt's text.
-    /// If none, the text input's `TextColor` is used.
-    pub color: Option<Color>,
-}
+    /// Remove the text after the first `char_len` characters, as a single undoable change.
+    /// Characters are counted like `TextInputNode::max_chars`, line breaks don't count. A line break
+    /// directly after the last kept character is removed.
+    ///
+    /// A cursor beyond the new end is moved to it, and a selection that extends beyond it is cleared.
+    /// Returns true if any text was removed.
+    pub fn truncate(&mut self, char_len: usize) -> bool {
+        let Some((start, end)) = self.editor.with_buffer(|buffer| {
+            let mut remaining = char_len;
+            let start = buffer
+                .lines
+                .iter()
+                .enumerate()
+                .find_map(|(line_index, line)| {
+                    let text = line.text();
+                    let len = text.chars().count();
+                    if remaining < len {
+                        let (index, _) = text.char_indices().nth(remaining)?;
+                        Some(Some(Cursor::new(line_index, index)))
+                    } else if remaining == len {
+                        Some(
+                            (line_index + 1 < buffer.lines.len())
+                                .then(|| Cursor::new(line_index, text.len())),
+                        )
+                    } else {
+                        remaining -= len;
+                        None
+                    }
+                })??;
+            let end = edit::clamp_cursor(buffer, Cursor::new(usize::MAX, usize::MAX));
+            Some((start, end))
+        }) else {
+            return false;
+        };
 
-impl TextInputPrompt {
-    pub fn new(text: impl Into<String>) -> Self {
-        Self {
-            text: text.into(),
-            ..Default::default()
+        let is_beyond = |cursor: Cursor| (start.line, start.index) < (cursor.line, cursor.index);
+
+        self.editor.start_change();
+        self.editor.delete_range(start, end);
+        if self
+            .editor
+            .selection_bounds()
+            .is_some_and(|(_, selection_end)| is_beyond(selection_end))
+        {
+            self.editor.set_selection(Selection::None);
+        }
+        if is_beyond(self.editor.cursor()) {
+            self.editor.set_cursor(start);
         }
-    }
-}
 
-impl Default for TextInputPrompt {
-    fn default() -> Self {
-        Self {
-            text: "Enter some text here".into(),
-            font: None,
-            color: Some(bevy::color::palettes::css::GRAY.into()),
+        if let Some(change) = self.editor.finish_change() {
+            self.changes.push(change);
         }
+        self.editor.set_redraw(true);
+        true
     }
-}
 
-/// Styling for a text cursor
-#[derive(Component, Copy, Clone, Debug, PartialEq, Reflect)]
-#[reflect(Component, Default, Debug, PartialEq)]
-pub struct TextInputStyle {
-    /// Color of the cursor
-    pub cursor_color: Color,
-    /// Selection color
-    pub selection_color: Color,
-    /// Selected text tint, if unset uses the `TextColor`
-    pub selected_text_color: Option<Color>,
-    /// Width of the cursor
-    pub cursor_width: f32,
-    /// Corner radius in logical pixels
-    pub cursor_radius: f32,
-    /// Normalized height of the cursor relative to the text block's line height.
-    pub cursor_height: f32,
-    /// Time cursor blinks in seconds
-    pub blink_interval: f32,
-}
+    /// Select the word at a position relative to the top-left corner of the text input, in physical pixels.
+    /// Performs the same selection as a double-click, so it can be used by custom pointer handlers,
+    /// for example to select the word under the pointer before opening a context menu.
+    ///
+    /// Masked inputs select all of their text instead.
+    pub fn select_word_at(&mut self, font_system: &mut cosmic_text::FontSystem, x: f32, y: f32) {
+        let scroll = self.horizontal_scroll();
+        let mut editor = self.editor.borrow_with(font_system);
+        let x = (x + scroll).round() as i32;
+        let y = y.round() as i32;
+        match self.mask.as_ref() {
+            Some(mask) => {
+                mask.apply_pointer_edit(&mut editor, &TextInputEdit::DoubleClick { x, y });
+            }
+            None => editor.action(cosmic_text::Action::DoubleClick { x, y }),
+        }
+    }
 
-impl Default for TextInputStyle {
-    fn default() -> Self {
-        Self {
-            cursor_color: GRAY_400.into(),
-            selection_color: SKY_BLUE.into(),
-            selected_text_color: None,
-            cursor_width: 3.,
-            cursor_radius: 0.,
-            cursor_height: 1.,
-            blink_interval: 0.5,
+    /// Horizontal scroll of the drawn text, in physical pixels.
+    /// Masked inputs scroll their mask instead of the editor's buffer.
+    pub(crate) fn horizontal_scroll(&self) -> f32 {
+        match self.mask.as_ref() {
+            Some(mask) => mask.buffer.scroll().horizontal,
+            None => self.editor.with_buffer(|buffer| buffer.scroll().horizontal),
         }
     }
-}
 
-fn get_text(buffer: &Buffer) -> String {
-    buffer
-        .lines
-        .iter()
-        .map(|buffer_line| buffer_line.text())
-        .fold(String::new(), |mut out, line| {
-            if !out.is_empty() {
-                out.push('\n');
+    /// Position of the cursor in the drawn text, like `Editor::cursor_position`
+    pub(crate) fn drawn_cursor_position(&self) -> Option<(i32, i32)> {
+        match self.mask.as_ref() {
+            Some(mask) => {
+                edit::caret_position(&mask.buffer, mask.mask_cursor(self.editor.cursor()))
+                    .map(|position| (position.x as i32, position.y as i32))
             }
-            out.push_str(line);
-            out
-        })
-}
+            None => self.editor.cursor_position(),
+        }
+    }
 
-#[derive(Component, Clone, Default, Debug, Reflect)]
-#[reflect(Component, Default, Debug)]
-pub struct TextInputLayoutInfo {
-    pub glyphs: Vec<TextInputGlyph>,
-    pub size: Vec2,
-}
+    /// The buffer's current font metrics, in physical pixels.
+    /// Updated from the input's `TextFont` and `LineHeight` by `text_input_system`.
+    pub fn metrics(&self) -> Metrics {
+        self.editor.with_buffer(|buffer| buffer.metrics())
+    }
 
-#[derive(Component, Clone, Default, Debug, Reflect)]
-#[reflect(Component, Default, Debug)]
-pub struct TextInputPromptLayoutInfo {
-    pub glyphs: Vec<TextInputGlyph>,
+    /// The buffer's current font size, in physical pixels.
+    pub fn font_size(&self) -> f32 {
+        self.metrics().font_size
+    }
+
+    /// The buffer's current line height, in physical pixels.
+    pub fn line_height(&self) -> f32 {
+        self.metrics().line_height
+    }
+
+    /// If true typed glyphs overwrite the glyph at the current cursor position, instead of inserting before it.
+    /// Toggled with the Insert key when the input's `allow_overwrite_mode` is true.
+    pub fn overwrite_mode(&self) -> bool {
+        self.overwrite_mode
+    }
+
+    /// Set whether typed glyphs overwrite the glyph at the current cursor position.
+    pub fn set_overwrite_mode(&mut self, overwrite_mode: bool) {
+        self.overwrite_mode = overwrite_mode;
+    }
+
+    /// Maximum number of lines kept by `append_str` and `append_line`.
+    pub fn max_lines(&self) -> Option<usize> {
+        self.max_lines
+    }
+
+    /// Set the maximum number of lines kept by `append_str` and `append_line`.
+    /// When appending takes the text over the limit, the oldest lines are removed.
+    pub fn set_max_lines(&mut self, max_lines: Option<usize>) {
+        self.max_lines = max_lines.map(|max_lines| max_lines.max(1));
+    }
+
+    /// Append text to the end of the buffer, for log style outputs. Appends aren't recorded in the
+    /// undo history.
+    ///
+    /// If the cursor was at the end of the text it follows the new end, so a multi-line input scrolls
+    /// to show the appended text. If appending takes the text over `max_lines`, the oldest lines
+    /// are removed and the undo history is cleared.
+    pub fn append_str(&mut self, text: &str) {
+        let end = self
+            .editor
+            .with_buffer(|buffer| edit::clamp_cursor(buffer, Cursor::new(usize::MAX, usize::MAX)));
+        let cursor = self.editor.cursor();
+        let follow = (cursor.line, cursor.index) == (end.line, end.index);
+
+        let new_end = self.editor.insert_at(end, &to_buffer_text(text), None);
+        if follow {
+            self.editor.set_cursor(new_end);
+        }
+
+        if let Some(max_lines) = self.max_lines {
+            let excess = self
+                .editor
+                .with_buffer(|buffer| buffer.lines.len().saturating_sub(max_lines));
+            if 0 < excess {
+                self.remove_first_lines(excess);
+            }
+        }
+        self.editor.set_redraw(true);
+    }
+
+    /// Append a line to the end of the buffer, see `append_str`.
+    /// The line is added after a line break, unless the buffer is empty.
+    pub fn append_line(&mut self, line: &str) {
+        if self.editor.with_buffer(edit::is_buffer_empty) {
+            self.append_str(line);
+        } else {
+            self.append_str(&format!("\n{line}"));
+        }
+    }
+
+    fn remove_first_lines(&mut self, count: usize) {
+        self.editor.with_buffer_mut(|buffer| {
+            buffer.lines.drain(..count);
+            let mut scroll = buffer.scroll();
+            scroll.line = scroll.line.saturating_sub(count);
+            buffer.set_scroll(scroll);
+            buffer.set_redraw(true);
+        });
+
+        let shift = |cursor: Cursor| {
+            if cursor.line < count {
+                Cursor::default()
+            } else {
+                Cursor {
+                    line: cursor.line - count,
+                    ..cursor
+                }
+            }
+        };
+        let cursor = shift(self.editor.cursor());
+        let selection = match self.editor.selection() {
+            Selection::None => Selection::None,
+            Selection::Normal(select) => Selection::Normal(shift(select)),
+            Selection::Line(select) => Selection::Line(shift(select)),
+            Selection::Word(select) => Selection::Word(shift(select)),
+        };
+        self.editor.set_cursor(cursor);
+        self.editor.set_selection(selection);
+        // The recorded changes refer to the removed lines
+        self.changes = cosmic_undo_2::Commands::default();
+        self.vertical_motion_cursor = None;
+    }
+
+    /// The in-progress input method composition, if any.
+    pub fn preedit(&self) -> Option<&str> {
+        self.preedit.as_deref()
+    }
+
+    /// Set the in-progress input method composition. It isn't part of the text until committed.
+    pub fn set_preedit(&mut self, preedit: Option<String>) {
+        self.preedit = preedit.filter(|preedit| !preedit.is_empty());
+    }
+
+    /// Insert the in-progress input method composition at the cursor as a single undoable change,
+    /// replacing any selected text.
+    pub fn commit_preedit(&mut self) {
+        let Some(preedit) = self.preedit.take() else {
+            return;
+        };
+        self.editor.start_change();
+        self.editor.insert_string(&preedit, None);
+        if let Some(change) = self.editor.finish_change() {
+            self.changes.push(change);
+        }
+        self.editor.set_redraw(true);
+    }
+
+    /// Replace the text, placing the cursor at its end and clearing the undo history.
+    pub fn set_text(&mut self, text: &str) {
+        self.set_text_with(text, SetTextUndo::Reset);
+    }
+
+    /// Replace the text, placing the cursor at its end.
+    /// `undo` sets whether the replacement can be undone or clears the undo history.
+    ///
+    /// Doesn't check the input's `max_chars` or filter.
+    pub fn set_text_with(&mut self, text: &str, undo: SetTextUndo) {
+        let cursor = self.editor.cursor();
+        self.editor.set_selection(Selection::None);
+        self.editor.start_change();
+        // The buffer has no lines until the text input is first laid out
+        if self.editor.with_buffer(|buffer| !buffer.lines.is_empty()) {
+            // Delete the text before the cursor separately, so that undoing the change restores the cursor
+            self.editor.delete_range(Cursor::default(), cursor);
+            let end = self.editor.with_buffer(|buffer| {
+                edit::clamp_cursor(buffer, Cursor::new(usize::MAX, usize::MAX))
+            });
+            self.editor.delete_range(Cursor::default(), end);
+        }
+        let cursor = self
+            .editor
+            .insert_at(Cursor::default(), &to_buffer_text(text), None);
+        self.editor.set_cursor(cursor);
+
+        let change = self.editor.finish_change();
+        match undo {
+            SetTextUndo::Reset => self.changes = cosmic_undo_2::Commands::default(),
+            SetTextUndo::Recordable => {
+                if let Some(change) = change {
+                    self.changes.push(change);
+                }
+            }
+        }
+        self.editor.set_redraw(true);
+    }
+
+    /// Apply a single edit to the buffer, recording it in the undo history.
+    /// Returns the reason the edit was rejected by `max_chars` or `filter`, if it was rejected.
+    ///
+    /// The buffer doesn't know its input's `secure` setting, so the edit is recorded even for secure
+    /// inputs. Only `process_text_input_queues` keeps their undo history empty.
+    ///
+    /// This is the same edit path used by `process_text_input_queues`, it only needs a `FontSystem`
+    /// so it can be used without an `App`:
+    /// ```ignore
+    /// let mut font_system = cosmic_text::FontSystem::new();
+    /// let mut buffer = TextInputBuffer::default();
+    /// buffer.apply_edit(TextInputEdit::Paste("hello".into()), &mut font_system, Some(8), None);
+    /// assert_eq!(buffer.get_text(), "hello");
+    /// ```
+    pub fn apply_edit(
+        &mut self,
+        edit: TextInputEdit,
+        font_system: &mut cosmic_text::FontSystem,
+        max_chars: Option<usize>,
+        filter: Option<&TextInputFilter>,
+    ) -> Option<TextInputRejection> {
+        edit::apply_edit_with_goal_column(
+            edit,
+            &mut self.editor.borrow_with(font_system),
+            &mut self.changes,
+            &mut self.vertical_motion_cursor,
+            max_chars,
+            filter,
+            None,
+        )
+    }
+
+    /// Run `f` with the buffer's editor borrowed with the pipeline's `FontSystem`, for operations
+    /// cosmic-text supports that aren't wrapped by this crate. The text is laid out and redrawn
+    /// again afterwards.
+    ///
+    /// Changes made through the editor directly aren't recorded in the undo history and bypass
+    /// the input's `max_chars` and filter.
+    pub fn with_editor<R>(
+        &mut self,
+        pipeline: &mut TextInputPipeline,
+        f: impl FnOnce(&mut BorrowedWithFontSystem<Editor<'static>>) -> R,
+    ) -> R {
+        let result = f(&mut self.editor.borrow_with(&mut pipeline.font_system));
+        self.needs_update = true;
+        self.editor.set_redraw(true);
+        result
+    }
+
+    /// Capture the buffer's text, cursor and selection, to be restored later with `restore`.
+    pub fn snapshot(&self) -> TextInputSnapshot {
+        TextInputSnapshot {
+            text: self.editor.with_buffer(get_text_with_line_endings),
+            cursor: self.editor.cursor(),
+            selection: self.editor.selection(),
+        }
+    }
+
+    /// Restore the text, cursor and selection captured by `snapshot`, as a single undoable change.
+    /// If `max_chars` is set, text exceeding it is truncated.
+    pub fn restore(&mut self, snapshot: &TextInputSnapshot, max_chars: Option<usize>) {
+        let mut text = snapshot.text.as_str();
+        if let Some(max_chars) = max_chars
+            && let Some((end, _)) = text
+                .char_indices()
+                .filter(|(_, ch)| !matches!(ch, '\n' | '\r'))
+                .nth(max_chars)
+        {
+            text = &text[..end];
+        }
+
+        self.editor.start_change();
+        let (start, end) = self.editor.with_buffer(|buffer| {
+            (
+                Cursor::default(),
+                edit::clamp_cursor(buffer, Cursor::new(usize::MAX, usize::MAX)),
+            )
+        });
+        if self.editor.with_buffer(|buffer| !buffer.lines.is_empty()) {
+            self.editor.delete_range(start, end);
+        }
+        self.editor.insert_at(start, text, None);
+
+        let (cursor, selection) = self.editor.with_buffer(|buffer| {
+            let selection = match snapshot.selection {
+                Selection::None => Selection::None,
+                Selection::Normal(cursor) => Selection::Normal(edit::clamp_cursor(buffer, cursor)),
+                Selection::Line(cursor) => Selection::Line(edit::clamp_cursor(buffer, cursor)),
+                Selection::Word(cursor) => Selection::Word(edit::clamp_cursor(buffer, cursor)),
+            };
+            (edit::clamp_cursor(buffer, snapshot.cursor), selection)
+        });
+        self.editor.set_cursor(cursor);
+        self.editor.set_selection(selection);
+
+        if let Some(change) = self.editor.finish_change() {
+            self.changes.push(change);
+        }
+        self.editor.set_redraw(true);
+    }
+
+    /// Returns the start and end of the selection as lines and columns, with the start first
+    /// whichever direction the selection was made in, or `None` if nothing is selected.
+    pub fn selection_line_col(&self) -> Option<(LineCol, LineCol)> {
+        let (start, end) = self.editor.selection_bounds()?;
+        if (start.line, start.index) == (end.line, end.index) {
+            return None;
+        }
+        self.editor.with_buffer(|buffer| {
+            let line_col = |cursor: Cursor| LineCol {
+                line: cursor.line,
+                column: buffer.lines[cursor.line].text()[..cursor.index]
+                    .chars()
+                    .count(),
+            };
+            Some((line_col(start), line_col(end)))
+        })
+    }
+
+    /// Returns the bounding rect of the selection as drawn after the last layout, relative to the
+    /// top-left corner of the text area in physical pixels, or `None` if nothing is selected.
+    /// Transform it using the node's `UiGlobalTransform` to get a screen-space rect.
+    pub fn selection_bounds_rect(&self) -> Option<Rect> {
+        let scroll = self.horizontal_scroll();
+        self.selection_rects
+            .iter()
+            .copied()
+            .reduce(|a, b| a.union(b))
+            .map(|rect| Rect {
+                min: rect.min - scroll * Vec2::X,
+                max: rect.max - scroll * Vec2::X,
+            })
+    }
+
+    /// Returns the position of a caret at the char offset `offset` as drawn after the last layout,
+    /// relative to the top-left corner of the text area in physical pixels. The position is the top of
+    /// the caret, which is `line_height` tall. For drawing other carets, like those of remote collaborators.
+    ///
+    /// Line breaks count as one char, and offsets past the end are clamped to the end.
+    /// Returns `None` if the offset's line is scrolled out of view.
+    pub fn caret_pixel_for_offset(&self, offset: usize) -> Option<Vec2> {
+        let cursor = self
+            .editor
+            .with_buffer(|buffer| edit::cursor_at_char_offset(buffer, offset));
+        let position = match self.mask.as_ref() {
+            Some(mask) => edit::caret_position(&mask.buffer, mask.mask_cursor(cursor)),
+            None => self
+                .editor
+                .with_buffer(|buffer| edit::caret_position(buffer, cursor)),
+        };
+        position.map(|position| position - self.horizontal_scroll() * Vec2::X)
+    }
+
+    /// Returns the word the cursor is inside or touching, and its char range in the text.
+    ///
+    /// Words are found using the same Unicode word boundaries as word motions and double-click selection.
+    /// A cursor at the start or end of a word returns that word. If the cursor is between two words
+    /// with no separator, the preceding word is returned. Returns `None` if the cursor isn't touching a word,
+    /// for example when it is surrounded by whitespace.
+    pub fn word_at_cursor(&self) -> Option<(String, Range<usize>)> {
+        let cursor = self.editor.cursor();
+        self.editor.with_buffer(|buffer| {
+            let text = buffer.lines.get(cursor.line)?.text();
+            let (start, word) = text
+                .unicode_word_indices()
+                .take_while(|(start, _)| *start <= cursor.index)
+                .find(|(start, word)| cursor.index <= start + word.len())?;
+            let line_start = buffer
+                .lines
+                .iter()
+                .take(cursor.line)
+                .map(|line| line.text().chars().count() + 1)
+                .sum::<usize>();
+            let char_start = line_start + text[..start].chars().count();
+            Some((
+                word.to_string(),
+                char_start..char_start + word.chars().count(),
+            ))
+        })
+    }
+
+    /// Returns the char offset of the next occurrence of `pattern` at or after the char offset `from`.
+    pub fn find_next(&self, pattern: &str, from: usize) -> Option<usize> {
+        let text = self.get_text();
+        let from_byte = text
+            .char_indices()
+            .nth(from)
+            .map(|(i, _)| i)
+            .unwrap_or(text.len());
+        text[from_byte..]
+            .find(pattern)
+            .map(|i| from + text[from_byte..from_byte + i].chars().count())
+    }
+}
+
+/// A text input's text, cursor and selection, captured by `TextInputBuffer::snapshot`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TextInputSnapshot {
+    text: String,
+    cursor: Cursor,
+    selection: Selection,
+}
+
+/// How `TextInputBuffer::set_text_with` treats the undo history
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SetTextUndo {
+    /// Clear the undo history, the previous text can't be restored
+    #[default]
+    Reset,
+    /// Record the replacement as a single change, undo restores the previous text and cursor
+    Recordable,
+}
+
+impl Default for TextInputBuffer {
+    fn default() -> Self {
+        Self {
+            editor: Editor::new(Buffer::new_empty(Metrics::new(20.0, 20.0))),
+            selection_rects: vec![],
+            needs_update: true,
+            prompt_buffer: None,
+            gutter_buffer: None,
+            mask: None,
+            changes: cosmic_undo_2::Commands::default(),
+            last_cursor: Cursor::default(),
+            vertical_motion_cursor: None,
+            overwrite_mode: false,
+            preedit: None,
+            max_lines: None,
+            space_advance: 0.,
+        }
+    }
+}
+
+/// Cursor blink state of a text input, updated every frame by `cursor_blink_system`.
+///
+/// Kept separate from `TextInputBuffer` so that the buffer is only marked as changed by edits and
+/// cursor movement, not by the passing of time.
+#[derive(Component, Debug, Clone, Default)]
+pub struct TextInputCursorBlink {
+    pub(crate) time: f32,
+    pub(crate) time_since_input: f32,
+}
+
+impl TextInputCursorBlink {
+    /// Show the cursor and restart its blink
+    pub fn reset(&mut self) {
+        self.time = 0.;
+    }
+}
+
+/// An edit applied to a text input, recorded by a [`TextInputRecorder`]
+#[derive(Debug, Clone)]
+pub struct RecordedEdit {
+    /// Elapsed time in seconds, from `Time`, when the edit was applied
+    pub time: f64,
+    /// The edit, after any processing by the text input. Pastes hold the pasted text.
+    pub edit: TextInputEdit,
+}
+
+/// Opt-in recorder of the edits applied to a text input by `process_text_input_queues`, for
+/// reproducing editing bugs. Keeps the most recent `capacity` edits.
+///
+/// Only edits that weren't rejected are recorded, so a replay doesn't need the input's `max_chars`
+/// or filter. Changes made directly to the `TextInputBuffer`, like `set_text`, aren't recorded.
+#[derive(Component, Debug, Clone)]
+pub struct TextInputRecorder {
+    capacity: usize,
+    edits: VecDeque<RecordedEdit>,
+    dropped: usize,
+}
+
+impl TextInputRecorder {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            edits: VecDeque::with_capacity(capacity),
+            dropped: 0,
+        }
+    }
+
+    /// The recorded edits, oldest first
+    pub fn edits(&self) -> impl Iterator<Item = &RecordedEdit> {
+        self.edits.iter()
+    }
+
+    /// Number of edits dropped from the start of the recording because it was full.
+    /// A replay only reproduces the recorded session if this is zero.
+    pub fn dropped(&self) -> usize {
+        self.dropped
+    }
+
+    pub fn clear(&mut self) {
+        self.edits.clear();
+        self.dropped = 0;
+    }
+
+    pub(crate) fn record(&mut self, time: f64, edit: TextInputEdit) {
+        if self.capacity == 0 {
+            self.dropped += 1;
+            return;
+        }
+        if self.edits.len() == self.capacity {
+            self.edits.pop_front();
+            self.dropped += 1;
+        }
+        self.edits.push_back(RecordedEdit { time, edit });
+    }
+
+    /// Apply the recorded edits to `buffer`, in order. Replaying into a fresh buffer with the same
+    /// size and font as the recorded input reproduces its text and cursor.
+    pub fn replay(&self, buffer: &mut TextInputBuffer, font_system: &mut cosmic_text::FontSystem) {
+        for recorded in &self.edits {
+            buffer.apply_edit(recorded.edit.clone(), font_system, None, None);
+        }
+    }
+}
+
+#[derive(Component, Debug, PartialEq, Eq, Default)]
+#[component(on_insert)]
+pub struct TextInputInsertValue(pub String);
+
+impl TextInputInsertValue {
+    fn on_insert(mut world: DeferredWorld, context: HookContext) {
+        let set_value = world
+            .get::<TextInputInsertValue>(context.entity)
+            .expect("TextInputSetValue should exist, as it is what this hook is called from")
+            .0
+            .clone();
+
+        let text_input_buffer = world.get_mut::<TextInputBuffer>(context.entity);
+
+        if let Some(mut text_input_buffer) = text_input_buffer {
+            text_input_buffer
+                .editor
+                .insert_string(&to_buffer_text(&set_value), None);
+        };
+    }
+}
+
+/// Completes the text at the cursor from an app-provided function, for example in a command input.
+/// Optional component.
+///
+/// In single-line mode, Tab queues `TextInputAction::Complete`. The function is called with the
+/// input's text and the char offset of the cursor, and returns candidate strings to insert at the
+/// cursor. A single candidate is inserted, if there is more than one a `CompletionCandidates`
+/// message is sent instead.
+#[derive(Component)]
+pub struct TextInputCompletion {
+    complete: Box<dyn Fn(&str, usize) -> Vec<String> + Send + Sync>,
+}
+
+impl core::fmt::Debug for TextInputCompletion {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.write_str("TextInputCompletion")
+    }
+}
+
+impl TextInputCompletion {
+    pub fn new(complete: impl Fn(&str, usize) -> Vec<String> + Send + Sync + 'static) -> Self {
+        Self {
+            complete: Box::new(complete),
+        }
+    }
+
+    /// Candidate completions for `text` with the cursor at the char offset `cursor_offset`
+    pub fn candidates(&self, text: &str, cursor_offset: usize) -> Vec<String> {
+        (self.complete)(text, cursor_offset)
+    }
+}
+
+/// Formats the text a text input copies or cuts to the clipboard, for example to add line numbers or
+/// wrap it in a code fence. Optional component.
+///
+/// The function is called with the copied text, before its line breaks are converted to the input's
+/// `output_newline` style. Cut still deletes the unformatted selection from the input.
+#[derive(Component)]
+pub struct CopyFormatter(pub Box<dyn Fn(&str) -> String + Send + Sync>);
+
+impl core::fmt::Debug for CopyFormatter {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.write_str("CopyFormatter")
+    }
+}
+
+impl CopyFormatter {
+    pub fn new(format: impl Fn(&str) -> String + Send + Sync + 'static) -> Self {
+        Self(Box::new(format))
+    }
+
+    /// The clipboard text for the copied `text`
+    pub fn format(&self, text: &str) -> String {
+        (self.0)(text)
+    }
+}
+
+/// Prompt displayed when the input is empty (including whitespace).
+/// Optional component.
+#[derive(Component, Clone, Debug, Reflect)]
+#[reflect(Component, Default, Debug)]
+#[require(TextInputPromptLayoutInfo)]
+pub struct TextInputPrompt {
+    /// Prompt's text
+    pub text: String,
+    /// The prompt's font.
+    /// If none, the text input's font is used.
+    pub font: Option<TextFont>,
+    /// The color of the prompt's text.
+    /// If none, the text input's `TextColor` is used.
+    pub color: Option<Color>,
+    /// Alpha multiplier applied to the prompt's color while the input is focused
+    pub focused_alpha: f32,
+    /// Scale applied to the prompt while the input is focused, relative to the top-left corner of the text
+    pub focused_scale: f32,
+}
+
+impl TextInputPrompt {
+    pub fn new(text: impl Into<String>) -> Self {
+        Self {
+            text: text.into(),
+            ..Default::default()
+        }
+    }
+}
+
+impl Default for TextInputPrompt {
+    fn default() -> Self {
+        Self {
+            text: "Enter some text here".into(),
+            font: None,
+            color: Some(bevy::color::palettes::css::GRAY.into()),
+            focused_alpha: 1.,
+            focused_scale: 1.,
+        }
+    }
+}
+
+/// Line numbers displayed in a gutter to the left of the text.
+/// Optional component, intended for multi-line inputs.
+///
+/// The text is inset by the gutter's width. Numbers count logical lines, so when the input wraps
+/// only the first row of each line is numbered and its continuation rows are left blank.
+#[derive(Component, Clone, Debug, Reflect)]
+#[reflect(Component, Default, Debug)]
+#[require(TextInputGutterLayoutInfo)]
+pub struct TextInputGutter {
+    /// Width of the gutter in logical pixels
+    pub width: f32,
+    /// The color of the line numbers
+    pub color: Color,
+}
+
+impl TextInputGutter {
+    /// Width of the gutter in physical pixels, zero if there is no gutter.
+    pub(crate) fn inset(gutter: Option<&Self>, scale_factor: f32) -> f32 {
+        gutter.map_or(0., |gutter| gutter.width * scale_factor)
+    }
+}
+
+impl Default for TextInputGutter {
+    fn default() -> Self {
+        Self {
+            width: 40.,
+            color: bevy::color::palettes::css::GRAY.into(),
+        }
+    }
+}
+
+/// Underlines ranges of a text input's text, for example misspelled words found by the app's spellchecker.
+/// Optional component.
+///
+/// Update the ranges when the input's `TextInputContents` changes.
+#[derive(Component, Clone, Debug, Reflect)]
+#[reflect(Component, Default, Debug)]
+#[require(TextInputSpellcheckLayoutInfo)]
+pub struct TextInputSpellcheck {
+    /// Char ranges into the input's text to underline. Line breaks count as one char.
+    pub misspelled_ranges: Vec<Range<usize>>,
+    /// The color of the underline
+    pub color: Color,
+}
+
+impl Default for TextInputSpellcheck {
+    fn default() -> Self {
+        Self {
+            misspelled_ranges: vec![],
+            color: bevy::color::palettes::css::RED.into(),
+        }
+    }
+}
+
+/// Highlights ranges of a text input's text with background colors, for example search matches.
+/// Optional component. Highlights are drawn below the selection and don't affect it or the cursor.
+///
+/// Update the ranges when the input's `TextInputContents` changes.
+#[derive(Component, Clone, Debug, Default, Reflect)]
+#[reflect(Component, Default, Debug)]
+#[require(TextInputHighlightsLayoutInfo)]
+pub struct TextInputHighlights {
+    /// Char ranges into the input's text and their highlight colors. Line breaks count as one char.
+    pub ranges: Vec<(Range<usize>, Color)>,
+}
+
+/// Colors used in one state of a text input.
+/// Unset colors fall back to the input's `TextColor` and `TextInputStyle`.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Reflect)]
+#[reflect(Default, Debug, PartialEq)]
+pub struct TextInputColors {
+    /// Color of the text
+    pub text: Option<Color>,
+    /// Color of the cursor
+    pub cursor: Option<Color>,
+    /// Color of the selection rects
+    pub selection: Option<Color>,
+    /// Background color, drawn over the node's `BackgroundColor`
+    pub background: Option<Color>,
+}
+
+/// Colors for each state of a text input, chosen when the input is rendered.
+/// Optional component.
+///
+/// The first matching state is used: `disabled` if the input isn't enabled, `error` if it has the
+/// `TextInputError` marker or its `TextInputHint` shows a rejected edit, `focused` if it is focused,
+/// otherwise `normal`.
+#[derive(Component, Copy, Clone, Debug, Default, PartialEq, Reflect)]
+#[reflect(Component, Default, Debug, PartialEq)]
+pub struct TextInputStateColors {
+    pub normal: TextInputColors,
+    pub focused: TextInputColors,
+    pub disabled: TextInputColors,
+    pub error: TextInputColors,
+}
+
+impl TextInputStateColors {
+    /// The colors for an input in the given state
+    pub fn current(&self, is_enabled: bool, is_focused: bool, is_error: bool) -> TextInputColors {
+        if !is_enabled {
+            self.disabled
+        } else if is_error {
+            self.error
+        } else if is_focused {
+            self.focused
+        } else {
+            self.normal
+        }
+    }
+}
+
+/// Marker component that puts a text input in the error state, for `TextInputStateColors`
+#[derive(Component, Default, Debug, Clone, Copy)]
+pub struct TextInputError;
+
+/// Styling for a text cursor
+#[derive(Component, Copy, Clone, Debug, PartialEq, Reflect)]
+#[reflect(Component, Default, Debug, PartialEq)]
+pub struct TextInputStyle {
+    /// Color of the cursor
+    pub cursor_color: Color,
+    /// Selection color
+    pub selection_color: Color,
+    /// Selected text tint, if unset uses the `TextColor`
+    pub selected_text_color: Option<Color>,
+    /// Minimum width of a selection rect, in widths of a space in the input's font.
+    /// Makes selected empty lines visible.
+    pub selection_min_width: f32,
+    /// Width of the cursor
+    pub cursor_width: f32,
+    /// Corner radius in logical pixels
+    pub cursor_radius: f32,
+    /// Normalized height of the cursor relative to the text block's line height.
+    pub cursor_height: f32,
+    /// Offset of the cursor from its default position in logical pixels, positive y is down.
+    /// For aligning the cursor with fonts whose glyphs sit high or low in their line.
+    pub cursor_offset: Vec2,
+    /// Time cursor blinks in seconds.
+    /// If none, `TextInputConfig::blink_interval` is used.
+    pub blink_interval: Option<f32>,
+    /// Color of the ring drawn around the input while it is focused.
+    /// If none, no focus ring is drawn.
+    pub focus_ring_color: Option<Color>,
+    /// Width of the focus ring in logical pixels
+    pub focus_ring_width: f32,
+    /// Color of a fill drawn behind the text and selection, covering the text area.
+    /// Independent of the node's `BackgroundColor`. If none, no fill is drawn.
+    pub fill_color: Option<Color>,
+    /// Corner radius of the fill in logical pixels
+    pub fill_radius: f32,
+    /// Slide the cursor to its new position when it moves, instead of jumping.
+    /// Moves of more than one line always jump.
+    pub caret_animation: bool,
+    /// Duration of the cursor's slide in seconds
+    pub caret_animation_duration: f32,
+    /// Draw the cursor in black or white, whichever contrasts more with the input's background:
+    /// `fill_color`, else the state background from `TextInputStateColors`, else the node's
+    /// `BackgroundColor`. Keeps the cursor visible over backgrounds that change.
+    pub cursor_auto_contrast: bool,
+    /// Whether the selection is drawn behind or over the text
+    pub selection_layer: SelectionLayer,
+}
+
+impl Default for TextInputStyle {
+    fn default() -> Self {
+        Self {
+            cursor_color: GRAY_400.into(),
+            selection_color: SKY_BLUE.into(),
+            selected_text_color: None,
+            selection_min_width: 1.,
+            cursor_width: 3.,
+            cursor_radius: 0.,
+            cursor_height: 1.,
+            cursor_offset: Vec2::ZERO,
+            blink_interval: None,
+            focus_ring_color: None,
+            focus_ring_width: 2.,
+            fill_color: None,
+            fill_radius: 0.,
+            caret_animation: false,
+            caret_animation_duration: 0.08,
+            cursor_auto_contrast: false,
+            selection_layer: SelectionLayer::BehindText,
+        }
+    }
+}
+
+/// Where selection rects are drawn relative to the text's glyphs
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Reflect)]
+pub enum SelectionLayer {
+    /// Draw the selection behind the glyphs
+    #[default]
+    BehindText,
+    /// Draw the selection over the glyphs, blended by the alpha of `selection_color`.
+    /// Use a translucent color for a highlighter look.
+    OverText,
+}
+
+/// Line ending that marks a soft line break, see `TextInputNode::soft_breaks`.
+pub(crate) const SOFT_BREAK: LineEnding = LineEnding::LfCr;
+
+/// Character representing soft line breaks in the text returned by `TextInputBuffer::get_text`.
+/// Text set, appended or pasted into a text input has its line separators converted back to
+/// soft breaks.
+pub const LINE_SEPARATOR: char = '\u{2028}';
+
+/// `text` as it is inserted into a text input's buffer. Line separators become soft breaks, and a
+/// line feed followed by a carriage return, which the buffer would read as a soft break, becomes
+/// two paragraph breaks.
+pub(crate) fn to_buffer_text(text: &str) -> std::borrow::Cow<'_, str> {
+    if text.contains(LINE_SEPARATOR) || text.contains("\n\r") {
+        text.replace("\n\r", "\n\n")
+            .replace(LINE_SEPARATOR, SOFT_BREAK.as_str())
+            .into()
+    } else {
+        text.into()
+    }
+}
+
+/// The character a line ending is represented by in the text returned by `get_text`.
+pub(crate) fn line_break(ending: LineEnding) -> char {
+    if ending == SOFT_BREAK {
+        LINE_SEPARATOR
+    } else {
+        '\n'
+    }
+}
+
+fn get_text(buffer: &Buffer) -> String {
+    buffer
+        .lines
+        .iter()
+        .enumerate()
+        .fold(String::new(), |mut out, (index, line)| {
+            if 0 < index {
+                out.push(line_break(buffer.lines[index - 1].ending()));
+            }
+            out.push_str(line.text());
+            out
+        })
+}
+
+/// The buffer's text including its line endings, so that setting it again preserves soft breaks.
+fn get_text_with_line_endings(buffer: &Buffer) -> String {
+    buffer
+        .lines
+        .iter()
+        .flat_map(|line| [line.text(), line.ending().as_str()])
+        .collect()
+}
+
+#[derive(Component, Clone, Default, Debug, Reflect)]
+#[reflect(Component, Default, Debug)]
+pub struct TextInputLayoutInfo {
+    pub glyphs: Vec<TextInputGlyph>,
     pub size: Vec2,
+    /// The "…" drawn at the clipped edge of an unfocused single-line input with `Overflow::Ellipsis`,
+    /// if its text overflows. The glyphs behind it are removed from `glyphs`.
+    pub ellipsis: Option<TextInputGlyph>,
+}
+
+#[derive(Component, Clone, Default, Debug, Reflect)]
+#[reflect(Component, Default, Debug)]
+pub struct TextInputPromptLayoutInfo {
+    pub glyphs: Vec<TextInputGlyph>,
+    pub size: Vec2,
+}
+
+#[derive(Component, Clone, Default, Debug, Reflect)]
+#[reflect(Component, Default, Debug)]
+pub struct TextInputGutterLayoutInfo {
+    pub glyphs: Vec<TextInputGlyph>,
+    /// Number and baseline of each numbered line the glyphs were laid out for
+    pub(crate) lines: Vec<(usize, f32)>,
+}
+
+#[derive(Component, Clone, Default, Debug, Reflect)]
+#[reflect(Component, Default, Debug)]
+pub struct TextInputSpellcheckLayoutInfo {
+    /// Underline rects, relative to the top left corner of the text
+    pub rects: Vec<Rect>,
+}
+
+#[derive(Component, Clone, Default, Debug, Reflect)]
+#[reflect(Component, Default, Debug)]
+pub struct TextInputHighlightsLayoutInfo {
+    /// Highlight rects and their colors, relative to the top left corner of the text
+    pub rects: Vec<(Rect, Color)>,
 }
 
 #[derive(Debug, Clone, Reflect)]
@@ -428,19 +2051,43 @@ pub struct TextInputGlyph {
     pub byte_length: usize,
 }
 
-#[derive(Default, Debug, Component, PartialEq)]
+/// The text of a text input, updated by `update_text_input_contents` after edits.
+///
+/// Equality and hashing only consider the text, not the version.
+#[derive(Default, Debug, Component)]
 pub struct TextInputContents {
     text: String,
+    version: u64,
 }
 
 impl TextInputContents {
     pub fn get(&self) -> &str {
         &self.text
     }
+
+    /// Incremented each time the text changes. Monotonic per entity, starting at 0.
+    pub fn version(&self) -> u64 {
+        self.version
+    }
+}
+
+impl PartialEq for TextInputContents {
+    fn eq(&self, other: &Self) -> bool {
+        self.text == other.text
+    }
+}
+
+impl Eq for TextInputContents {}
+
+impl core::hash::Hash for TextInputContents {
+    fn hash<H: core::hash::Hasher>(&self, state: &mut H) {
+        self.text.hash(state);
+    }
 }
 
 pub fn update_text_input_contents(
     mut commands: Commands,
+    mut changed_writer: MessageWriter<TextChanged>,
     mut query: Query<
         (Entity, &TextInputBuffer, Option<&TextInputContents>),
         Changed<TextInputBuffer>,
@@ -455,7 +2102,351 @@ pub fn update_text_input_contents(
             continue;
         };
 
-        commands.entity(entity).insert(TextInputContents { text });
+        // Inputs without contents yet are treated as empty, like by `TextInputRefItem::text`
+        if !text.is_empty() || contents_option.is_some() {
+            commands.trigger(OnTextChanged {
+                entity,
+                text: text.clone(),
+            });
+            changed_writer.write(TextChanged {
+                entity,
+                text: text.clone(),
+            });
+        }
+
+        let version = contents_option.map_or(0, |contents| contents.version + 1);
+        commands
+            .entity(entity)
+            .insert(TextInputContents { text, version });
+    }
+}
+
+/// Mirrors the text of another text input, for preview panes.
+///
+/// The text input with this component is read-only, edits that would change its text are rejected with
+/// [`TextInputRejection::ReadOnly`]. Its text is replaced whenever the source's text changes.
+/// If the source is despawned, mirroring stops and the component is removed.
+#[derive(Component, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MirrorTextInput {
+    /// The text input entity to copy the text from
+    pub source: Entity,
+}
+
+/// Copy the text of each [`MirrorTextInput`]'s source into its buffer, when the source's text changes
+pub fn mirror_text_inputs(
+    mut commands: Commands,
+    sources: Query<Ref<TextInputContents>>,
+    mut mirrors: Query<(Entity, Ref<MirrorTextInput>, &mut TextInputBuffer)>,
+) {
+    for (entity, mirror, mut buffer) in mirrors.iter_mut() {
+        let Ok(contents) = sources.get(mirror.source) else {
+            if commands.get_entity(mirror.source).is_err() {
+                commands.entity(entity).remove::<MirrorTextInput>();
+            }
+            continue;
+        };
+        if contents.is_changed() || mirror.is_changed() {
+            buffer.set_text(contents.get());
+        }
+    }
+}
+
+/// Summary of a text input's interactive state, for convenient querying from app code.
+///
+/// This is a read-only cache updated by `update_text_input_state` after layout, when the input's
+/// buffer, its layout or the focus changed.
+/// Changes made to it directly are overwritten.
+#[derive(Component, Clone, Default, Debug, PartialEq, Eq, Reflect)]
+#[reflect(Component, Default, Debug)]
+pub struct TextInputState {
+    /// The text input is the entity held by the `InputFocus` resource
+    pub focused: bool,
+    /// Some text is selected
+    pub has_selection: bool,
+    /// The text input contains no text
+    pub is_empty: bool,
+    /// Number of characters in the text, including line breaks
+    pub char_count: usize,
+    /// The text doesn't fit within the text input's bounds
+    pub overflowing: bool,
+}
+
+/// Returns true if the laid out text is wider or taller than the buffer's size.
+fn buffer_overflows(buffer: &Buffer) -> bool {
+    let (width, height) = buffer.size();
+    let mut total_height = 0.;
+    for layout_line in buffer
+        .lines
+        .iter()
+        .filter_map(|line| line.layout_opt())
+        .flatten()
+    {
+        if width.is_some_and(|width| width < layout_line.w) {
+            return true;
+        }
+        total_height += layout_line
+            .line_height_opt
+            .unwrap_or(buffer.metrics().line_height);
+    }
+    height.is_some_and(|height| height < total_height)
+}
+
+pub fn update_text_input_state(
+    input_focus: Res<InputFocus>,
+    mut query: Query<(
+        Entity,
+        Ref<TextInputBuffer>,
+        Ref<ComputedNode>,
+        Ref<TextFont>,
+        Ref<LineHeight>,
+        Ref<TextInputNode>,
+        Option<Ref<TextInputGutter>>,
+        &mut TextInputState,
+    )>,
+) {
+    for (entity, buffer, node, text_font, line_height, input, gutter, mut state) in query.iter_mut()
+    {
+        // The state only depends on the text, its layout and focus. Layout doesn't mark the buffer
+        // as changed, so also check the components `text_input_system` lays it out with.
+        let relaid_out = node.is_changed()
+            || text_font.is_changed()
+            || line_height.is_changed()
+            || input.is_changed()
+            || gutter.is_some_and(|gutter| gutter.is_changed());
+        if !(buffer.is_changed() || relaid_out || input_focus.is_changed()) {
+            continue;
+        }
+        let (char_count, overflowing) = buffer.editor.with_buffer(|buffer| {
+            (
+                edit::buffer_len(buffer) + buffer.lines.len().saturating_sub(1),
+                buffer_overflows(buffer),
+            )
+        });
+        state.set_if_neq(TextInputState {
+            focused: is_focused(entity, &input_focus),
+            has_selection: buffer
+                .editor
+                .selection_bounds()
+                .is_some_and(|(start, end)| (start.line, start.index) != (end.line, end.index)),
+            is_empty: char_count == 0,
+            char_count,
+            overflowing,
+        });
+    }
+}
+
+/// Validation state shown by a [`TextInputHint`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Reflect)]
+pub enum TextInputHintStatus {
+    /// The last edit was accepted and the text is below the `max_chars` limit
+    #[default]
+    Valid,
+    /// The last keystroke or paste was rejected by the input's filter
+    Invalid,
+    /// The text has reached the `max_chars` limit
+    AtLimit,
+}
+
+/// Keeps the text of a separate `Text` entity, such as a line below the input, in sync with the
+/// input's validation state. The app is responsible for spawning and positioning the hint entity.
+/// Optional component.
+#[derive(Component, Clone, Debug, Reflect)]
+#[reflect(Component, Debug)]
+pub struct TextInputHint {
+    /// Entity with the `Text` component that displays the hint
+    pub target: Entity,
+    /// Hint shown while the input is valid
+    pub valid: String,
+    /// Hint shown after a keystroke or paste is rejected by the input's filter,
+    /// until the text next changes
+    pub invalid: String,
+    /// Hint shown while the text is at the `max_chars` limit
+    pub at_limit: String,
+    status: TextInputHintStatus,
+}
+
+impl TextInputHint {
+    /// Show `text` in the `target` entity, whatever the state of the input
+    pub fn new(target: Entity, text: impl Into<String>) -> Self {
+        let text = text.into();
+        Self {
+            target,
+            valid: text.clone(),
+            invalid: text.clone(),
+            at_limit: text,
+            status: TextInputHintStatus::Valid,
+        }
+    }
+
+    /// Hint shown after a keystroke or paste is rejected by the input's filter
+    pub fn with_invalid(mut self, text: impl Into<String>) -> Self {
+        self.invalid = text.into();
+        self
+    }
+
+    /// Hint shown while the text is at the `max_chars` limit
+    pub fn with_at_limit(mut self, text: impl Into<String>) -> Self {
+        self.at_limit = text.into();
+        self
+    }
+
+    /// The validation state currently shown
+    pub fn status(&self) -> TextInputHintStatus {
+        self.status
+    }
+
+    /// The hint text for the current validation state
+    pub fn text(&self) -> &str {
+        match self.status {
+            TextInputHintStatus::Valid => &self.valid,
+            TextInputHintStatus::Invalid => &self.invalid,
+            TextInputHintStatus::AtLimit => &self.at_limit,
+        }
+    }
+}
+
+pub fn text_input_hint_system(
+    mut rejected_reader: MessageReader<TextInputRejected>,
+    mut hint_query: Query<(
+        Entity,
+        &TextInputNode,
+        &TextInputBuffer,
+        Option<Ref<TextInputContents>>,
+        &mut TextInputHint,
+    )>,
+    mut text_query: Query<&mut Text>,
+) {
+    let rejected: HashMap<Entity, TextInputRejection> = rejected_reader
+        .read()
+        .map(|rejected| (rejected.entity, rejected.reason))
+        .collect();
+
+    for (entity, input, buffer, contents, mut hint) in hint_query.iter_mut() {
+        let status = match rejected.get(&entity) {
+            Some(TextInputRejection::Filter) => TextInputHintStatus::Invalid,
+            Some(TextInputRejection::MaxChars) => TextInputHintStatus::AtLimit,
+            // Rejections by read-only inputs aren't a validation problem, treat them as no rejection.
+            // An invalid hint stays until the text changes
+            _ if hint.status == TextInputHintStatus::Invalid
+                && !contents.is_some_and(|contents| contents.is_changed()) =>
+            {
+                TextInputHintStatus::Invalid
+            }
+            // `max_chars` doesn't count line breaks, unlike `TextInputState::char_count`
+            _ if input
+                .max_chars
+                .is_some_and(|max| max <= buffer.editor.with_buffer(edit::buffer_len)) =>
+            {
+                TextInputHintStatus::AtLimit
+            }
+            _ => TextInputHintStatus::Valid,
+        };
+
+        if status == hint.status && !hint.is_changed() {
+            continue;
+        }
+        hint.status = status;
+
+        if let Ok(mut text) = text_query.get_mut(hint.target)
+            && text.0 != hint.text()
+        {
+            text.0 = hint.text().to_string();
+        }
+    }
+}
+
+/// App-wide text input configuration, set using the `TextInputPlugin` builder methods.
+#[derive(Resource, Clone, Debug)]
+pub struct TextInputConfig {
+    /// Default time the cursor blinks in seconds, used when `TextInputStyle::blink_interval` is `None`.
+    pub blink_interval: f32,
+    /// Multiplier applied to mouse wheel scrolling.
+    pub scroll_speed: f32,
+    /// Clock that advances the cursor blink
+    pub blink_clock: BlinkClock,
+    /// Distance in logical pixels the pointer can move from the first click of a double or
+    /// triple click before the click count is reset.
+    pub multi_click_distance: f32,
+    /// Key bindings checked before the built-in ones
+    pub keymap: TextInputKeymap,
+}
+
+/// Key bindings that take precedence over the built-in ones, see `TextInputPlugin::with_keymap`.
+///
+/// ```ignore
+/// TextInputKeymap::default().bind(KeyCode::KeyD, true, false, || {
+///     TextInputAction::Edit(TextInputEdit::DeleteLine)
+/// })
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct TextInputKeymap {
+    bindings: Vec<TextInputKeyBinding>,
+}
+
+/// A key bound to an action in a `TextInputKeymap`
+#[derive(Copy, Clone, Debug)]
+pub struct TextInputKeyBinding {
+    /// Physical key that triggers the binding
+    pub key: KeyCode,
+    /// Ctrl or Command must be held
+    pub command: bool,
+    /// Shift must be held
+    pub shift: bool,
+    /// Creates the action queued when the binding is pressed
+    pub action: fn() -> TextInputAction,
+}
+
+impl TextInputKeymap {
+    /// Bind `key`, pressed with exactly these modifiers, to the action returned by `action`.
+    /// Replaces any earlier binding of the same key and modifiers.
+    pub fn bind(
+        mut self,
+        key: KeyCode,
+        command: bool,
+        shift: bool,
+        action: fn() -> TextInputAction,
+    ) -> Self {
+        self.bindings.retain(|binding| {
+            (binding.key, binding.command, binding.shift) != (key, command, shift)
+        });
+        self.bindings.push(TextInputKeyBinding {
+            key,
+            command,
+            shift,
+            action,
+        });
+        self
+    }
+
+    /// The action bound to `key` pressed with these modifiers, if any
+    pub fn action(&self, key: KeyCode, command: bool, shift: bool) -> Option<TextInputAction> {
+        self.bindings
+            .iter()
+            .find(|binding| (binding.key, binding.command, binding.shift) == (key, command, shift))
+            .map(|binding| (binding.action)())
+    }
+}
+
+/// Clock that advances the cursor blink, see `TextInputConfig::blink_clock`
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum BlinkClock {
+    /// The default `Time`, virtual time in `Update`. The blink stops while virtual time is paused
+    /// and follows its relative speed.
+    #[default]
+    Virtual,
+    /// Wall-clock `Time<Real>`, unaffected by pausing or scaling virtual time
+    Real,
+}
+
+impl Default for TextInputConfig {
+    fn default() -> Self {
+        Self {
+            blink_interval: 0.5,
+            scroll_speed: 1.,
+            blink_clock: BlinkClock::Virtual,
+            multi_click_distance: 4.,
+            keymap: TextInputKeymap::default(),
+        }
     }
 }
 
@@ -465,8 +2456,10 @@ pub struct TextInputGlobalState {
     pub shift: bool,
     /// Ctrl or Command key is held down
     pub command: bool,
-    /// If true typed glyphs overwrite the glyph at the current cursor position, instead of inserting before it.
-    pub overwrite_mode: bool,
+    /// Alt or Option key is held down
+    pub alt: bool,
+    /// Text input focused by a pointer press since focus changes were last checked
+    pub pointer_focused: Option<Entity>,
 }
 
 /// Queued `TextInputActions` to be processed by `process_text_input_queues` and applied to the `TextInputBuffer`
@@ -499,3 +2492,385 @@ impl Iterator for TextInputQueue {
         self.actions.pop_front()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A font system with a single monospace font, enough to lay out and edit text without an `App`
+    pub(crate) fn font_system() -> cosmic_text::FontSystem {
+        let mut fonts = cosmic_text::fontdb::Database::new();
+        fonts.load_font_data(include_bytes!("../assets/fonts/FiraMono-Medium.ttf").to_vec());
+        cosmic_text::FontSystem::new_with_locale_and_db("en-US".into(), fonts)
+    }
+
+    fn type_str(
+        buffer: &mut TextInputBuffer,
+        font_system: &mut cosmic_text::FontSystem,
+        text: &str,
+    ) {
+        for ch in text.chars() {
+            buffer.apply_edit(TextInputEdit::Insert(ch, false), font_system, None, None);
+        }
+    }
+
+    #[test]
+    fn apply_edit_inserts_and_pastes() {
+        let mut font_system = font_system();
+        let mut buffer = TextInputBuffer::default();
+        type_str(&mut buffer, &mut font_system, "hello");
+        assert_eq!(buffer.get_text(), "hello");
+
+        buffer.apply_edit(
+            TextInputEdit::Paste(" world\nagain".into()),
+            &mut font_system,
+            None,
+            None,
+        );
+        assert_eq!(buffer.get_text(), "hello world\nagain");
+    }
+
+    #[test]
+    fn apply_edit_respects_max_chars() {
+        let mut font_system = font_system();
+        let mut buffer = TextInputBuffer::default();
+        type_str(&mut buffer, &mut font_system, "abc");
+
+        let rejection = buffer.apply_edit(
+            TextInputEdit::Insert('d', false),
+            &mut font_system,
+            Some(3),
+            None,
+        );
+        assert_eq!(rejection, Some(TextInputRejection::MaxChars));
+        assert_eq!(buffer.get_text(), "abc");
+    }
+
+    #[test]
+    fn apply_edit_rejects_filtered_text() {
+        let mut font_system = font_system();
+        let mut buffer = TextInputBuffer::default();
+        let filters = [
+            TextInputFilter::Integer,
+            TextInputFilter::custom(|text| TextInputFilter::Integer.is_match(text)),
+        ];
+        for filter in &filters {
+            buffer.set_text("12");
+            buffer.editor.set_cursor(cosmic_text::Cursor::new(0, 2));
+            for (edit, rejection) in [
+                (TextInputEdit::Insert('3', false), None),
+                (
+                    TextInputEdit::Insert('a', false),
+                    Some(TextInputRejection::Filter),
+                ),
+                (
+                    TextInputEdit::Paste("4x".into()),
+                    Some(TextInputRejection::Filter),
+                ),
+            ] {
+                assert_eq!(
+                    buffer.apply_edit(edit, &mut font_system, None, Some(filter)),
+                    rejection
+                );
+            }
+            assert_eq!(buffer.get_text(), "123");
+        }
+    }
+
+    #[test]
+    fn apply_edit_undo_and_redo() {
+        let mut font_system = font_system();
+        let mut buffer = TextInputBuffer::default();
+        buffer.apply_edit(
+            TextInputEdit::Paste("one".into()),
+            &mut font_system,
+            None,
+            None,
+        );
+        buffer.apply_edit(
+            TextInputEdit::Paste(" two".into()),
+            &mut font_system,
+            None,
+            None,
+        );
+
+        buffer.apply_edit(TextInputEdit::Undo, &mut font_system, None, None);
+        assert_eq!(buffer.get_text(), "one");
+        buffer.apply_edit(TextInputEdit::Redo, &mut font_system, None, None);
+        assert_eq!(buffer.get_text(), "one two");
+    }
+
+    #[test]
+    fn replace_all_is_case_sensitive_across_lines() {
+        let mut font_system = font_system();
+        let mut buffer = TextInputBuffer::default();
+        buffer.set_text("cat Cat\ncat dog\nCAT cat");
+
+        assert_eq!(buffer.replace_all("cat", "dog", None, None), 3);
+        assert_eq!(buffer.get_text(), "dog Cat\ndog dog\nCAT dog");
+        assert_eq!(buffer.find_next("dog", 5), Some(8));
+        assert_eq!(buffer.find_next("cat", 0), None);
+
+        // All the replacements are undone together
+        buffer.apply_edit(TextInputEdit::Undo, &mut font_system, None, None);
+        assert_eq!(buffer.get_text(), "cat Cat\ncat dog\nCAT cat");
+    }
+
+    #[test]
+    fn select_word_at_position() {
+        let mut font_system = font_system();
+        let mut buffer = TextInputBuffer::default();
+        buffer.set_text("hello brave world");
+        let width = buffer.editor.with_buffer_mut(|buffer| {
+            buffer.set_size(&mut font_system, Some(500.), Some(100.));
+            buffer.shape_until_scroll(&mut font_system, false);
+            buffer.layout_runs().next().unwrap().glyphs[0].w
+        });
+
+        // The middle of the "a" in "brave", with no prior click
+        buffer.select_word_at(&mut font_system, 8.5 * width, 5.);
+        assert_eq!(buffer.editor.copy_selection().as_deref(), Some("brave"));
+    }
+
+    #[test]
+    fn snapshot_round_trips_text_and_cursor() {
+        let mut font_system = font_system();
+        let mut buffer = TextInputBuffer::default();
+        buffer.set_text("one\ntwo");
+        buffer.editor.set_cursor(Cursor::new(1, 1));
+        let snapshot = buffer.snapshot();
+
+        type_str(&mut buffer, &mut font_system, "xyz");
+        buffer.editor.set_cursor(Cursor::new(0, 0));
+        buffer.restore(&snapshot, None);
+
+        assert_eq!(buffer.get_text(), "one\ntwo");
+        let cursor = buffer.editor.cursor();
+        assert_eq!((cursor.line, cursor.index), (1, 1));
+        assert_eq!(buffer.snapshot(), snapshot);
+    }
+
+    #[test]
+    fn word_at_cursor_inside_and_at_edges() {
+        let mut buffer = TextInputBuffer::default();
+        buffer.set_text("one two  three\nfour");
+        for (line, index, expected) in [
+            (0, 5, Some(("two", 4..7))),
+            (0, 4, Some(("two", 4..7))),
+            (0, 7, Some(("two", 4..7))),
+            (0, 8, None),
+            (1, 0, Some(("four", 15..19))),
+        ] {
+            buffer.editor.set_cursor(Cursor::new(line, index));
+            assert_eq!(
+                buffer.word_at_cursor(),
+                expected.map(|(word, range)| (word.to_string(), range)),
+                "cursor at {line}:{index}"
+            );
+        }
+    }
+
+    #[test]
+    fn focused_text_input_ignores_other_widgets() {
+        use bevy::ecs::system::RunSystemOnce;
+        use bevy::ecs::world::World;
+
+        let mut world = World::new();
+        let input = world.spawn(TextInputNode::default()).id();
+        let button = world.spawn_empty().id();
+        for (focus, expected) in [
+            (None, None),
+            (Some(input), Some(input)),
+            (Some(button), None),
+        ] {
+            world.insert_resource(InputFocus(focus));
+            let focused = world
+                .run_system_once(|focus: Res<InputFocus>, query: Query<&TextInputNode>| {
+                    focused_text_input(&focus, &query)
+                })
+                .unwrap();
+            assert_eq!(focused, expected);
+        }
+    }
+
+    #[test]
+    fn truncate_mid_line_keeps_char_len() {
+        let mut font_system = font_system();
+        let mut buffer = TextInputBuffer::default();
+        buffer.set_text("abc\ndefgh\nij");
+        buffer
+            .editor
+            .set_selection(Selection::Normal(Cursor::new(0, 1)));
+        buffer.editor.set_cursor(Cursor::new(2, 2));
+
+        assert!(buffer.truncate(5));
+        assert_eq!(buffer.get_text(), "abc\nde");
+        assert_eq!(buffer.editor.selection(), Selection::None);
+        let cursor = buffer.editor.cursor();
+        assert_eq!((cursor.line, cursor.index), (1, 2));
+        assert!(!buffer.truncate(5));
+
+        buffer.apply_edit(TextInputEdit::Undo, &mut font_system, None, None);
+        assert_eq!(buffer.get_text(), "abc\ndefgh\nij");
+    }
+
+    #[test]
+    fn with_editor_runs_custom_motion() {
+        use cosmic_text::{Action, Motion};
+
+        let mut pipeline = TextInputPipeline {
+            font_system: font_system(),
+            ..Default::default()
+        };
+        let mut buffer = TextInputBuffer::default();
+        buffer.set_text("hello world");
+        buffer.editor.set_cursor(Cursor::new(0, 0));
+        buffer.needs_update = false;
+
+        let cursor = buffer.with_editor(&mut pipeline, |editor| {
+            editor.action(Action::Motion(Motion::NextWord));
+            editor.insert_string("!", None);
+            editor.cursor()
+        });
+        assert_eq!((cursor.line, cursor.index), (0, 6));
+        assert_eq!(buffer.get_text(), "hello! world");
+        assert!(buffer.needs_update);
+    }
+
+    #[test]
+    fn plugin_adds_input_dispatch_unless_disabled() {
+        use bevy::input_focus::InputDispatchPlugin;
+
+        let mut app = bevy::app::App::new();
+        app.add_plugins(TextInputPlugin::headless());
+        assert!(app.is_plugin_added::<InputDispatchPlugin>());
+
+        // Already added by the app
+        let mut app = bevy::app::App::new();
+        app.add_plugins((InputDispatchPlugin, TextInputPlugin::headless()));
+        app.finish();
+
+        let mut app = bevy::app::App::new();
+        app.add_plugins(TextInputPlugin::headless().without_input_dispatch());
+        assert!(!app.is_plugin_added::<InputDispatchPlugin>());
+        app.add_plugins(InputDispatchPlugin);
+        app.finish();
+    }
+
+    #[test]
+    #[should_panic(expected = "`TextInputPlugin` requires the `InputFocus` resource")]
+    fn plugin_without_input_dispatch_requires_input_focus() {
+        let mut app = bevy::app::App::new();
+        app.add_plugins(TextInputPlugin::headless().without_input_dispatch());
+        app.finish();
+    }
+
+    #[test]
+    fn state_tracks_overflow_after_font_change() {
+        use crate::text_input_pipeline::tests::layout_world;
+        use crate::text_input_pipeline::text_input_system;
+
+        let (mut world, entity) = layout_world(TextInputNode::default(), "abc");
+        let mut schedule = bevy::ecs::schedule::Schedule::default();
+        schedule.add_systems((text_input_system, update_text_input_state).chain());
+        schedule.run(&mut world);
+        assert!(!world.get::<TextInputState>(entity).unwrap().overflowing);
+
+        // A single line no longer fits in the 30px tall node
+        world.get_mut::<TextFont>(entity).unwrap().font_size = 40.;
+        schedule.run(&mut world);
+        assert!(world.get::<TextInputState>(entity).unwrap().overflowing);
+    }
+
+    #[test]
+    fn selection_line_col_normalizes_reversed_selection() {
+        let mut font_system = font_system();
+        let mut buffer = TextInputBuffer::default();
+        buffer.apply_edit(
+            TextInputEdit::Paste("héllo\nwörld".into()),
+            &mut font_system,
+            None,
+            None,
+        );
+        assert_eq!(buffer.selection_line_col(), None);
+
+        // From after "h" on the first line to after "wö" on the second, dragged either way
+        let start = Cursor::new(0, "h".len());
+        let end = Cursor::new(1, "wö".len());
+        for (anchor, cursor) in [(start, end), (end, start)] {
+            buffer.editor.set_cursor(cursor);
+            buffer.editor.set_selection(Selection::Normal(anchor));
+            assert_eq!(
+                buffer.selection_line_col(),
+                Some((
+                    LineCol { line: 0, column: 1 },
+                    LineCol { line: 1, column: 2 }
+                ))
+            );
+        }
+    }
+
+    #[test]
+    fn actions_in_pre_update_visible_in_update() {
+        use bevy::app::Update;
+        use bevy::ecs::system::ResMut;
+        use bevy::input::ButtonState;
+        use bevy::input::keyboard::{Key, KeyboardInput};
+
+        #[derive(Resource, Default)]
+        struct SeenInUpdate(Option<String>);
+
+        let mut app = bevy::app::App::new();
+        app.add_plugins((
+            bevy::time::TimePlugin,
+            bevy::input::InputPlugin,
+            TextInputPlugin::headless().with_actions_in_pre_update(),
+        ))
+        .init_resource::<SeenInUpdate>()
+        .add_systems(
+            Update,
+            |contents: Query<&TextInputContents>, mut seen: ResMut<SeenInUpdate>| {
+                seen.0 = contents.iter().next().map(|contents| contents.get().into());
+            },
+        );
+        app.finish();
+        let world = app.world_mut();
+        // Focused input is only dispatched in apps with a primary window
+        world.spawn((bevy::window::Window::default(), bevy::window::PrimaryWindow));
+        let entity = world.spawn(TextInputNode::default()).id();
+        world.resource_mut::<InputFocus>().set(entity);
+        world.write_message(KeyboardInput {
+            key_code: KeyCode::KeyA,
+            logical_key: Key::Character("a".into()),
+            state: ButtonState::Pressed,
+            text: Some("a".into()),
+            repeat: false,
+            window: Entity::PLACEHOLDER,
+        });
+
+        // Only the schedules up to `Update`, layout in `PostUpdate` isn't needed to see the edit
+        world.run_schedule(PreUpdate);
+        world.run_schedule(Update);
+        assert_eq!(world.resource::<SeenInUpdate>().0.as_deref(), Some("a"));
+    }
+
+    #[test]
+    fn trailing_newline_leaves_empty_last_line() {
+        let mut font_system = font_system();
+        let mut buffer = TextInputBuffer::default();
+        buffer.apply_edit(
+            TextInputEdit::Insert('a', false),
+            &mut font_system,
+            None,
+            None,
+        );
+        buffer.apply_edit(TextInputEdit::Enter, &mut font_system, None, None);
+
+        assert_eq!(buffer.get_text(), "a\n");
+        assert_eq!(buffer.editor.cursor(), Cursor::new(1, 0));
+        buffer.editor.with_buffer(|buffer| {
+            assert_eq!(buffer.lines.len(), 2);
+            assert_eq!(buffer.lines[1].text(), "");
+        });
+    }
+}
@@ -0,0 +1,91 @@
+use bevy::ecs::entity::Entity;
+use bevy::ecs::query::QueryData;
+use bevy::input_focus::InputFocus;
+use cosmic_text::Cursor;
+use cosmic_text::Edit;
+
+use crate::TextInputBuffer;
+use crate::TextInputContents;
+use crate::TextInputNode;
+use crate::TextInputQueue;
+use crate::actions::TextInputAction;
+use crate::is_focused;
+
+/// Read-only access to a text input's settings, buffer and contents in a single query parameter.
+///
+/// ```ignore
+/// fn counter(inputs: Query<TextInputRef>) {
+///     for input in &inputs {
+///         println!("{}: {} chars", input.entity, input.text().chars().count());
+///     }
+/// }
+/// ```
+#[derive(QueryData)]
+pub struct TextInputRef {
+    pub entity: Entity,
+    pub node: &'static TextInputNode,
+    pub buffer: &'static TextInputBuffer,
+    pub contents: Option<&'static TextInputContents>,
+}
+
+impl TextInputRefItem<'_, '_> {
+    /// The input's text, as of the last `update_text_input_contents`.
+    /// Empty until the contents are first updated.
+    pub fn text(&self) -> &str {
+        self.contents.map_or("", TextInputContents::get)
+    }
+
+    /// Position of the cursor
+    pub fn caret(&self) -> Cursor {
+        self.buffer.editor.cursor()
+    }
+
+    /// Start and end of the selection, if any
+    pub fn selection(&self) -> Option<(Cursor, Cursor)> {
+        self.buffer.editor.selection_bounds()
+    }
+
+    /// True if the input is the focused entity
+    pub fn is_focused(&self, input_focus: &InputFocus) -> bool {
+        is_focused(self.entity, input_focus)
+    }
+}
+
+/// Like [`TextInputRef`], with mutable access to the input's `TextInputQueue` for queueing actions.
+#[derive(QueryData)]
+#[query_data(mutable)]
+pub struct TextInputMut {
+    pub entity: Entity,
+    pub node: &'static TextInputNode,
+    pub buffer: &'static TextInputBuffer,
+    pub contents: Option<&'static TextInputContents>,
+    pub queue: &'static mut TextInputQueue,
+}
+
+impl TextInputMutItem<'_, '_> {
+    /// The input's text, as of the last `update_text_input_contents`.
+    /// Empty until the contents are first updated.
+    pub fn text(&self) -> &str {
+        self.contents.map_or("", TextInputContents::get)
+    }
+
+    /// Position of the cursor
+    pub fn caret(&self) -> Cursor {
+        self.buffer.editor.cursor()
+    }
+
+    /// Start and end of the selection, if any
+    pub fn selection(&self) -> Option<(Cursor, Cursor)> {
+        self.buffer.editor.selection_bounds()
+    }
+
+    /// True if the input is the focused entity
+    pub fn is_focused(&self, input_focus: &InputFocus) -> bool {
+        is_focused(self.entity, input_focus)
+    }
+
+    /// Queue an action to be processed by `process_text_input_queues`
+    pub fn add(&mut self, action: TextInputAction) {
+        self.queue.add(action);
+    }
+}
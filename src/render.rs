@@ -1,18 +1,36 @@
+use crate::SelectionLayer;
 use crate::TextInputBuffer;
+use crate::TextInputConfig;
+use crate::TextInputCursorBlink;
+use crate::TextInputError;
 use crate::TextInputGlyph;
+use crate::TextInputGutter;
+use crate::TextInputGutterLayoutInfo;
+use crate::TextInputHighlightsLayoutInfo;
+use crate::TextInputHint;
+use crate::TextInputHintStatus;
 use crate::TextInputLayoutInfo;
 use crate::TextInputNode;
 use crate::TextInputPrompt;
 use crate::TextInputPromptLayoutInfo;
+use crate::TextInputSpellcheck;
+use crate::TextInputSpellcheckLayoutInfo;
+use crate::TextInputStateColors;
 use crate::TextInputStyle;
 use crate::edit::is_buffer_empty;
+use crate::is_focused;
 use bevy::asset::AssetId;
 use bevy::asset::Assets;
 use bevy::camera::visibility::InheritedVisibility;
 use bevy::color::Alpha;
+use bevy::color::Color;
 use bevy::color::LinearRgba;
+use bevy::color::Luminance;
 use bevy::ecs::entity::Entity;
+use bevy::ecs::entity::EntityHashMap;
+use bevy::ecs::query::Has;
 use bevy::ecs::system::Commands;
+use bevy::ecs::system::Local;
 use bevy::ecs::system::Query;
 use bevy::ecs::system::Res;
 use bevy::ecs::system::ResMut;
@@ -25,6 +43,8 @@ use bevy::render::Extract;
 use bevy::render::sync_world::TemporaryRenderEntity;
 use bevy::sprite::BorderRect;
 use bevy::text::TextColor;
+use bevy::time::Time;
+use bevy::ui::BackgroundColor;
 use bevy::ui::CalculatedClip;
 use bevy::ui::ComputedNode;
 use bevy::ui::ComputedUiTargetCamera;
@@ -36,14 +56,55 @@ use bevy::ui_render::ExtractedUiNode;
 use bevy::ui_render::ExtractedUiNodes;
 use bevy::ui_render::NodeType;
 use bevy::ui_render::UiCameraMap;
+use bevy::ui_render::shader_flags;
 use bevy::ui_render::stack_z_offsets;
 use cosmic_text::Edit;
+use cosmic_text::Wrap;
+use std::f32::consts::FRAC_1_SQRT_2;
+use std::ops::Range;
+
+// Explicit offsets from the glyphs, so that the layering doesn't depend on the extraction order.
+// The fill is drawn below the highlights, highlights below the selection rects, selection rects below the
+// glyphs and spellcheck underlines, and the cursor above everything.
+const FILL_Z_OFFSET: f32 = stack_z_offsets::TEXT - 0.003;
+const HIGHLIGHT_Z_OFFSET: f32 = stack_z_offsets::TEXT - 0.0025;
+const SELECTION_Z_OFFSET: f32 = stack_z_offsets::TEXT - 0.002;
+const UNDERLINE_Z_OFFSET: f32 = stack_z_offsets::TEXT - 0.001;
+const SELECTION_OVER_TEXT_Z_OFFSET: f32 = stack_z_offsets::TEXT + 0.0005;
+const CURSOR_Z_OFFSET: f32 = stack_z_offsets::TEXT + 0.001;
+
+/// Animated cursor position of a text input, in buffer coordinates
+#[derive(Default)]
+pub struct CaretAnimation {
+    from: Vec2,
+    to: Vec2,
+    elapsed: f32,
+    generation: u32,
+}
+
+impl CaretAnimation {
+    fn position(&self, duration: f32) -> Vec2 {
+        let t = (self.elapsed / duration).clamp(0., 1.);
+        // Ease out
+        self.from.lerp(self.to, 1. - (1. - t) * (1. - t))
+    }
+}
+
+/// Cursor animations of the text inputs extracted last frame
+#[derive(Default)]
+pub struct CaretAnimations {
+    generation: u32,
+    carets: EntityHashMap<CaretAnimation>,
+}
 
 pub fn extract_text_input_nodes(
     mut commands: Commands,
+    mut caret_animations: Local<CaretAnimations>,
+    time: Extract<Res<Time>>,
     mut extracted_uinodes: ResMut<ExtractedUiNodes>,
     texture_atlases: Extract<Res<Assets<TextureAtlasLayout>>>,
     active_text_input: Extract<Res<InputFocus>>,
+    config: Extract<Res<TextInputConfig>>,
     uinode_query: Extract<
         Query<(
             Entity,
@@ -57,14 +118,24 @@ pub fn extract_text_input_nodes(
             &TextInputStyle,
             &TextInputNode,
             &TextInputBuffer,
+            Option<&TextInputGutter>,
+            Option<(&TextInputSpellcheck, &TextInputSpellcheckLayoutInfo)>,
+            (
+                &TextInputCursorBlink,
+                Option<&BackgroundColor>,
+                Option<&TextInputHighlightsLayoutInfo>,
+                Option<&TextInputStateColors>,
+                Option<&TextInputHint>,
+                Has<TextInputError>,
+            ),
         )>,
     >,
     camera_map: Extract<UiCameraMap>,
 ) {
     let mut camera_mapper = camera_map.get_mapper();
 
-    let mut start = extracted_uinodes.glyphs.len();
-    let mut end = start + 1;
+    let CaretAnimations { generation, carets } = &mut *caret_animations;
+    *generation = generation.wrapping_add(1);
 
     for (
         entity,
@@ -78,6 +149,9 @@ pub fn extract_text_input_nodes(
         style,
         input,
         input_buffer,
+        gutter,
+        spellcheck,
+        (blink, background_color, highlights, state_colors, hint, has_error),
     ) in &uinode_query
     {
         // Skip if not visible or if size is set to zero (e.g. when a parent is set to `Display::None`)
@@ -89,51 +163,218 @@ pub fn extract_text_input_nodes(
             continue;
         };
 
-        let color = text_color.0.to_linear();
+        let state_colors = state_colors
+            .map(|state_colors| {
+                state_colors.current(
+                    input.is_enabled,
+                    is_focused(entity, &active_text_input),
+                    has_error
+                        || hint.is_some_and(|hint| hint.status() == TextInputHintStatus::Invalid),
+                )
+            })
+            .unwrap_or_default();
+
+        let color = state_colors.text.unwrap_or(text_color.0).to_linear();
         let selection_color = style
             .selected_text_color
             .map(|selection_color| selection_color.to_linear())
             .unwrap_or(color);
 
-        let scroll = input_buffer
-            .editor
-            .with_buffer(|buffer| Vec2::new(buffer.scroll().horizontal, 0.)); // buffer.scroll().vertical));
+        // An ellipsis is only shown for text laid out from its start
+        let scroll = if text_layout_info.ellipsis.is_some() {
+            Vec2::ZERO
+        } else if input.mode.wrap() == Wrap::None {
+            Vec2::new(input_buffer.horizontal_scroll(), 0.)
+        } else {
+            Vec2::ZERO
+        };
+
+        if let Some(background) = state_colors.background {
+            extracted_uinodes.uinodes.push(ExtractedUiNode {
+                // Above the node's own background color, below its border
+                z_order: uinode.stack_index as f32
+                    + 0.5 * (stack_z_offsets::BACKGROUND_COLOR + stack_z_offsets::BORDER),
+                image: AssetId::default(),
+                clip: clip.map(|clip| clip.clip),
+                extracted_camera_entity,
+                transform: global_transform.into(),
+                item: ExtractedUiItem::Node {
+                    color: background.into(),
+                    atlas_scaling: None,
+                    flip_x: false,
+                    flip_y: false,
+                    border_radius: uinode.border_radius(),
+                    border: BorderRect::ZERO,
+                    node_type: NodeType::Rect,
+                    rect: Rect {
+                        min: Vec2::ZERO,
+                        max: uinode.size(),
+                    },
+                },
+                main_entity: entity.into(),
+                render_entity: commands.spawn(TemporaryRenderEntity).id(),
+            });
+        }
+
+        if let Some(focus_ring_color) = style.focus_ring_color
+            && 0. < style.focus_ring_width
+            && is_focused(entity, &active_text_input)
+        {
+            let width = style.focus_ring_width * uinode.inverse_scale_factor().recip();
+            let radius = uinode.border_radius();
+            let outer_radius = |radius: f32| if 0. < radius { radius + width } else { 0. };
+            extracted_uinodes.uinodes.push(ExtractedUiNode {
+                z_order: uinode.stack_index as f32 + stack_z_offsets::BORDER,
+                image: AssetId::default(),
+                clip: clip.map(|clip| clip.clip),
+                extracted_camera_entity,
+                transform: global_transform.into(),
+                item: ExtractedUiItem::Node {
+                    color: focus_ring_color.into(),
+                    atlas_scaling: None,
+                    flip_x: false,
+                    flip_y: false,
+                    border_radius: ResolvedBorderRadius {
+                        top_left: outer_radius(radius.top_left),
+                        top_right: outer_radius(radius.top_right),
+                        bottom_right: outer_radius(radius.bottom_right),
+                        bottom_left: outer_radius(radius.bottom_left),
+                    },
+                    border: BorderRect::all(width),
+                    node_type: NodeType::Border(shader_flags::BORDER_ALL),
+                    rect: Rect {
+                        min: Vec2::ZERO,
+                        max: uinode.size() + 2. * width,
+                    },
+                },
+                main_entity: entity.into(),
+                render_entity: commands.spawn(TemporaryRenderEntity).id(),
+            });
+        }
+
+        let inset = TextInputGutter::inset(gutter, uinode.inverse_scale_factor().recip());
+
+        if let Some(fill_color) = style.fill_color {
+            let size = uinode.size() - inset * Vec2::X;
+            let radius = (style.fill_radius * uinode.inverse_scale_factor().recip())
+                .min(0.5 * size.min_element());
+            extracted_uinodes.uinodes.push(ExtractedUiNode {
+                z_order: uinode.stack_index as f32 + FILL_Z_OFFSET,
+                image: AssetId::default(),
+                clip: clip.map(|clip| clip.clip),
+                extracted_camera_entity,
+                transform: Affine2::from(global_transform)
+                    * Affine2::from_translation(0.5 * inset * Vec2::X),
+                item: ExtractedUiItem::Node {
+                    color: fill_color.into(),
+                    atlas_scaling: None,
+                    flip_x: false,
+                    flip_y: false,
+                    border_radius: ResolvedBorderRadius {
+                        top_left: radius,
+                        top_right: radius,
+                        bottom_right: radius,
+                        bottom_left: radius,
+                    },
+                    border: BorderRect::ZERO,
+                    node_type: NodeType::Rect,
+                    rect: Rect {
+                        min: Vec2::ZERO,
+                        max: size,
+                    },
+                },
+                main_entity: entity.into(),
+                render_entity: commands.spawn(TemporaryRenderEntity).id(),
+            });
+        }
 
         let transform = Affine2::from(global_transform)
-            * Affine2::from_translation(uinode.size() * -0.5 - scroll);
-
-        let node_rect = Rect::from_center_size(
-            global_transform.translation,
-            uinode.size()
-                * Vec2::new(
-                    global_transform.matrix2.col(0).length(),
-                    global_transform.matrix2.col(1).length(),
-                ),
+            * Affine2::from_translation(uinode.size() * -0.5 - scroll + inset * Vec2::X);
+
+        let scale = Vec2::new(
+            global_transform.matrix2.col(0).length(),
+            global_transform.matrix2.col(1).length(),
         );
 
+        let node_rect = Rect::from_center_size(global_transform.translation, uinode.size() * scale);
+        let mut content_rect = inside_rounded_corners(node_rect, uinode.border_radius(), scale);
+        // Don't draw text scrolled under the gutter
+        content_rect.min.x = (node_rect.min.x + inset * scale.x)
+            .max(content_rect.min.x)
+            .min(content_rect.max.x);
+
         let clip = Some(
-            clip.map(|clip| clip.clip.intersect(node_rect))
-                .unwrap_or(node_rect),
+            clip.map(|clip| clip.clip.intersect(content_rect))
+                .unwrap_or(content_rect),
         );
 
-        let line_height = input_buffer
+        let line_height = input_buffer.line_height();
+
+        let selection_min_width = style.selection_min_width * input_buffer.space_advance;
+
+        // Visible horizontal extent of the text area in buffer coordinates
+        let text_width = input_buffer
             .editor
-            .with_buffer(|buffer| buffer.metrics().line_height);
+            .with_buffer(|buffer| buffer.size().0)
+            .unwrap_or(uinode.size().x - inset);
+        let visible_max_x = scroll.x + text_width;
+
+        for (rect, color) in highlights
+            .iter()
+            .flat_map(|highlights| highlights.rects.iter())
+        {
+            extracted_uinodes.uinodes.push(ExtractedUiNode {
+                z_order: uinode.stack_index as f32 + HIGHLIGHT_Z_OFFSET,
+                image: AssetId::default(),
+                clip,
+                extracted_camera_entity,
+                transform: transform * Affine2::from_translation(rect.center()),
+                item: ExtractedUiItem::Node {
+                    color: LinearRgba::from(*color),
+                    atlas_scaling: None,
+                    flip_x: false,
+                    flip_y: false,
+                    border_radius: ResolvedBorderRadius::ZERO,
+                    border: BorderRect::ZERO,
+                    node_type: NodeType::Rect,
+                    rect: Rect {
+                        min: Vec2::ZERO,
+                        max: rect.size(),
+                    },
+                },
+                main_entity: entity.into(),
+                render_entity: commands.spawn(TemporaryRenderEntity).id(),
+            });
+        }
 
+        let selection_z_offset = match style.selection_layer {
+            SelectionLayer::BehindText => SELECTION_Z_OFFSET,
+            SelectionLayer::OverText => SELECTION_OVER_TEXT_Z_OFFSET,
+        };
         for (i, rect) in input_buffer.selection_rects.iter().enumerate() {
-            let size = if (1..input_buffer.selection_rects.len()).contains(&i) {
+            let mut size = if (1..input_buffer.selection_rects.len()).contains(&i) {
                 rect.size() + Vec2::Y
             } else {
                 rect.size()
             } + 2. * Vec2::X;
+            let mut center = rect.center();
+            if size.x < selection_min_width {
+                size.x = selection_min_width;
+                // Widen towards the left at the right edge of the text area, so that selected
+                // empty lines in right-justified inputs stay visible
+                center.x = (rect.min.x + 0.5 * selection_min_width)
+                    .min(visible_max_x - 0.5 * selection_min_width);
+            }
             extracted_uinodes.uinodes.push(ExtractedUiNode {
-                z_order: uinode.stack_index as f32 + stack_z_offsets::TEXT,
+                z_order: uinode.stack_index as f32 + selection_z_offset,
                 image: AssetId::default(),
                 clip,
                 extracted_camera_entity,
-                transform: transform * Affine2::from_translation(rect.center()),
+                transform: transform * Affine2::from_translation(center),
                 item: ExtractedUiItem::Node {
-                    color: LinearRgba::from(style.selection_color),
+                    color: LinearRgba::from(
+                        state_colors.selection.unwrap_or(style.selection_color),
+                    ),
                     atlas_scaling: None,
                     flip_x: false,
                     flip_y: false,
@@ -150,14 +391,40 @@ pub fn extract_text_input_nodes(
             });
         }
 
-        let cursor_visable = active_text_input.0.is_some_and(|active| active == entity)
+        if let Some((spellcheck, spellcheck_layout_info)) = spellcheck {
+            for rect in spellcheck_layout_info.rects.iter() {
+                extracted_uinodes.uinodes.push(ExtractedUiNode {
+                    z_order: uinode.stack_index as f32 + UNDERLINE_Z_OFFSET,
+                    image: AssetId::default(),
+                    clip,
+                    extracted_camera_entity,
+                    transform: transform * Affine2::from_translation(rect.center()),
+                    item: ExtractedUiItem::Node {
+                        color: LinearRgba::from(spellcheck.color),
+                        atlas_scaling: None,
+                        flip_x: false,
+                        flip_y: false,
+                        border_radius: ResolvedBorderRadius::ZERO,
+                        border: BorderRect::ZERO,
+                        node_type: NodeType::Rect,
+                        rect: Rect {
+                            min: Vec2::ZERO,
+                            max: rect.size(),
+                        },
+                    },
+                    main_entity: entity.into(),
+                    render_entity: commands.spawn(TemporaryRenderEntity).id(),
+                });
+            }
+        }
+
+        let cursor_visable = is_focused(entity, &active_text_input)
             && input.is_enabled
-            && input_buffer.cursor_blink_time < style.blink_interval
+            && blink.time < style.blink_interval.unwrap_or(config.blink_interval)
             && !style.cursor_color.is_fully_transparent();
 
         let cursor_position = input_buffer
-            .editor
-            .cursor_position()
+            .drawn_cursor_position()
             .filter(|_| cursor_visable);
 
         let selection = input_buffer.editor.selection_bounds();
@@ -190,45 +457,113 @@ pub fn extract_text_input_nodes(
                 continue;
             };
 
-            extracted_uinodes.glyphs.push(ExtractedGlyph {
-                color: color_out,
-                translation: *position,
-                rect,
-            });
+            let range = push_glyph(
+                &mut extracted_uinodes,
+                ExtractedGlyph {
+                    color: color_out,
+                    translation: *position,
+                    rect,
+                },
+            );
 
             extracted_uinodes.uinodes.push(ExtractedUiNode {
                 z_order: uinode.stack_index as f32 + stack_z_offsets::TEXT,
                 image: atlas_info.texture,
                 clip,
                 extracted_camera_entity,
-                item: ExtractedUiItem::Glyphs { range: start..end },
+                item: ExtractedUiItem::Glyphs { range },
                 main_entity: entity.into(),
                 render_entity: commands.spawn(TemporaryRenderEntity).id(),
                 transform,
             });
+        }
 
-            start = end;
-            end += 1;
+        if let Some(ellipsis) = text_layout_info.ellipsis.as_ref()
+            && let Some(rect) = texture_atlases
+                .get(ellipsis.atlas_info.texture_atlas)
+                .map(|atlas| atlas.textures[ellipsis.atlas_info.location.glyph_index].as_rect())
+        {
+            let range = push_glyph(
+                &mut extracted_uinodes,
+                ExtractedGlyph {
+                    color,
+                    translation: ellipsis.position,
+                    rect,
+                },
+            );
+
+            extracted_uinodes.uinodes.push(ExtractedUiNode {
+                z_order: uinode.stack_index as f32 + stack_z_offsets::TEXT,
+                image: ellipsis.atlas_info.texture,
+                clip,
+                extracted_camera_entity,
+                item: ExtractedUiItem::Glyphs { range },
+                main_entity: entity.into(),
+                render_entity: commands.spawn(TemporaryRenderEntity).id(),
+                transform,
+            });
         }
 
         if let Some((x, y)) = cursor_position {
             let cursor_height = line_height * style.cursor_height;
 
-            let x = x as f32;
-            let y = y as f32;
-
             let scale_factor = uinode.inverse_scale_factor().recip();
             let width = style.cursor_width * scale_factor;
+            let offset = style.cursor_offset * scale_factor;
+
+            // The cursor is drawn to the right of its position, keep it inside the text area so it
+            // isn't clipped at the end of right-justified or horizontally scrolled lines.
+            let x = (x as f32).min(visible_max_x - width).max(scroll.x);
+            let y = y as f32;
+
+            let Vec2 { x, y } = if style.caret_animation {
+                let target = Vec2::new(x, y);
+                let caret = carets.entry(entity).or_insert_with(|| CaretAnimation {
+                    from: target,
+                    to: target,
+                    ..Default::default()
+                });
+                let duration = style.caret_animation_duration.max(f32::EPSILON);
+                caret.elapsed += time.delta_secs();
+                if caret.to != target {
+                    // Moves to another line further than the next or previous jump
+                    caret.from = if 1.5 * line_height < (target.y - caret.to.y).abs() {
+                        target
+                    } else {
+                        caret.position(duration)
+                    };
+                    caret.to = target;
+                    caret.elapsed = 0.;
+                }
+                caret.generation = *generation;
+                caret.position(duration)
+            } else {
+                Vec2::new(x, y)
+            };
+
+            let background = style
+                .fill_color
+                .or(state_colors.background)
+                .or(background_color.map(|background_color| background_color.0))
+                .filter(|_| style.cursor_auto_contrast);
+            let cursor_color = match background {
+                Some(background) => contrasting_color(background).to_linear(),
+                None => state_colors
+                    .cursor
+                    .map_or(color, |cursor| cursor.to_linear()),
+            };
 
             extracted_uinodes.uinodes.push(ExtractedUiNode {
-                z_order: uinode.stack_index as f32 + stack_z_offsets::TEXT,
+                z_order: uinode.stack_index as f32 + CURSOR_Z_OFFSET,
                 image: AssetId::default(),
                 clip,
                 extracted_camera_entity,
                 transform: transform
-                    * Affine2::from_translation(Vec2::new(x + 0.5 * width, y + 0.5 * line_height)),
+                    * Affine2::from_translation(
+                        Vec2::new(x + 0.5 * width, y + 0.5 * line_height) + offset,
+                    ),
                 item: ExtractedUiItem::Node {
-                    color,
+                    color: cursor_color,
                     atlas_scaling: None,
                     flip_x: false,
                     flip_y: false,
@@ -245,12 +580,57 @@ pub fn extract_text_input_nodes(
             });
         }
     }
+
+    carets.retain(|_, caret| caret.generation == *generation);
+}
+
+/// Push a glyph and return its range in `ExtractedUiNodes::glyphs`.
+/// Taken from the glyphs' length instead of a running count, so that skipped nodes and glyphs
+/// can't shift the ranges of the glyphs extracted after them.
+fn push_glyph(extracted_uinodes: &mut ExtractedUiNodes, glyph: ExtractedGlyph) -> Range<usize> {
+    let index = extracted_uinodes.glyphs.len();
+    extracted_uinodes.glyphs.push(glyph);
+    index..index + 1
+}
+
+/// The largest rect inset equally from each edge of `node_rect` that fits inside its rounded
+/// corners, used to clip content so it doesn't draw into the corners.
+/// `radius` is in the node's coordinates and scaled by `scale` to those of `node_rect`.
+fn inside_rounded_corners(node_rect: Rect, radius: ResolvedBorderRadius, scale: Vec2) -> Rect {
+    // A corner of the inset rect touches the arc of a corner with radius `r` at an inset of
+    // `r * (1 - 1/√2)` along both axes
+    let inset = |radius: f32, other: f32| radius.max(other) * (1. - FRAC_1_SQRT_2);
+    let min = scale
+        * Vec2::new(
+            inset(radius.top_left, radius.bottom_left),
+            inset(radius.top_left, radius.top_right),
+        );
+    let max = scale
+        * Vec2::new(
+            inset(radius.top_right, radius.bottom_right),
+            inset(radius.bottom_left, radius.bottom_right),
+        );
+    Rect {
+        min: node_rect.min + min,
+        max: node_rect.max - max,
+    }
+}
+
+/// Black or white, whichever contrasts more with `background`
+pub fn contrasting_color(background: Color) -> Color {
+    // Relative luminance where the contrast ratios against black and white are equal
+    if 0.179 < background.luminance() {
+        Color::BLACK
+    } else {
+        Color::WHITE
+    }
 }
 
 pub fn extract_text_input_prompts(
     mut commands: Commands,
     mut extracted_uinodes: ResMut<ExtractedUiNodes>,
     texture_atlases: Extract<Res<Assets<TextureAtlasLayout>>>,
+    active_text_input: Extract<Res<InputFocus>>,
     uinode_query: Extract<
         Query<(
             Entity,
@@ -263,15 +643,13 @@ pub fn extract_text_input_prompts(
             &TextColor,
             &TextInputBuffer,
             &TextInputPrompt,
+            Option<&TextInputGutter>,
         )>,
     >,
     camera_map: Extract<UiCameraMap>,
 ) {
     let mut camera_mapper = camera_map.get_mapper();
 
-    let mut start = extracted_uinodes.glyphs.len();
-    let mut end = start + 1;
-
     for (
         entity,
         uinode,
@@ -283,6 +661,7 @@ pub fn extract_text_input_prompts(
         text_color,
         input,
         prompt,
+        gutter,
     ) in &uinode_query
     {
         // only display the prompt if the text input is empty, including whitespace
@@ -299,18 +678,27 @@ pub fn extract_text_input_prompts(
             continue;
         };
 
-        let color = prompt.color.unwrap_or(text_color.0).to_linear();
+        let mut color = prompt.color.unwrap_or(text_color.0).to_linear();
+        let mut scale = 1.;
+        if is_focused(entity, &active_text_input) {
+            color.alpha *= prompt.focused_alpha;
+            scale = prompt.focused_scale;
+        }
 
-        let transform =
-            Affine2::from(global_transform) * Affine2::from_translation(-0.5 * uinode.size());
+        let inset = TextInputGutter::inset(gutter, uinode.inverse_scale_factor().recip());
 
-        let node_rect = Rect::from_center_size(
-            global_transform.translation,
-            uinode.size()
-                * Vec2::new(
-                    global_transform.matrix2.col(0).length(),
-                    global_transform.matrix2.col(1).length(),
-                ),
+        let transform = Affine2::from(global_transform)
+            * Affine2::from_translation(-0.5 * uinode.size() + inset * Vec2::X)
+            * Affine2::from_scale(Vec2::splat(scale));
+
+        let scale = Vec2::new(
+            global_transform.matrix2.col(0).length(),
+            global_transform.matrix2.col(1).length(),
+        );
+        let node_rect = inside_rounded_corners(
+            Rect::from_center_size(global_transform.translation, uinode.size() * scale),
+            uinode.border_radius(),
+            scale,
         );
 
         let clip = Some(
@@ -324,29 +712,583 @@ pub fn extract_text_input_prompts(
             ..
         } in text_layout_info.glyphs.iter()
         {
-            let rect = texture_atlases
+            let Some(rect) = texture_atlases
                 .get(atlas_info.texture_atlas)
-                .unwrap()
-                .textures[atlas_info.location.glyph_index]
-                .as_rect();
-            extracted_uinodes.glyphs.push(ExtractedGlyph {
-                color,
-                translation: *position,
-                rect,
+                .map(|atlas| atlas.textures[atlas_info.location.glyph_index].as_rect())
+            else {
+                continue;
+            };
+            let range = push_glyph(
+                &mut extracted_uinodes,
+                ExtractedGlyph {
+                    color,
+                    translation: *position,
+                    rect,
+                },
+            );
+            extracted_uinodes.uinodes.push(ExtractedUiNode {
+                z_order: uinode.stack_index() as f32 + stack_z_offsets::TEXT,
+                transform,
+                image: atlas_info.texture,
+                clip,
+                item: ExtractedUiItem::Glyphs { range },
+                main_entity: entity.into(),
+                render_entity: commands.spawn(TemporaryRenderEntity).id(),
+                extracted_camera_entity,
             });
+        }
+    }
+}
+
+pub fn extract_text_input_gutters(
+    mut commands: Commands,
+    mut extracted_uinodes: ResMut<ExtractedUiNodes>,
+    texture_atlases: Extract<Res<Assets<TextureAtlasLayout>>>,
+    uinode_query: Extract<
+        Query<(
+            Entity,
+            &ComputedNode,
+            &UiGlobalTransform,
+            &InheritedVisibility,
+            Option<&CalculatedClip>,
+            &ComputedUiTargetCamera,
+            &TextInputGutterLayoutInfo,
+            &TextInputGutter,
+        )>,
+    >,
+    camera_map: Extract<UiCameraMap>,
+) {
+    let mut camera_mapper = camera_map.get_mapper();
+
+    for (
+        entity,
+        uinode,
+        global_transform,
+        inherited_visibility,
+        clip,
+        target,
+        gutter_layout_info,
+        gutter,
+    ) in &uinode_query
+    {
+        // Skip if not visible or if size is set to zero (e.g. when a parent is set to `Display::None`)
+        if !inherited_visibility.get() || uinode.is_empty() {
+            continue;
+        }
+
+        let Some(extracted_camera_entity) = camera_mapper.map(target) else {
+            continue;
+        };
+
+        let color = gutter.color.to_linear();
+
+        let transform =
+            Affine2::from(global_transform) * Affine2::from_translation(-0.5 * uinode.size());
+
+        let scale = Vec2::new(
+            global_transform.matrix2.col(0).length(),
+            global_transform.matrix2.col(1).length(),
+        );
+        let node_rect = inside_rounded_corners(
+            Rect::from_center_size(global_transform.translation, uinode.size() * scale),
+            uinode.border_radius(),
+            scale,
+        );
+
+        let clip = Some(
+            clip.map(|clip| clip.clip.intersect(node_rect))
+                .unwrap_or(node_rect),
+        );
+
+        for TextInputGlyph {
+            position,
+            atlas_info,
+            ..
+        } in gutter_layout_info.glyphs.iter()
+        {
+            let Some(rect) = texture_atlases
+                .get(atlas_info.texture_atlas)
+                .map(|atlas| atlas.textures[atlas_info.location.glyph_index].as_rect())
+            else {
+                continue;
+            };
+            let range = push_glyph(
+                &mut extracted_uinodes,
+                ExtractedGlyph {
+                    color,
+                    translation: *position,
+                    rect,
+                },
+            );
             extracted_uinodes.uinodes.push(ExtractedUiNode {
                 z_order: uinode.stack_index() as f32 + stack_z_offsets::TEXT,
                 transform,
                 image: atlas_info.texture,
                 clip,
-                item: ExtractedUiItem::Glyphs { range: start..end },
+                item: ExtractedUiItem::Glyphs { range },
                 main_entity: entity.into(),
                 render_entity: commands.spawn(TemporaryRenderEntity).id(),
                 extracted_camera_entity,
             });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::text_input_pipeline::tests::layout_world;
+    use crate::text_input_pipeline::{text_input_prompt_system, text_input_system};
+    use crate::{TextInputColors, TextInputHighlights};
+    use bevy::ecs::system::RunSystemOnce;
+    use bevy::ecs::world::World;
+    use bevy::reflect::{DynamicStruct, FromReflect};
+    use bevy::render::MainWorld;
+    use bevy::render::sync_world::RenderEntity;
+    use bevy::text::TextFont;
+
+    /// A render world whose `MainWorld` is a `layout_world` with its text input visible to a camera
+    fn render_world(input: TextInputNode, text: &str) -> (World, Entity) {
+        let mut render_world = World::new();
+        let render_camera = render_world.spawn_empty().id();
+        let (mut world, entity) = layout_world(input, text);
+        let camera = world.spawn(RenderEntity::from(render_camera)).id();
+        // `ComputedUiTargetCamera` is set by bevy's UI systems, outside of tests it can't be constructed
+        let mut target = DynamicStruct::default();
+        target.insert("camera", camera);
+        world.entity_mut(entity).insert((
+            ComputedUiTargetCamera::from_reflect(&target).unwrap(),
+            InheritedVisibility::VISIBLE,
+        ));
+        world.init_resource::<Time>();
+        world.init_resource::<TextInputConfig>();
+
+        let mut main_world = MainWorld::default();
+        *main_world = world;
+        render_world.insert_resource(main_world);
+        (render_world, entity)
+    }
+
+    /// Lays out the main world's text inputs and extracts them
+    fn extract(render_world: &mut World) -> &ExtractedUiNodes {
+        render_world
+            .resource_mut::<MainWorld>()
+            .run_system_once(text_input_system)
+            .unwrap();
+        render_world.insert_resource(ExtractedUiNodes::default());
+        render_world
+            .run_system_once(extract_text_input_nodes)
+            .unwrap();
+        render_world.resource::<ExtractedUiNodes>()
+    }
+
+    /// The translation and color of the extracted caret, if one was drawn
+    fn caret(nodes: &ExtractedUiNodes) -> Option<(Vec2, LinearRgba)> {
+        nodes.uinodes.iter().find_map(|node| match node.item {
+            ExtractedUiItem::Node { color, .. } if node.z_order == CURSOR_Z_OFFSET => {
+                Some((node.transform.translation, color))
+            }
+            _ => None,
+        })
+    }
+
+    #[test]
+    fn focus_ring_drawn_while_focused() {
+        let (mut render_world, entity) = render_world(TextInputNode::default(), "abc");
+        let color = Color::srgb(1., 0., 0.);
+        render_world
+            .resource_mut::<MainWorld>()
+            .entity_mut(entity)
+            .insert(TextInputStyle {
+                focus_ring_color: Some(color),
+                ..Default::default()
+            });
+        let has_ring = |nodes: &ExtractedUiNodes| {
+            nodes.uinodes.iter().any(|node| {
+                matches!(node.item, ExtractedUiItem::Node {
+                    color: ring_color,
+                    node_type: NodeType::Border(_),
+                    ..
+                } if ring_color == color.into())
+            })
+        };
+
+        for focused in [true, false] {
+            render_world
+                .resource_mut::<MainWorld>()
+                .resource_mut::<InputFocus>()
+                .0 = focused.then_some(entity);
+            assert_eq!(has_ring(extract(&mut render_world)), focused);
+        }
+    }
+
+    #[test]
+    fn state_colors_follow_focus_and_error() {
+        let (mut render_world, entity) = render_world(TextInputNode::default(), "abc");
+        let text_colors = |text: Color| TextInputColors {
+            text: Some(text),
+            ..Default::default()
+        };
+        let [normal, focused, error] = [
+            Color::srgb(1., 0., 0.),
+            Color::srgb(0., 1., 0.),
+            Color::srgb(0., 0., 1.),
+        ];
+        render_world
+            .resource_mut::<MainWorld>()
+            .entity_mut(entity)
+            .insert(TextInputStateColors {
+                normal: text_colors(normal),
+                focused: text_colors(focused),
+                error: text_colors(error),
+                ..Default::default()
+            });
+        let glyph_colors = |nodes: &ExtractedUiNodes| {
+            assert_eq!(nodes.glyphs.len(), 3);
+            nodes
+                .glyphs
+                .iter()
+                .map(|glyph| glyph.color)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            glyph_colors(extract(&mut render_world)),
+            [normal.to_linear(); 3]
+        );
+
+        let mut main_world = render_world.resource_mut::<MainWorld>();
+        main_world.resource_mut::<InputFocus>().0 = Some(entity);
+        assert_eq!(
+            glyph_colors(extract(&mut render_world)),
+            [focused.to_linear(); 3]
+        );
+
+        let mut main_world = render_world.resource_mut::<MainWorld>();
+        main_world.entity_mut(entity).insert(TextInputError);
+        assert_eq!(
+            glyph_colors(extract(&mut render_world)),
+            [error.to_linear(); 3]
+        );
+    }
+
+    #[test]
+    fn focused_empty_input_fades_prompt() {
+        let (mut render_world, entity) = render_world(TextInputNode::default(), "");
+        render_world
+            .resource_mut::<MainWorld>()
+            .entity_mut(entity)
+            .insert(TextInputPrompt {
+                text: "ab".into(),
+                color: Some(Color::WHITE),
+                focused_alpha: 0.25,
+                ..Default::default()
+            });
+        let mut prompt_alphas = |focused: bool| {
+            let mut main_world = render_world.resource_mut::<MainWorld>();
+            main_world.resource_mut::<InputFocus>().0 = focused.then_some(entity);
+            main_world
+                .run_system_once(text_input_prompt_system)
+                .unwrap();
+            render_world.insert_resource(ExtractedUiNodes::default());
+            render_world
+                .run_system_once(extract_text_input_prompts)
+                .unwrap();
+            render_world
+                .resource::<ExtractedUiNodes>()
+                .glyphs
+                .iter()
+                .map(|glyph| glyph.color.alpha)
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(prompt_alphas(false), [1.; 2]);
+        assert_eq!(prompt_alphas(true), [0.25; 2]);
+    }
+
+    #[test]
+    fn fill_drawn_rounded_behind_text() {
+        let (mut render_world, entity) = render_world(TextInputNode::default(), "abc");
+        let fill = Color::srgb(1., 0., 0.);
+        render_world
+            .resource_mut::<MainWorld>()
+            .entity_mut(entity)
+            .insert((
+                TextInputStyle {
+                    fill_color: Some(fill),
+                    fill_radius: 4.,
+                    ..Default::default()
+                },
+                BackgroundColor(Color::srgb(0., 0., 1.)),
+            ));
+        let nodes = extract(&mut render_world);
+
+        let fills: Vec<_> = nodes
+            .uinodes
+            .iter()
+            .filter_map(|node| match node.item {
+                ExtractedUiItem::Node {
+                    color,
+                    node_type: NodeType::Rect,
+                    border_radius,
+                    rect,
+                    ..
+                } if color == fill.into() => Some((node.z_order, border_radius.top_left, rect)),
+                _ => None,
+            })
+            .collect();
+        let [(fill_z, radius, rect)] = fills[..] else {
+            panic!("expected one fill, found {fills:?}");
+        };
+        assert_eq!(radius, 4.);
+        assert_eq!(rect.size(), Vec2::new(100., 30.));
+        let text_z = nodes
+            .uinodes
+            .iter()
+            .filter(|node| matches!(node.item, ExtractedUiItem::Glyphs { .. }))
+            .map(|node| node.z_order)
+            .reduce(f32::min)
+            .unwrap();
+        assert!(fill_z < text_z);
+    }
+
+    #[test]
+    fn highlights_drawn_under_their_glyphs() {
+        let (mut render_world, entity) = render_world(TextInputNode::default(), "abcdef");
+        let [red, green] = [Color::srgb(1., 0., 0.), Color::srgb(0., 1., 0.)];
+        render_world
+            .resource_mut::<MainWorld>()
+            .entity_mut(entity)
+            .insert(TextInputHighlights {
+                ranges: vec![(0..1, red), (3..5, green)],
+            });
+        let nodes = extract(&mut render_world);
+        let text_z = nodes
+            .uinodes
+            .iter()
+            .find(|node| matches!(node.item, ExtractedUiItem::Glyphs { .. }))
+            .unwrap()
+            .z_order;
+
+        let highlights: Vec<_> = nodes
+            .uinodes
+            .iter()
+            .filter_map(|node| match node.item {
+                ExtractedUiItem::Node {
+                    color,
+                    node_type: NodeType::Rect,
+                    rect,
+                    ..
+                } => {
+                    assert!(node.z_order < text_z);
+                    // The rect is centered on its translation, which is relative to the node's center
+                    let left = node.transform.translation.x - 0.5 * rect.width() + 50.;
+                    Some((color, left, rect.width()))
+                }
+                _ => None,
+            })
+            .collect();
+        // FiraMono's glyphs are 9.6 wide at 16px
+        let expected = [(red, 0..1), (green, 3..5)].map(|(color, range)| {
+            (
+                LinearRgba::from(color),
+                9.6 * range.start as f32,
+                9.6 * range.len() as f32,
+            )
+        });
+        assert_eq!(highlights.len(), expected.len());
+        for ((color, left, width), (expected_color, expected_left, expected_width)) in
+            highlights.into_iter().zip(expected)
+        {
+            assert_eq!(color, expected_color);
+            assert!((left - expected_left).abs() < 0.01, "{left}");
+            assert!((width - expected_width).abs() < 0.01, "{width}");
+        }
+    }
+
+    #[test]
+    fn cursor_offset_shifts_caret() {
+        let (mut render_world, entity) = render_world(TextInputNode::default(), "abc");
+        let mut main_world = render_world.resource_mut::<MainWorld>();
+        main_world.resource_mut::<InputFocus>().0 = Some(entity);
+        let (translation, _) = caret(extract(&mut render_world)).unwrap();
+
+        let offset = Vec2::new(2., -3.);
+        render_world
+            .resource_mut::<MainWorld>()
+            .entity_mut(entity)
+            .insert(TextInputStyle {
+                cursor_offset: offset,
+                ..Default::default()
+            });
+        let (offset_translation, _) = caret(extract(&mut render_world)).unwrap();
+        assert_eq!(offset_translation - translation, offset);
+    }
+
+    #[test]
+    fn auto_contrast_caret_follows_background() {
+        let (mut render_world, entity) = render_world(TextInputNode::default(), "abc");
+        let mut main_world = render_world.resource_mut::<MainWorld>();
+        main_world.resource_mut::<InputFocus>().0 = Some(entity);
+        main_world.entity_mut(entity).insert(TextInputStyle {
+            cursor_auto_contrast: true,
+            ..Default::default()
+        });
 
-            start = end;
-            end += 1;
+        for (background, expected) in [
+            (Color::srgb(0.9, 0.9, 0.8), Color::BLACK),
+            (Color::srgb(0.1, 0.1, 0.2), Color::WHITE),
+        ] {
+            render_world
+                .resource_mut::<MainWorld>()
+                .entity_mut(entity)
+                .insert(BackgroundColor(background));
+            let (_, color) = caret(extract(&mut render_world)).unwrap();
+            assert_eq!(color, expected.to_linear());
+        }
+    }
+
+    #[test]
+    fn selection_layer_stacks_selection_against_glyphs() {
+        let (mut render_world, entity) = render_world(TextInputNode::default(), "abc");
+        let highlighter = Color::srgba(1., 1., 0., 0.5);
+        let mut main_world = render_world.resource_mut::<MainWorld>();
+        main_world.resource_mut::<InputFocus>().0 = Some(entity);
+        let mut buffer = main_world.get_mut::<TextInputBuffer>(entity).unwrap();
+        buffer
+            .editor
+            .set_selection(cosmic_text::Selection::Normal(cosmic_text::Cursor::new(
+                0, 0,
+            )));
+
+        for (selection_layer, over_text) in [
+            (SelectionLayer::BehindText, false),
+            (SelectionLayer::OverText, true),
+        ] {
+            render_world
+                .resource_mut::<MainWorld>()
+                .entity_mut(entity)
+                .insert(TextInputStyle {
+                    selection_color: highlighter,
+                    selection_layer,
+                    ..Default::default()
+                });
+            let nodes = extract(&mut render_world);
+            let z_order = |is_item: &dyn Fn(&ExtractedUiItem) -> bool| {
+                nodes
+                    .uinodes
+                    .iter()
+                    .find(|node| is_item(&node.item))
+                    .unwrap()
+                    .z_order
+            };
+            let selection_z = z_order(
+                &|item| matches!(item, ExtractedUiItem::Node { color, .. } if *color == highlighter.into()),
+            );
+            let text_z = z_order(&|item| matches!(item, ExtractedUiItem::Glyphs { .. }));
+            assert_eq!(text_z < selection_z, over_text, "{selection_layer:?}");
+        }
+    }
+
+    #[test]
+    fn input_with_despawned_camera_skipped_cleanly() {
+        let (mut render_world, entity) = render_world(TextInputNode::default(), "abc");
+        let mut main_world = render_world.resource_mut::<MainWorld>();
+        let text_font = main_world.get::<TextFont>(entity).unwrap().clone();
+        let computed_node = *main_world.get::<ComputedNode>(entity).unwrap();
+        let camera = main_world.spawn_empty().id();
+        let mut target = DynamicStruct::default();
+        target.insert("camera", camera);
+        let orphan = main_world
+            .spawn((
+                TextInputNode::default(),
+                text_font,
+                computed_node,
+                ComputedUiTargetCamera::from_reflect(&target).unwrap(),
+                InheritedVisibility::VISIBLE,
+            ))
+            .id();
+        main_world
+            .get_mut::<TextInputBuffer>(orphan)
+            .unwrap()
+            .set_text("defgh");
+        main_world.despawn(camera);
+
+        let nodes = extract(&mut render_world);
+        assert_eq!(nodes.glyphs.len(), 3);
+        let mut ranges: Vec<_> = nodes
+            .uinodes
+            .iter()
+            .filter_map(|node| match &node.item {
+                ExtractedUiItem::Glyphs { range } => {
+                    assert_eq!(node.main_entity, entity.into());
+                    Some(range.clone())
+                }
+                _ => None,
+            })
+            .collect();
+        ranges.sort_by_key(|range| range.start);
+        assert_eq!(ranges, [0..1, 1..2, 2..3]);
+    }
+
+    #[test]
+    fn rounded_corners_clip_full_width_selection() {
+        let (mut render_world, entity) =
+            render_world(TextInputNode::default(), "abcdefghijklmnopqrstuvwxyz");
+        let highlighter = Color::srgba(1., 1., 0., 0.5);
+        let mut main_world = render_world.resource_mut::<MainWorld>();
+        main_world.resource_mut::<InputFocus>().0 = Some(entity);
+        main_world
+            .get_mut::<ComputedNode>(entity)
+            .unwrap()
+            .border_radius = ResolvedBorderRadius {
+            top_left: 10.,
+            top_right: 10.,
+            bottom_right: 10.,
+            bottom_left: 10.,
+        };
+        main_world.entity_mut(entity).insert(TextInputStyle {
+            selection_color: highlighter,
+            ..Default::default()
+        });
+        main_world
+            .get_mut::<TextInputBuffer>(entity)
+            .unwrap()
+            .editor
+            .set_cursor(cosmic_text::Cursor::new(0, 26));
+        render_world
+            .resource_mut::<MainWorld>()
+            .get_mut::<TextInputBuffer>(entity)
+            .unwrap()
+            .editor
+            .set_selection(cosmic_text::Selection::Normal(cosmic_text::Cursor::new(
+                0, 0,
+            )));
+
+        let nodes = extract(&mut render_world);
+        let selection = nodes
+            .uinodes
+            .iter()
+            .find(|node| {
+                matches!(node.item, ExtractedUiItem::Node { color, .. } if color == highlighter.into())
+            })
+            .unwrap();
+        let ExtractedUiItem::Node { rect, .. } = selection.item else {
+            unreachable!();
+        };
+        // The selection spans the whole visible width, only the clip keeps it out of the corners
+        let clip = selection.clip.unwrap();
+        assert!(clip.width() < rect.width());
+        let inset = 10. * (1. - FRAC_1_SQRT_2);
+        let node_rect = Rect::from_center_size(Vec2::ZERO, Vec2::new(100., 30.));
+        assert!((clip.min - (node_rect.min + inset)).abs().max_element() < 1e-4);
+        assert!((clip.max - (node_rect.max - inset)).abs().max_element() < 1e-4);
+        for corner in [
+            node_rect.min,
+            node_rect.max,
+            Vec2::new(node_rect.min.x, node_rect.max.y),
+            Vec2::new(node_rect.max.x, node_rect.min.y),
+        ] {
+            assert!(!clip.contains(corner + (Vec2::ZERO - corner).signum()));
         }
     }
 }
@@ -1,26 +1,35 @@
 use crate::{
-    TextInputBuffer, TextInputGlyph, TextInputLayoutInfo, TextInputNode, TextInputPrompt,
-    TextInputPromptLayoutInfo,
+    Overflow, TextInputBuffer, TextInputGlyph, TextInputGutter, TextInputGutterLayoutInfo,
+    TextInputHighlights, TextInputHighlightsLayoutInfo, TextInputLayoutInfo, TextInputMode,
+    TextInputNode, TextInputPrompt, TextInputPromptLayoutInfo, TextInputSpellcheck,
+    TextInputSpellcheckLayoutInfo,
+    edit::{TextMask, cursor_at_char_offset},
+    is_focused,
 };
 use bevy::{
     asset::{AssetEvent, AssetId, Assets},
+    color::Color,
     ecs::{
-        change_detection::DetectChanges,
+        change_detection::{DetectChanges, DetectChangesMut},
+        entity::Entity,
         message::MessageReader,
         resource::Resource,
         system::{Query, Res, ResMut},
         world::Ref,
     },
     image::{Image, TextureAtlasLayout},
+    input_focus::InputFocus,
     math::{Rect, UVec2, Vec2},
     platform::collections::HashMap,
     text::{
-        Font, FontAtlasKey, FontAtlasSet, FontSmoothing, LineBreak, LineHeight, TextBounds,
-        TextError, TextFont, add_glyph_to_atlas, get_glyph_atlas_info,
+        Font, FontAtlasKey, FontAtlasSet, FontSmoothing, Justify, LineBreak, LineHeight,
+        TextBounds, TextError, TextFont, add_glyph_to_atlas, get_glyph_atlas_info,
     },
     ui::ComputedNode,
 };
-use cosmic_text::{self, Buffer, Edit, Metrics};
+use cosmic_text::{self, Buffer, BufferLine, Cursor, Edit, Metrics};
+use std::collections::VecDeque;
+use std::hash::{DefaultHasher, Hash, Hasher};
 use std::sync::Arc;
 
 #[derive(Resource)]
@@ -28,6 +37,7 @@ pub struct TextInputPipeline {
     pub(crate) handle_to_font_id_map: HashMap<AssetId<Font>, (cosmic_text::fontdb::ID, Arc<str>)>,
     pub font_system: cosmic_text::FontSystem,
     pub(crate) swash_cache: cosmic_text::SwashCache,
+    pub shaping_cache: ShapingCache,
 }
 
 impl Default for TextInputPipeline {
@@ -38,10 +48,144 @@ impl Default for TextInputPipeline {
             handle_to_font_id_map: Default::default(),
             font_system: cosmic_text::FontSystem::new_with_locale_and_db(locale, db),
             swash_cache: cosmic_text::SwashCache::new(),
+            shaping_cache: ShapingCache::new(64),
         }
     }
 }
 
+/// Hash of a buffer's text and the settings it's laid out with
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct ShapingKey {
+    hash: u64,
+    font: AssetId<Font>,
+}
+
+impl ShapingKey {
+    fn new(
+        buffer: &Buffer,
+        font: AssetId<Font>,
+        metrics: Metrics,
+        wrap: cosmic_text::Wrap,
+        width: Option<f32>,
+        align: Option<cosmic_text::Align>,
+    ) -> Self {
+        let mut hasher = DefaultHasher::new();
+        for line in &buffer.lines {
+            line.text().hash(&mut hasher);
+            line.ending().as_str().hash(&mut hasher);
+        }
+        font.hash(&mut hasher);
+        metrics.font_size.to_bits().hash(&mut hasher);
+        metrics.line_height.to_bits().hash(&mut hasher);
+        core::mem::discriminant(&wrap).hash(&mut hasher);
+        width.map(f32::to_bits).hash(&mut hasher);
+        align
+            .map(|align| core::mem::discriminant(&align))
+            .hash(&mut hasher);
+        Self {
+            hash: hasher.finish(),
+            font,
+        }
+    }
+}
+
+/// Bounded least recently used cache of shaped and laid out text, shared by all text inputs.
+///
+/// A text input whose text, font, metrics, wrapping, width and justification match an entry reuses its
+/// lines instead of shaping the text again, for example when a node is resized back and forth or when
+/// many identical inputs are spawned. Entries are looked up by a hash of the text and these settings,
+/// and only reused if their text matches too.
+/// Layouts of text edited since its previous layout are neither looked up nor cached.
+#[derive(Debug)]
+pub struct ShapingCache {
+    capacity: usize,
+    entries: VecDeque<(ShapingKey, Vec<BufferLine>)>,
+    hits: usize,
+    misses: usize,
+}
+
+impl ShapingCache {
+    /// New cache holding at most `capacity` entries, zero disables caching
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            entries: VecDeque::new(),
+            hits: 0,
+            misses: 0,
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Set the maximum number of entries, evicting the least recently used entries over the limit
+    pub fn set_capacity(&mut self, capacity: usize) {
+        self.capacity = capacity;
+        while capacity < self.entries.len() {
+            self.entries.pop_front();
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Number of layouts that reused cached lines
+    pub fn hits(&self) -> usize {
+        self.hits
+    }
+
+    /// Number of layouts, other than after edits, that had to shape or lay out their text
+    pub fn misses(&self) -> usize {
+        self.misses
+    }
+
+    /// Remove all entries, the hit and miss counts are kept
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+
+    /// Cached lines for `key`, if their text and line endings also match `lines`. Different texts
+    /// can share a hash, and the cached lines replace the buffer's lines and their text.
+    fn get(&mut self, key: &ShapingKey, lines: &[BufferLine]) -> Option<&Vec<BufferLine>> {
+        let Some(index) = self.entries.iter().position(|(entry, cached)| {
+            entry == key
+                && cached.len() == lines.len()
+                && cached.iter().zip(lines).all(|(cached, line)| {
+                    cached.text() == line.text() && cached.ending() == line.ending()
+                })
+        }) else {
+            self.misses += 1;
+            return None;
+        };
+        self.hits += 1;
+        let entry = self.entries.remove(index)?;
+        self.entries.push_back(entry);
+        self.entries.back().map(|(_, lines)| lines)
+    }
+
+    /// Remove the entries shaped with `font`
+    pub(crate) fn remove_font(&mut self, font: AssetId<Font>) {
+        self.entries.retain(|(key, _)| key.font != font);
+    }
+
+    fn insert(&mut self, key: ShapingKey, lines: Vec<BufferLine>) {
+        if self.capacity == 0 {
+            return;
+        }
+        self.entries.retain(|(entry, _)| *entry != key);
+        while self.capacity <= self.entries.len() {
+            self.entries.pop_front();
+        }
+        self.entries.push_back((key, lines));
+    }
+}
+
 #[derive(Clone)]
 struct FontFaceInfo {
     stretch: cosmic_text::fontdb::Stretch,
@@ -98,29 +242,180 @@ fn buffer_dimensions(buffer: &cosmic_text::Buffer) -> Vec2 {
     Vec2::new(width, height).ceil()
 }
 
+/// Measures the advance width of a single character.
+fn char_advance(
+    font_system: &mut cosmic_text::FontSystem,
+    metrics: Metrics,
+    attrs: &cosmic_text::Attrs,
+    ch: char,
+) -> f32 {
+    let mut buffer = Buffer::new(font_system, metrics);
+    buffer.set_text(
+        font_system,
+        ch.encode_utf8(&mut [0; 4]),
+        attrs,
+        cosmic_text::Shaping::Advanced,
+        None,
+    );
+    buffer
+        .layout_runs()
+        .next()
+        .map(|run| run.line_w)
+        .unwrap_or(0.)
+}
+
+/// Adds the glyph to the font atlas if needed and computes its position relative to the top left
+/// corner of the buffer.
+fn layout_glyph_to_text_input_glyph(
+    layout_glyph: &cosmic_text::LayoutGlyph,
+    line_y: f32,
+    line_index: usize,
+    text_font: &TextFont,
+    text_input_pipeline: &mut TextInputPipeline,
+    font_atlas_set: &mut FontAtlasSet,
+    texture_atlases: &mut Assets<TextureAtlasLayout>,
+    textures: &mut Assets<Image>,
+) -> Result<TextInputGlyph, TextError> {
+    let mut temp_glyph;
+    let span_index = layout_glyph.metadata;
+    let font_id = text_font.font.id();
+    let font_smoothing = text_font.font_smoothing;
+
+    let layout_glyph = if font_smoothing == FontSmoothing::None {
+        // If font smoothing is disabled, round the glyph positions and sizes,
+        // effectively discarding all subpixel layout.
+        temp_glyph = layout_glyph.clone();
+        temp_glyph.x = temp_glyph.x.round();
+        temp_glyph.y = temp_glyph.y.round();
+        temp_glyph.w = temp_glyph.w.round();
+        temp_glyph.x_offset = temp_glyph.x_offset.round();
+        temp_glyph.y_offset = temp_glyph.y_offset.round();
+        temp_glyph.line_height_opt = temp_glyph.line_height_opt.map(f32::round);
+
+        &temp_glyph
+    } else {
+        layout_glyph
+    };
+
+    let TextInputPipeline {
+        font_system,
+        swash_cache,
+        ..
+    } = text_input_pipeline;
+
+    let physical_glyph = layout_glyph.physical((0., 0.), 1.);
+
+    let font_atlases = font_atlas_set
+        .entry(FontAtlasKey(
+            font_id,
+            physical_glyph.cache_key.font_size_bits,
+            font_smoothing,
+        ))
+        .or_default();
+
+    let atlas_info = get_glyph_atlas_info(font_atlases, physical_glyph.cache_key)
+        .map(Ok)
+        .unwrap_or_else(|| {
+            add_glyph_to_atlas(
+                font_atlases,
+                texture_atlases,
+                textures,
+                font_system,
+                swash_cache,
+                layout_glyph,
+                font_smoothing,
+            )
+        })?;
+
+    let texture_atlas = texture_atlases.get(atlas_info.texture_atlas).unwrap();
+    let location = atlas_info.location;
+    let glyph_rect = texture_atlas.textures[location.glyph_index];
+    let left = location.offset.x as f32;
+    let top = location.offset.y as f32;
+    let glyph_size = UVec2::new(glyph_rect.width(), glyph_rect.height());
+
+    // offset by half the size because the origin is center
+    let x = glyph_size.x as f32 / 2.0 + left + physical_glyph.x as f32;
+    let y = line_y.round() + physical_glyph.y as f32 - top + glyph_size.y as f32 / 2.0;
+
+    let position = Vec2::new(x, y);
+
+    Ok(TextInputGlyph {
+        position,
+        size: glyph_size.as_vec2(),
+        atlas_info,
+        span_index,
+        byte_index: layout_glyph.start,
+        byte_length: layout_glyph.end - layout_glyph.start,
+        line_index,
+    })
+}
+
 pub fn text_input_system(
     mut textures: ResMut<Assets<Image>>,
     fonts: Res<Assets<Font>>,
     mut texture_atlases: ResMut<Assets<TextureAtlasLayout>>,
     mut text_input_pipeline: ResMut<TextInputPipeline>,
     mut font_atlas_set: ResMut<FontAtlasSet>,
+    input_focus: Res<InputFocus>,
     mut text_query: Query<(
+        Entity,
         Ref<ComputedNode>,
         Ref<TextFont>,
         Ref<LineHeight>,
         &mut TextInputLayoutInfo,
         &mut TextInputBuffer,
         Ref<TextInputNode>,
+        Option<Ref<TextInputGutter>>,
+        Option<(Ref<TextInputSpellcheck>, &mut TextInputSpellcheckLayoutInfo)>,
+        Option<(Ref<TextInputHighlights>, &mut TextInputHighlightsLayoutInfo)>,
     )>,
 ) {
-    for (node, text_font, line_height, text_input_layout_info, mut editor, input) in
-        text_query.iter_mut()
+    for (
+        entity,
+        node,
+        text_font,
+        line_height,
+        text_input_layout_info,
+        mut editor,
+        input,
+        gutter,
+        mut spellcheck,
+        mut highlights,
+    ) in text_query.iter_mut()
     {
         let layout_info = text_input_layout_info.into_inner();
-        if editor.needs_update || text_font.is_changed() || node.is_changed() || input.is_changed()
+        // Text edited since the last layout is unlikely to be laid out again, so it isn't cached
+        let edited = editor.is_changed() && !editor.is_added();
+        // Layout only updates state derived from the text, so it doesn't mark the buffer as changed
+        let editor = editor.bypass_change_detection();
+        let show_ellipsis = input.overflow_indicator == Overflow::Ellipsis
+            && input.mode == TextInputMode::SingleLine
+            && !is_focused(entity, &input_focus);
+        if input_focus.is_changed() && input.overflow_indicator == Overflow::Ellipsis
+            || spellcheck
+                .as_ref()
+                .is_some_and(|(spellcheck, _)| spellcheck.is_changed())
+            || highlights
+                .as_ref()
+                .is_some_and(|(highlights, _)| highlights.is_changed())
+        {
+            editor.editor.set_redraw(true);
+        }
+        let mut shaped_key = None;
+        if editor.needs_update
+            || text_font.is_changed()
+            || line_height.is_changed()
+            || node.is_changed()
+            || input.is_changed()
+            || gutter.as_ref().is_some_and(DetectChanges::is_changed)
         {
+            // Resizing only needs a new layout, the text's existing shaping can be kept
+            let reshape = editor.needs_update || text_font.is_changed() || input.is_changed();
+            let inset =
+                TextInputGutter::inset(gutter.as_deref(), node.inverse_scale_factor().recip());
             let bounds = TextBounds {
-                width: Some(node.size().x),
+                width: Some((node.size().x - inset).max(0.)),
                 height: Some(node.size().y),
             };
 
@@ -133,6 +428,7 @@ pub fn text_input_system(
                 let TextInputPipeline {
                     font_system,
                     handle_to_font_id_map: map_handle_to_font_id,
+                    shaping_cache,
                     ..
                 } = &mut *text_input_pipeline;
                 if !fonts.contains(text_font.font.id()) {
@@ -148,12 +444,6 @@ pub fn text_input_system(
                 metrics.font_size = metrics.font_size.max(0.000001);
                 metrics.line_height = metrics.line_height.max(0.000001);
 
-                buffer.set_metrics_and_size(font_system, metrics, bounds.width, bounds.height);
-
-                buffer.set_wrap(font_system, input.mode.wrap());
-
-                let text = crate::get_text(buffer);
-
                 let attrs = cosmic_text::Attrs::new()
                     .metadata(0)
                     .family(cosmic_text::Family::Name(&face_info.family_name))
@@ -162,20 +452,59 @@ pub fn text_input_system(
                     .weight(face_info.weight)
                     .metrics(metrics);
 
+                let width = match input.max_width_chars {
+                    Some(max_width_chars) => {
+                        let advance = char_advance(font_system, metrics, &attrs, '0');
+                        bounds
+                            .width
+                            .map(|width| width.min(max_width_chars as f32 * advance))
+                    }
+                    None => bounds.width,
+                };
+
+                let reshape = reshape || buffer.metrics() != metrics;
+                if reshape || editor.space_advance == 0. {
+                    editor.space_advance = char_advance(font_system, metrics, &attrs, ' ');
+                }
+
+                let wrap = input.mode.wrap();
                 let align = Some(input.justification.into());
+                let key = (!edited).then(|| {
+                    ShapingKey::new(buffer, text_font.font.id(), metrics, wrap, width, align)
+                });
+
+                if let Some(lines) = key.and_then(|key| shaping_cache.get(&key, &buffer.lines)) {
+                    // Resize the buffer while it's empty, so its current lines aren't laid out
+                    // again only to be replaced
+                    let scroll = buffer.scroll();
+                    buffer.lines.clear();
+                    buffer.set_metrics_and_size(font_system, metrics, width, bounds.height);
+                    buffer.set_wrap(font_system, wrap);
+                    buffer.lines.clone_from(lines);
+                    buffer.set_scroll(scroll);
+                    buffer.set_redraw(true);
+                    return Ok(None);
+                }
 
-                buffer.set_text(
-                    font_system,
-                    &text,
-                    &attrs,
-                    cosmic_text::Shaping::Advanced,
-                    align,
-                );
+                buffer.set_metrics_and_size(font_system, metrics, width, bounds.height);
 
-                Ok(())
+                buffer.set_wrap(font_system, wrap);
+
+                if reshape {
+                    buffer.set_text(
+                        font_system,
+                        &crate::get_text_with_line_endings(buffer),
+                        &attrs,
+                        cosmic_text::Shaping::Advanced,
+                        align,
+                    );
+                }
+
+                Ok(key)
             });
 
-            if result.is_ok() {
+            if let Ok(key) = result {
+                shaped_key = key;
                 editor.needs_update = false;
                 editor.editor.set_redraw(true);
             } else {
@@ -188,10 +517,31 @@ pub fn text_input_system(
             .editor
             .shape_as_needed(&mut text_input_pipeline.font_system, false);
 
+        if let Some(key) = shaped_key
+            && 0 < text_input_pipeline.shaping_cache.capacity()
+        {
+            let lines = editor.editor.with_buffer(|buffer| buffer.lines.clone());
+            text_input_pipeline.shaping_cache.insert(key, lines);
+        }
+
+        if input.mode.wrap() != cosmic_text::Wrap::None {
+            // Wrapped text never scrolls horizontally, even if an unbreakable word or trailing
+            // whitespace overflows
+            editor.editor.with_buffer_mut(|buffer| {
+                let mut scroll = buffer.scroll();
+                if scroll.horizontal != 0. {
+                    scroll.horizontal = 0.;
+                    buffer.set_scroll(scroll);
+                }
+            });
+        }
+
         let selection = editor.editor.selection_bounds();
+        let justification = input.justification;
         let TextInputBuffer {
             editor,
             selection_rects,
+            mask,
             ..
         } = &mut *editor;
 
@@ -199,8 +549,67 @@ pub fn text_input_system(
             layout_info.glyphs.clear();
             selection_rects.clear();
 
-            let result = editor.with_buffer_mut(|buffer| {
+            // Masked inputs draw their mask, laid out from the text's layout
+            *mask = input.is_masked().then(|| {
+                editor.with_buffer(|buffer| {
+                    TextMask::new(
+                        &mut text_input_pipeline.font_system,
+                        buffer,
+                        editor.cursor(),
+                        input.mask_char,
+                        Some(justification.into()),
+                        mask.as_ref(),
+                    )
+                })
+            });
+            let mask = mask.as_ref();
+            let to_drawn = |cursor: Cursor| mask.map_or(cursor, |mask| mask.mask_cursor(cursor));
+            let to_text_index = |line: usize, index: usize| {
+                mask.map_or(index, |mask| {
+                    mask.text_cursor(Cursor::new(line, index)).index
+                })
+            };
+            let selection = selection.map(|(s0, s1)| (to_drawn(s0), to_drawn(s1)));
+            let text_cursor_at_char_offset = |offset: usize| {
+                to_drawn(editor.with_buffer(|text| cursor_at_char_offset(text, offset)))
+            };
+
+            let mut layout = |buffer: &Buffer| {
                 let box_size = buffer_dimensions(buffer);
+                let width = buffer.size().0.unwrap_or(box_size.x);
+                let underline_thickness = (buffer.metrics().font_size / 14.).max(1.);
+
+                let mut underlines = spellcheck.as_mut().map(|(spellcheck, layout_info)| {
+                    layout_info.rects.clear();
+                    let ranges: Vec<(Cursor, Cursor)> = spellcheck
+                        .misspelled_ranges
+                        .iter()
+                        .map(|range| {
+                            (
+                                text_cursor_at_char_offset(range.start),
+                                text_cursor_at_char_offset(range.end),
+                            )
+                        })
+                        .collect();
+                    (ranges, &mut layout_info.rects)
+                });
+
+                let mut highlights = highlights.as_mut().map(|(highlights, layout_info)| {
+                    layout_info.rects.clear();
+                    let ranges: Vec<(Cursor, Cursor, Color)> = highlights
+                        .ranges
+                        .iter()
+                        .map(|(range, color)| {
+                            (
+                                text_cursor_at_char_offset(range.start),
+                                text_cursor_at_char_offset(range.end),
+                                *color,
+                            )
+                        })
+                        .collect();
+                    (ranges, &mut layout_info.rects)
+                });
+
                 buffer.layout_runs().try_for_each(|run| {
                     if let Some(selection) = selection
                         && let Some((x0, w)) = run.highlight(selection.0, selection.1)
@@ -210,97 +619,117 @@ pub fn text_input_system(
                         let x1 = x0 + w;
                         let r = Rect::new(x0, y0, x1, y1);
                         selection_rects.push(r);
+                    } else if let Some((s0, s1)) = selection
+                        && run.glyphs.is_empty()
+                        && (s0.line..s1.line).contains(&run.line_i)
+                    {
+                        // Empty lines have no glyphs to highlight, add a zero width rect that is
+                        // widened to `TextInputStyle::selection_min_width` during extraction.
+                        let x = match justification {
+                            Justify::Center => 0.5 * width,
+                            Justify::Right => width,
+                            Justify::Left | Justify::Justified => 0.,
+                        };
+                        let y0 = run.line_top;
+                        let y1 = y0 + run.line_height;
+                        selection_rects.push(Rect::new(x, y0, x, y1));
                     }
 
-                    run.glyphs
-                        .iter()
-                        .map(move |layout_glyph| (layout_glyph, run.line_y, run.line_i))
-                        .try_for_each(|(layout_glyph, line_y, line_i)| {
-                            let mut temp_glyph;
-                            let span_index = layout_glyph.metadata;
-                            let font_id = text_font.font.id();
-                            let font_smoothing = text_font.font_smoothing;
-
-                            let layout_glyph = if font_smoothing == FontSmoothing::None {
-                                // If font smoothing is disabled, round the glyph positions and sizes,
-                                // effectively discarding all subpixel layout.
-                                temp_glyph = layout_glyph.clone();
-                                temp_glyph.x = temp_glyph.x.round();
-                                temp_glyph.y = temp_glyph.y.round();
-                                temp_glyph.w = temp_glyph.w.round();
-                                temp_glyph.x_offset = temp_glyph.x_offset.round();
-                                temp_glyph.y_offset = temp_glyph.y_offset.round();
-                                temp_glyph.line_height_opt =
-                                    temp_glyph.line_height_opt.map(f32::round);
-
-                                &temp_glyph
-                            } else {
-                                layout_glyph
-                            };
-
-                            let TextInputPipeline {
-                                font_system,
-                                swash_cache,
-                                ..
-                            } = &mut *text_input_pipeline;
-
-                            let physical_glyph = layout_glyph.physical((0., 0.), 1.);
-
-                            let font_atlases = font_atlas_set
-                                .entry(FontAtlasKey(
-                                    font_id,
-                                    physical_glyph.cache_key.font_size_bits,
-                                    font_smoothing,
-                                ))
-                                .or_default();
-
-                            let atlas_info =
-                                get_glyph_atlas_info(font_atlases, physical_glyph.cache_key)
-                                    .map(Ok)
-                                    .unwrap_or_else(|| {
-                                        add_glyph_to_atlas(
-                                            font_atlases,
-                                            &mut texture_atlases,
-                                            &mut textures,
-                                            font_system,
-                                            swash_cache,
-                                            layout_glyph,
-                                            font_smoothing,
-                                        )
-                                    })?;
-
-                            let texture_atlas =
-                                texture_atlases.get(atlas_info.texture_atlas).unwrap();
-                            let location = atlas_info.location;
-                            let glyph_rect = texture_atlas.textures[location.glyph_index];
-                            let left = location.offset.x as f32;
-                            let top = location.offset.y as f32;
-                            let glyph_size = UVec2::new(glyph_rect.width(), glyph_rect.height());
-
-                            // offset by half the size because the origin is center
-                            let x = glyph_size.x as f32 / 2.0 + left + physical_glyph.x as f32;
-                            let y = line_y.round() + physical_glyph.y as f32 - top
-                                + glyph_size.y as f32 / 2.0;
-
-                            let position = Vec2::new(x, y);
-
-                            let pos_glyph = TextInputGlyph {
-                                position,
-                                size: glyph_size.as_vec2(),
-                                atlas_info,
-                                span_index,
-                                byte_index: layout_glyph.start,
-                                byte_length: layout_glyph.end - layout_glyph.start,
-                                line_index: line_i,
-                            };
-                            layout_info.glyphs.push(pos_glyph);
-                            Ok(())
-                        })
+                    if let Some((ranges, rects)) = highlights.as_mut() {
+                        for &(c0, c1, color) in ranges.iter() {
+                            if let Some((x0, w)) = run.highlight(c0, c1) {
+                                let y0 = run.line_top;
+                                rects
+                                    .push((Rect::new(x0, y0, x0 + w, y0 + run.line_height), color));
+                            }
+                        }
+                    }
+
+                    if let Some((ranges, rects)) = underlines.as_mut() {
+                        for &(c0, c1) in ranges.iter() {
+                            if let Some((x0, w)) = run.highlight(c0, c1) {
+                                let y0 = run.line_y + underline_thickness;
+                                rects.push(Rect::new(x0, y0, x0 + w, y0 + underline_thickness));
+                            }
+                        }
+                    }
+
+                    run.glyphs.iter().try_for_each(|layout_glyph| {
+                        let mut glyph = layout_glyph_to_text_input_glyph(
+                            layout_glyph,
+                            run.line_y,
+                            run.line_i,
+                            &text_font,
+                            &mut text_input_pipeline,
+                            &mut font_atlas_set,
+                            &mut texture_atlases,
+                            &mut textures,
+                        )?;
+                        // Selected glyphs are found by their index into the text
+                        glyph.byte_index = to_text_index(run.line_i, layout_glyph.start);
+                        glyph.byte_length =
+                            to_text_index(run.line_i, layout_glyph.end) - glyph.byte_index;
+                        layout_info.glyphs.push(glyph);
+                        Ok(())
+                    })
                 })?;
 
+                layout_info.ellipsis = None;
+                if show_ellipsis
+                    && let Some(run) = buffer.layout_runs().next()
+                    && width < run.line_w
+                {
+                    let font_system = &mut text_input_pipeline.font_system;
+                    let mut ellipsis_buffer = Buffer::new(font_system, buffer.metrics());
+                    ellipsis_buffer.set_text(
+                        font_system,
+                        "\u{2026}",
+                        &buffer.lines[0].attrs_list().defaults(),
+                        cosmic_text::Shaping::Advanced,
+                        None,
+                    );
+                    ellipsis_buffer.shape_until_scroll(font_system, false);
+                    if let Some(mut ellipsis) = ellipsis_buffer
+                        .layout_runs()
+                        .next()
+                        .and_then(|ellipsis_run| ellipsis_run.glyphs.first().cloned())
+                    {
+                        // Remove the glyphs that don't fit in front of the ellipsis
+                        let max_x = width - ellipsis.w;
+                        let mut end = 0.;
+                        let mut clipped = vec![];
+                        for glyph in run.glyphs.iter() {
+                            if glyph.x + glyph.w <= max_x {
+                                end = glyph.x + glyph.w;
+                            } else {
+                                clipped.push(to_text_index(run.line_i, glyph.start));
+                            }
+                        }
+                        layout_info
+                            .glyphs
+                            .retain(|glyph| !clipped.contains(&glyph.byte_index));
+
+                        ellipsis.x = end;
+                        layout_info.ellipsis = Some(layout_glyph_to_text_input_glyph(
+                            &ellipsis,
+                            run.line_y,
+                            run.line_i,
+                            &text_font,
+                            &mut text_input_pipeline,
+                            &mut font_atlas_set,
+                            &mut texture_atlases,
+                            &mut textures,
+                        )?);
+                    }
+                }
+
                 layout_info.size = box_size;
                 Ok(())
-            });
+            };
+            let result = match mask {
+                Some(mask) => layout(&mask.buffer),
+                None => editor.with_buffer(layout),
+            };
 
             match result {
                 Err(TextError::NoSuchFont) => {
@@ -339,17 +768,20 @@ pub fn text_input_prompt_system(
         &mut TextInputBuffer,
         Ref<TextInputNode>,
         Ref<TextInputPrompt>,
+        Option<Ref<TextInputGutter>>,
     )>,
 ) {
-    for (node, text_font, line_height, text_input_layout_info, mut editor, input, prompt) in
+    for (node, text_font, line_height, text_input_layout_info, mut editor, input, prompt, gutter) in
         text_query.iter_mut()
     {
         let layout_info = text_input_layout_info.into_inner();
         if prompt.is_changed()
+            || gutter.as_ref().is_some_and(DetectChanges::is_changed)
             || input.is_changed()
             || editor.prompt_buffer.is_none()
             || layout_info.glyphs.is_empty()
             || text_font.is_changed() && prompt.font.is_none()
+            || line_height.is_changed()
             || node.is_changed()
         {
             layout_info.glyphs.clear();
@@ -389,8 +821,10 @@ pub fn text_input_prompt_system(
                 .get_or_insert(Buffer::new(font_system, metrics));
 
             let linebreak = LineBreak::WordBoundary;
+            let inset =
+                TextInputGutter::inset(gutter.as_deref(), node.inverse_scale_factor().recip());
             let bounds = TextBounds {
-                width: Some(node.size().x),
+                width: Some((node.size().x - inset).max(0.)),
                 height: Some(node.size().y),
             };
 
@@ -434,88 +868,20 @@ pub fn text_input_prompt_system(
 
             let box_size = buffer_dimensions(buffer);
             let result = buffer.layout_runs().try_for_each(|run| {
-                run.glyphs
-                    .iter()
-                    .map(move |layout_glyph| (layout_glyph, run.line_y, run.line_i))
-                    .try_for_each(|(layout_glyph, line_y, line_i)| {
-                        let mut temp_glyph;
-                        let span_index = layout_glyph.metadata;
-                        let font_id = text_font.font.id();
-                        let font_smoothing = text_font.font_smoothing;
-
-                        let layout_glyph = if font_smoothing == FontSmoothing::None {
-                            // If font smoothing is disabled, round the glyph positions and sizes,
-                            // effectively discarding all subpixel layout.
-                            temp_glyph = layout_glyph.clone();
-                            temp_glyph.x = temp_glyph.x.round();
-                            temp_glyph.y = temp_glyph.y.round();
-                            temp_glyph.w = temp_glyph.w.round();
-                            temp_glyph.x_offset = temp_glyph.x_offset.round();
-                            temp_glyph.y_offset = temp_glyph.y_offset.round();
-                            temp_glyph.line_height_opt = temp_glyph.line_height_opt.map(f32::round);
-
-                            &temp_glyph
-                        } else {
-                            layout_glyph
-                        };
-
-                        let TextInputPipeline {
-                            font_system,
-                            swash_cache,
-                            ..
-                        } = &mut *text_input_pipeline;
-
-                        let physical_glyph = layout_glyph.physical((0., 0.), 1.);
-
-                        let font_atlases = font_atlas_set
-                            .entry(FontAtlasKey(
-                                font_id,
-                                physical_glyph.cache_key.font_size_bits,
-                                font_smoothing,
-                            ))
-                            .or_default();
-
-                        let atlas_info =
-                            get_glyph_atlas_info(font_atlases, physical_glyph.cache_key)
-                                .map(Ok)
-                                .unwrap_or_else(|| {
-                                    add_glyph_to_atlas(
-                                        font_atlases,
-                                        &mut texture_atlases,
-                                        &mut textures,
-                                        font_system,
-                                        swash_cache,
-                                        layout_glyph,
-                                        font_smoothing,
-                                    )
-                                })?;
-
-                        let texture_atlas = texture_atlases.get(atlas_info.texture_atlas).unwrap();
-                        let location = atlas_info.location;
-                        let glyph_rect = texture_atlas.textures[location.glyph_index];
-                        let left = location.offset.x as f32;
-                        let top = location.offset.y as f32;
-                        let glyph_size = UVec2::new(glyph_rect.width(), glyph_rect.height());
-
-                        // offset by half the size because the origin is center
-                        let x = glyph_size.x as f32 / 2.0 + left + physical_glyph.x as f32;
-                        let y = line_y.round() + physical_glyph.y as f32 - top
-                            + glyph_size.y as f32 / 2.0;
-
-                        let position = Vec2::new(x, y);
-
-                        let pos_glyph = TextInputGlyph {
-                            position,
-                            size: glyph_size.as_vec2(),
-                            atlas_info,
-                            span_index,
-                            byte_index: layout_glyph.start,
-                            byte_length: layout_glyph.end - layout_glyph.start,
-                            line_index: line_i,
-                        };
-                        layout_info.glyphs.push(pos_glyph);
-                        Ok(())
-                    })
+                run.glyphs.iter().try_for_each(|layout_glyph| {
+                    let glyph = layout_glyph_to_text_input_glyph(
+                        layout_glyph,
+                        run.line_y,
+                        run.line_i,
+                        &text_font,
+                        &mut text_input_pipeline,
+                        &mut font_atlas_set,
+                        &mut texture_atlases,
+                        &mut textures,
+                    )?;
+                    layout_info.glyphs.push(glyph);
+                    Ok(())
+                })
             });
 
             layout_info.size = box_size;
@@ -543,6 +909,137 @@ pub fn text_input_prompt_system(
     }
 }
 
+pub fn text_input_gutter_system(
+    mut textures: ResMut<Assets<Image>>,
+    fonts: Res<Assets<Font>>,
+    mut texture_atlases: ResMut<Assets<TextureAtlasLayout>>,
+    mut text_input_pipeline: ResMut<TextInputPipeline>,
+    mut font_atlas_set: ResMut<FontAtlasSet>,
+    mut text_query: Query<(
+        Ref<ComputedNode>,
+        Ref<TextFont>,
+        &mut TextInputGutterLayoutInfo,
+        &mut TextInputBuffer,
+        Ref<TextInputGutter>,
+    )>,
+) {
+    for (node, text_font, mut layout_info, mut editor, gutter) in text_query.iter_mut() {
+        // Number and baseline of each visible line, skipping the continuation rows of wrapped lines
+        let lines: Vec<(usize, f32)> = editor.editor.with_buffer(|buffer| {
+            buffer
+                .layout_runs()
+                .filter(|run| {
+                    run.glyphs.is_empty() || run.glyphs.iter().any(|glyph| glyph.start == 0)
+                })
+                .map(|run| (run.line_i + 1, run.line_y))
+                .collect()
+        });
+
+        if !(gutter.is_changed()
+            || text_font.is_changed()
+            || node.is_changed()
+            || editor.gutter_buffer.is_none()
+            || layout_info.lines != lines)
+        {
+            continue;
+        }
+
+        layout_info.glyphs.clear();
+        layout_info.lines.clear();
+
+        let metrics = editor.metrics();
+        let TextInputPipeline {
+            font_system,
+            handle_to_font_id_map: map_handle_to_font_id,
+            ..
+        } = &mut *text_input_pipeline;
+
+        let Ok(face_info) =
+            load_font_to_fontdb(&text_font, font_system, map_handle_to_font_id, &fonts)
+        else {
+            editor.gutter_buffer = None;
+            continue;
+        };
+
+        let buffer = editor
+            .gutter_buffer
+            .get_or_insert(Buffer::new(font_system, metrics));
+
+        // Leave a small gap between the numbers and the text
+        let width = (TextInputGutter::inset(Some(&gutter), node.inverse_scale_factor().recip())
+            - 0.5 * metrics.font_size)
+            .max(0.);
+
+        buffer.set_metrics_and_size(font_system, metrics, Some(width), None);
+        buffer.set_wrap(font_system, cosmic_text::Wrap::None);
+
+        let attrs = cosmic_text::Attrs::new()
+            .metadata(0)
+            .family(cosmic_text::Family::Name(&face_info.family_name))
+            .stretch(face_info.stretch)
+            .style(face_info.style)
+            .weight(face_info.weight)
+            .metrics(metrics);
+
+        let text = lines
+            .iter()
+            .map(|(number, _)| number.to_string())
+            .collect::<Vec<_>>()
+            .join("\n");
+
+        buffer.set_text(
+            font_system,
+            &text,
+            &attrs,
+            cosmic_text::Shaping::Advanced,
+            Some(cosmic_text::Align::Right),
+        );
+
+        buffer.shape_until_scroll(font_system, false);
+
+        let TextInputGutterLayoutInfo { glyphs, .. } = &mut *layout_info;
+        let result = buffer.layout_runs().try_for_each(|run| {
+            // Align each number with the baseline of its line in the input's buffer
+            let Some(&(_, line_y)) = lines.get(run.line_i) else {
+                return Ok(());
+            };
+            run.glyphs.iter().try_for_each(|layout_glyph| {
+                let glyph = layout_glyph_to_text_input_glyph(
+                    layout_glyph,
+                    line_y,
+                    run.line_i,
+                    &text_font,
+                    &mut text_input_pipeline,
+                    &mut font_atlas_set,
+                    &mut texture_atlases,
+                    &mut textures,
+                )?;
+                glyphs.push(glyph);
+                Ok(())
+            })
+        });
+
+        match result {
+            Err(TextError::NoSuchFont) => {
+                editor.gutter_buffer = None;
+                // There was an error processing the text layout, try again next frame
+            }
+            Err(
+                e @ (TextError::FailedToAddGlyph(_)
+                | TextError::FailedToGetGlyphImage(_)
+                | TextError::MissingAtlasLayout
+                | TextError::MissingAtlasTexture
+                | TextError::InconsistentAtlasState),
+            ) => {
+                panic!("Fatal error when processing text: {e}.");
+            }
+            Ok(()) => {
+                layout_info.lines = lines;
+            }
+        }
+    }
+}
+
 pub fn remove_dropped_font_atlas_sets_from_text_input_pipeline(
     mut font_atlas_sets: ResMut<FontAtlasSet>,
     mut font_events: MessageReader<AssetEvent<Font>>,
@@ -553,3 +1050,486 @@ pub fn remove_dropped_font_atlas_sets_from_text_input_pipeline(
         }
     }
 }
+
+/// Lays out text inputs again when a font they use finishes loading or is modified, so inputs spawned
+/// before their font loaded, and inputs using a hot reloaded font, are shaped with the right font.
+pub fn relayout_text_inputs_on_font_events(
+    mut text_input_pipeline: ResMut<TextInputPipeline>,
+    mut font_atlas_sets: ResMut<FontAtlasSet>,
+    mut font_events: MessageReader<AssetEvent<Font>>,
+    mut query: Query<(&TextFont, Option<&TextInputPrompt>, &mut TextInputBuffer)>,
+) {
+    for event in font_events.read() {
+        let (AssetEvent::LoadedWithDependencies { id } | AssetEvent::Modified { id }) = *event
+        else {
+            continue;
+        };
+
+        if matches!(event, AssetEvent::Modified { .. }) {
+            // Forget the old version of the font, it's loaded again on the next layout
+            let TextInputPipeline {
+                handle_to_font_id_map,
+                font_system,
+                shaping_cache,
+                ..
+            } = &mut *text_input_pipeline;
+            if let Some((face_id, _)) = handle_to_font_id_map.remove(&id) {
+                font_system.db_mut().remove_face(face_id);
+            }
+            shaping_cache.remove_font(id);
+            font_atlas_sets.retain(|key, _| key.0 != id);
+        }
+
+        for (text_font, prompt, mut buffer) in query.iter_mut() {
+            if text_font.font.id() == id {
+                buffer.needs_update = true;
+                buffer.gutter_buffer = None;
+            }
+            if prompt
+                .is_some_and(|prompt| prompt.font.as_ref().unwrap_or(text_font).font.id() == id)
+            {
+                buffer.prompt_buffer = None;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use bevy::ecs::change_detection::Mut;
+    use bevy::ecs::schedule::Schedule;
+    use bevy::ecs::world::World;
+
+    /// A world with the resources `text_input_system` needs and a text input with a monospace font
+    pub(crate) fn layout_world(input: TextInputNode, text: &str) -> (World, Entity) {
+        let mut world = World::new();
+        world.init_resource::<InputFocus>();
+        world.init_resource::<TextInputPipeline>();
+        world.init_resource::<Assets<Font>>();
+        world.init_resource::<Assets<Image>>();
+        world.init_resource::<Assets<TextureAtlasLayout>>();
+        world.init_resource::<FontAtlasSet>();
+        let font = world.resource_mut::<Assets<Font>>().add(
+            Font::try_from_bytes(include_bytes!("../assets/fonts/FiraMono-Medium.ttf").to_vec())
+                .unwrap(),
+        );
+        let entity = world
+            .spawn((
+                input,
+                TextFont {
+                    font,
+                    font_size: 16.,
+                    ..Default::default()
+                },
+                ComputedNode {
+                    size: Vec2::new(100., 30.),
+                    inverse_scale_factor: 1.,
+                    ..Default::default()
+                },
+            ))
+            .id();
+        world
+            .get_mut::<TextInputBuffer>(entity)
+            .unwrap()
+            .set_text(text);
+        (world, entity)
+    }
+
+    #[test]
+    fn ellipsis_only_while_unfocused() {
+        let text = "the quick brown fox jumps over the lazy dog";
+        let (mut world, entity) = layout_world(
+            TextInputNode {
+                mode: TextInputMode::SingleLine,
+                overflow_indicator: Overflow::Ellipsis,
+                ..Default::default()
+            },
+            text,
+        );
+        let mut schedule = Schedule::default();
+        schedule.add_systems(text_input_system);
+
+        // Unfocused, the overflowing text is cut off before an ellipsis at the right edge
+        schedule.run(&mut world);
+        let info = world.get::<TextInputLayoutInfo>(entity).unwrap();
+        let ellipsis = info
+            .ellipsis
+            .as_ref()
+            .expect("overflowing text has an ellipsis");
+        assert!(info.glyphs.len() < text.len());
+        assert!(
+            info.glyphs
+                .iter()
+                .all(|glyph| glyph.position.x < ellipsis.position.x)
+        );
+        assert!(ellipsis.position.x + ellipsis.size.x * 0.5 <= 100.5);
+
+        // Focused, all the text is laid out and scrolled to the cursor at its end
+        world.resource_mut::<InputFocus>().set(entity);
+        schedule.run(&mut world);
+        let info = world.get::<TextInputLayoutInfo>(entity).unwrap();
+        assert!(info.ellipsis.is_none());
+        assert_eq!(info.glyphs.len(), text.len());
+        let scroll = world
+            .get::<TextInputBuffer>(entity)
+            .unwrap()
+            .editor
+            .with_buffer(|buffer| buffer.scroll());
+        assert!(0. < scroll.horizontal);
+    }
+
+    #[test]
+    fn shaping_cache_reuses_resized_layouts() {
+        let (mut world, entity) = layout_world(
+            TextInputNode::default(),
+            "the quick brown fox jumps over the lazy dog",
+        );
+        let mut schedule = Schedule::default();
+        schedule.add_systems(text_input_system);
+        let mut resize = |world: &mut World, width: f32| {
+            world.get_mut::<ComputedNode>(entity).unwrap().size.x = width;
+            schedule.run(world);
+            let cache = &world.resource::<TextInputPipeline>().shaping_cache;
+            (cache.hits(), cache.misses(), cache.len())
+        };
+
+        assert_eq!(resize(&mut world, 100.), (0, 1, 1));
+        assert_eq!(resize(&mut world, 150.), (0, 2, 2));
+        assert_eq!(resize(&mut world, 100.), (1, 2, 2));
+
+        // Edited text is laid out without the cache
+        world
+            .get_mut::<TextInputBuffer>(entity)
+            .unwrap()
+            .set_text("hello");
+        assert_eq!(resize(&mut world, 150.), (1, 2, 2));
+        assert_eq!(resize(&mut world, 100.), (1, 3, 3));
+    }
+
+    #[test]
+    fn shaping_cache_ignores_hash_collisions() {
+        let (mut world, entity) = layout_world(TextInputNode::default(), "abc");
+        let mut schedule = Schedule::default();
+        schedule.add_systems(text_input_system);
+        let mut resize = |world: &mut World, width: f32| {
+            world.get_mut::<ComputedNode>(entity).unwrap().size.x = width;
+            schedule.run(world);
+        };
+
+        resize(&mut world, 100.);
+        world
+            .get_mut::<TextInputBuffer>(entity)
+            .unwrap()
+            .set_text("xyz");
+        resize(&mut world, 150.);
+        resize(&mut world, 100.);
+
+        // Make the "xyz" entry look like a colliding key holding another input's lines
+        let entries = &mut world
+            .resource_mut::<TextInputPipeline>()
+            .into_inner()
+            .shaping_cache
+            .entries;
+        let cached = |entries: &VecDeque<(ShapingKey, Vec<BufferLine>)>, text: &str| {
+            entries
+                .iter()
+                .position(|(_, lines)| lines[0].text() == text)
+                .unwrap()
+        };
+        let abc = entries[cached(entries, "abc")].1.clone();
+        let xyz = cached(entries, "xyz");
+        entries[xyz].1 = abc;
+
+        resize(&mut world, 150.);
+        resize(&mut world, 100.);
+        assert_eq!(
+            world.get::<TextInputBuffer>(entity).unwrap().get_text(),
+            "xyz"
+        );
+    }
+
+    #[test]
+    fn selection_covers_blank_lines() {
+        let (mut world, entity) = layout_world(TextInputNode::default(), "one\n\nthree");
+        world.get_mut::<ComputedNode>(entity).unwrap().size.y = 100.;
+        let mut buffer = world.get_mut::<TextInputBuffer>(entity).unwrap();
+        buffer
+            .editor
+            .set_selection(cosmic_text::Selection::Normal(Cursor::new(0, 1)));
+        buffer.editor.set_cursor(Cursor::new(2, 2));
+        let mut schedule = Schedule::default();
+        schedule.add_systems(text_input_system);
+        schedule.run(&mut world);
+
+        let buffer = world.get::<TextInputBuffer>(entity).unwrap();
+        // The blank line gets an empty rect that's widened to `selection_min_width` spaces
+        let rects = &buffer.selection_rects;
+        assert_eq!(rects.len(), 3);
+        assert_eq!(rects[1].width(), 0.);
+        for pair in rects.windows(2) {
+            assert_eq!(pair[0].max.y, pair[1].min.y);
+        }
+        // Fira Mono's advance is 0.6em
+        assert!((buffer.space_advance - 0.6 * 16.).abs() < 0.01);
+    }
+
+    #[test]
+    fn metrics_report_line_height_override() {
+        let (mut world, entity) = layout_world(TextInputNode::default(), "abc");
+        let mut schedule = Schedule::default();
+        schedule.add_systems(text_input_system);
+
+        for (line_height, expected) in [
+            (LineHeight::Px(24.), 24.),
+            (LineHeight::RelativeToFont(2.), 32.),
+        ] {
+            world.entity_mut(entity).insert(line_height);
+            schedule.run(&mut world);
+            let buffer = world.get::<TextInputBuffer>(entity).unwrap();
+            assert_eq!(buffer.line_height(), expected);
+            assert_eq!(buffer.font_size(), 16.);
+        }
+    }
+
+    #[test]
+    fn max_width_chars_wraps_at_columns() {
+        let (mut world, entity) = layout_world(
+            TextInputNode {
+                mode: TextInputMode::MultiLine {
+                    wrap: cosmic_text::Wrap::Glyph,
+                },
+                max_width_chars: Some(80),
+                ..Default::default()
+            },
+            &"0".repeat(200),
+        );
+        world.get_mut::<ComputedNode>(entity).unwrap().size = Vec2::new(2000., 400.);
+        let mut schedule = Schedule::default();
+        schedule.add_systems(text_input_system);
+        schedule.run(&mut world);
+
+        let buffer = world.get::<TextInputBuffer>(entity).unwrap();
+        let columns: Vec<usize> = buffer
+            .editor
+            .with_buffer(|buffer| buffer.layout_runs().map(|run| run.glyphs.len()).collect());
+        assert_eq!(columns.len(), 3);
+        assert!((79..=80).contains(&columns[0]), "{columns:?}");
+    }
+
+    #[test]
+    fn click_in_right_justified_input() {
+        let (mut world, entity) = layout_world(
+            TextInputNode {
+                mode: TextInputMode::SingleLine,
+                justification: Justify::Right,
+                ..Default::default()
+            },
+            "abc",
+        );
+        let mut schedule = Schedule::default();
+        schedule.add_systems(text_input_system);
+        schedule.run(&mut world);
+
+        // The 9.6 pixel wide glyphs end at the right edge of the 100 pixel wide node
+        let glyphs = &world.get::<TextInputLayoutInfo>(entity).unwrap().glyphs;
+        assert!((glyphs[0].position.x - glyphs[0].size.x / 2. - 71.2).abs() < 1.);
+
+        world.resource_scope(|world, mut pipeline: Mut<TextInputPipeline>| {
+            let mut buffer = world.get_mut::<TextInputBuffer>(entity).unwrap();
+            // The right half of "b", then the left half of "a"
+            for (x, index) in [(88, 2), (74, 0)] {
+                buffer.apply_edit(
+                    crate::actions::TextInputEdit::Click { x, y: 5 },
+                    &mut pipeline.font_system,
+                    None,
+                    None,
+                );
+                assert_eq!(buffer.editor.cursor().index, index, "x = {x}");
+            }
+        });
+    }
+
+    #[test]
+    fn spellcheck_underlines_ranges() {
+        let (mut world, entity) = layout_world(TextInputNode::default(), "hello wrold\nteh end");
+        world.get_mut::<ComputedNode>(entity).unwrap().size = Vec2::new(300., 100.);
+        world.entity_mut(entity).insert(TextInputSpellcheck {
+            misspelled_ranges: vec![6..11, 12..15],
+            ..Default::default()
+        });
+        let mut schedule = Schedule::default();
+        schedule.add_systems(text_input_system);
+        schedule.run(&mut world);
+
+        // Underlines span exactly the 9.6 pixel wide glyphs of "wrold" and "teh"
+        let rects = &world
+            .get::<TextInputSpellcheckLayoutInfo>(entity)
+            .unwrap()
+            .rects;
+        assert_eq!(rects.len(), 2);
+        for (rect, (x0, x1)) in rects.iter().zip([(57.6, 105.6), (0., 28.8)]) {
+            assert!((rect.min.x - x0).abs() < 0.01, "{rect:?}");
+            assert!((rect.max.x - x1).abs() < 0.01, "{rect:?}");
+        }
+        assert!(rects[0].max.y < rects[1].min.y);
+    }
+
+    #[test]
+    fn unbroken_text_wraps_without_scrolling() {
+        let (mut world, entity) = layout_world(
+            TextInputNode {
+                mode: TextInputMode::MultiLine {
+                    wrap: cosmic_text::Wrap::WordOrGlyph,
+                },
+                ..Default::default()
+            },
+            &"x".repeat(200),
+        );
+        world.get_mut::<ComputedNode>(entity).unwrap().size = Vec2::new(100., 400.);
+        world
+            .get_mut::<TextInputBuffer>(entity)
+            .unwrap()
+            .editor
+            .set_cursor(Cursor::new(0, 200));
+        let mut schedule = Schedule::default();
+        schedule.add_systems(text_input_system);
+        schedule.run(&mut world);
+
+        let buffer = world.get::<TextInputBuffer>(entity).unwrap();
+        assert_eq!(buffer.horizontal_scroll(), 0.);
+        let widths: Vec<f32> = buffer
+            .editor
+            .with_buffer(|buffer| buffer.layout_runs().map(|run| run.line_w).collect());
+        assert!(1 < widths.len());
+        assert!(widths.iter().all(|&width| width <= 100.), "{widths:?}");
+    }
+
+    #[test]
+    fn selection_bounds_rect_covers_selected_lines() {
+        let (mut world, entity) = layout_world(TextInputNode::default(), "ab\ncdefg\nh");
+        world.get_mut::<ComputedNode>(entity).unwrap().size.y = 100.;
+        let mut buffer = world.get_mut::<TextInputBuffer>(entity).unwrap();
+        assert_eq!(buffer.selection_bounds_rect(), None);
+        buffer
+            .editor
+            .set_selection(cosmic_text::Selection::Normal(Cursor::new(0, 1)));
+        buffer.editor.set_cursor(Cursor::new(2, 1));
+        let mut schedule = Schedule::default();
+        schedule.add_systems(text_input_system);
+        schedule.run(&mut world);
+
+        // From the left edge of the longest line to its end, from the top of the first line to the
+        // bottom of the third
+        let buffer = world.get::<TextInputBuffer>(entity).unwrap();
+        let rect = buffer.selection_bounds_rect().unwrap();
+        let line_height = buffer.line_height();
+        assert_eq!(rect.min, Vec2::ZERO);
+        assert!((rect.max.x - 5. * 9.6).abs() < 0.01, "{rect:?}");
+        assert!((rect.max.y - 3. * line_height).abs() < 0.01, "{rect:?}");
+    }
+
+    #[test]
+    fn appended_lines_capped_and_scrolled_to_tail() {
+        let (mut world, entity) = layout_world(TextInputNode::default(), "");
+        let mut schedule = Schedule::default();
+        schedule.add_systems(text_input_system);
+        schedule.run(&mut world);
+
+        let mut buffer = world.get_mut::<TextInputBuffer>(entity).unwrap();
+        buffer.set_max_lines(Some(1000));
+        for i in 0..10_000 {
+            buffer.append_line(&format!("line {i}"));
+        }
+        schedule.run(&mut world);
+
+        let buffer = world.get::<TextInputBuffer>(entity).unwrap();
+        buffer.editor.with_buffer(|buffer| {
+            assert_eq!(buffer.lines.len(), 1000);
+            assert_eq!(buffer.lines[0].text(), "line 9000");
+            assert_eq!(buffer.lines[999].text(), "line 9999");
+            assert!(buffer.layout_runs().any(|run| run.line_i == 999));
+        });
+    }
+
+    #[test]
+    fn input_laid_out_once_its_font_loads() {
+        use bevy::ecs::message::Messages;
+        use bevy::ecs::schedule::IntoScheduleConfigs;
+
+        let (mut world, entity) = layout_world(TextInputNode::default(), "abc");
+        world.init_resource::<Messages<AssetEvent<Font>>>();
+        let font = world.resource::<Assets<Font>>().reserve_handle();
+        world.get_mut::<TextFont>(entity).unwrap().font = font.clone();
+        let mut schedule = Schedule::default();
+        schedule.add_systems((relayout_text_inputs_on_font_events, text_input_system).chain());
+
+        schedule.run(&mut world);
+        let info = world.get::<TextInputLayoutInfo>(entity).unwrap();
+        assert!(info.glyphs.is_empty());
+
+        world
+            .resource_mut::<Assets<Font>>()
+            .insert(
+                font.id(),
+                Font::try_from_bytes(
+                    include_bytes!("../assets/fonts/FiraMono-Medium.ttf").to_vec(),
+                )
+                .unwrap(),
+            )
+            .unwrap();
+        world.write_message(AssetEvent::LoadedWithDependencies { id: font.id() });
+        schedule.run(&mut world);
+        let info = world.get::<TextInputLayoutInfo>(entity).unwrap();
+        assert_eq!(info.glyphs.len(), 3);
+    }
+
+    #[test]
+    fn caret_pixels_at_char_offsets() {
+        let (mut world, entity) = layout_world(TextInputNode::default(), "abcdef");
+        let mut schedule = Schedule::default();
+        schedule.add_systems(text_input_system);
+        schedule.run(&mut world);
+
+        let buffer = world.get::<TextInputBuffer>(entity).unwrap();
+        assert_eq!(buffer.caret_pixel_for_offset(0), Some(Vec2::ZERO));
+        // FiraMono's glyphs are 9.6 wide at 16px
+        for (offset, x) in [(3, 28.8), (100, 57.6)] {
+            let position = buffer.caret_pixel_for_offset(offset).unwrap();
+            assert!((position - Vec2::new(x, 0.)).length() < 0.01, "{position}");
+        }
+    }
+
+    #[test]
+    fn caret_rect_follows_focus() {
+        use crate::CaretRect;
+        use crate::edit::caret_rect_system;
+        use bevy::ecs::schedule::IntoScheduleConfigs;
+        use bevy::ui::UiGlobalTransform;
+
+        let (mut world, entity) = layout_world(TextInputNode::default(), "abc");
+        // The node's top-left corner at the window's
+        world.entity_mut(entity).insert((
+            CaretRect::default(),
+            UiGlobalTransform::from_translation(Vec2::new(50., 15.)),
+        ));
+        let mut schedule = Schedule::default();
+        schedule.add_systems((text_input_system, caret_rect_system).chain());
+
+        schedule.run(&mut world);
+        assert_eq!(world.get::<CaretRect>(entity).unwrap().0, None);
+
+        // The cursor after "abc", three advances of the monospace font into the node
+        world.resource_mut::<InputFocus>().set(entity);
+        schedule.run(&mut world);
+        let rect = world.get::<CaretRect>(entity).unwrap().0.unwrap();
+        assert!((rect.min.x - 3. * 9.6).abs() < 1., "{rect:?}");
+        assert!(0. < rect.width() && 0. < rect.height());
+        let node_rect = Rect::new(0., 0., 100., 30.);
+        assert!(node_rect.contains(rect.min) && node_rect.contains(rect.max));
+
+        world.resource_mut::<InputFocus>().clear();
+        schedule.run(&mut world);
+        assert_eq!(world.get::<CaretRect>(entity).unwrap().0, None);
+    }
+}